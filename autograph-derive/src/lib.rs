@@ -158,7 +158,15 @@ fn process_vertex_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenS
 
 #[proc_macro_derive(
     ShaderInterface,
-    attributes(uniform_constant, texture_binding, vertex_buffer, index_buffer, uniform_buffer)
+    attributes(
+        uniform_constant,
+        texture_binding,
+        vertex_buffer,
+        index_buffer,
+        uniform_buffer,
+        storage_buffer,
+        image
+    )
 )]
 pub fn shader_interface_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).expect("Couldn't parse item");
@@ -219,6 +227,30 @@ struct UniformBuffer {
     index: Option<u32>,
 }
 
+#[derive(FromField)]
+#[darling(attributes(storage_buffer))]
+struct StorageBuffer {
+    ident: Option<syn::Ident>,
+    ty: syn::Type,
+    vis: syn::Visibility,
+    #[darling(default)]
+    rename: Option<String>,
+    #[darling(default)]
+    index: Option<u32>,
+}
+
+#[derive(FromField)]
+#[darling(attributes(image))]
+struct Image {
+    ident: Option<syn::Ident>,
+    ty: syn::Type,
+    vis: syn::Visibility,
+    #[darling(default)]
+    rename: Option<String>,
+    #[darling(default)]
+    index: Option<u32>,
+}
+
 #[derive(FromField)]
 #[darling(attributes(index_buffer))]
 struct IndexBuffer {
@@ -261,6 +293,8 @@ fn process_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenStream {
     let mut vertex_buffers = Vec::new();
     let mut render_targets = Vec::new();
     let mut uniform_buffers = Vec::new();
+    let mut storage_buffers = Vec::new();
+    let mut images = Vec::new();
     let mut index_buffer = None;
 
     match *fields {
@@ -311,6 +345,22 @@ fn process_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenStream {
                             uniform_buffers.push(ub);
                             seen_interface_attr = true;
                         }
+                        "storage_buffer" => {
+                            if seen_interface_attr {
+                                error_multiple_interface_attrs();
+                            }
+                            let sb = <StorageBuffer as FromField>::from_field(f).unwrap();
+                            storage_buffers.push(sb);
+                            seen_interface_attr = true;
+                        }
+                        "image" => {
+                            if seen_interface_attr {
+                                error_multiple_interface_attrs();
+                            }
+                            let img = <Image as FromField>::from_field(f).unwrap();
+                            images.push(img);
+                            seen_interface_attr = true;
+                        }
                         "index_buffer" => {
                             if seen_interface_attr {
                                 error_multiple_interface_attrs();
@@ -454,6 +504,77 @@ fn process_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenStream {
 
     let num_uniform_buffer_items = uniform_buffer_items.len();
 
+    //
+    // shader storage buffers
+    //
+    let mut storage_buffer_items = Vec::new();
+    let mut storage_buffer_bind_statements = Vec::new();
+    for sb in storage_buffers.iter() {
+        let orig_name = sb.ident.clone().unwrap();
+        let name = sb
+            .rename
+            .as_ref()
+            .map_or(sb.ident.clone().unwrap(), |s| syn::Ident::new(s.as_str(), Span::call_site()));
+        let index_tokens = make_option_tokens(&sb.index);
+        let ty = &sb.ty;
+
+        storage_buffer_items.push(quote! {
+            ::autograph::gfx::shader_interface::UniformBufferDesc {
+                name: Some(stringify!(#name).into()),
+                index: #index_tokens,
+                tydesc: <#ty as ::autograph::gfx::BufferInterface>::get_layout()
+            }
+        });
+        storage_buffer_bind_statements.push(quote! {
+            {
+                let slice_any = interface.#orig_name.to_slice_any();
+                state_cache.set_shader_storage_buffer((#index_tokens).unwrap(), &slice_any);
+                frame.ref_buffer(slice_any.owner);
+            }
+        });
+    }
+
+    let num_storage_buffer_items = storage_buffer_items.len();
+
+    //
+    // images
+    //
+    let mut image_items = Vec::new();
+    let mut image_bind_statements = Vec::new();
+    for img in images.iter() {
+        let orig_name = img.ident.clone().unwrap();
+        let name = img
+            .rename
+            .as_ref()
+            .map_or(img.ident.clone().unwrap(), |s| syn::Ident::new(s.as_str(), Span::call_site()));
+        let index_tokens = make_option_tokens(&img.index);
+        let ty = &img.ty;
+
+        image_items.push(quote! {
+            ::autograph::gfx::shader_interface::TextureBindingDesc {
+                name: Some(stringify!(#name).into()),
+                index: #index_tokens,
+                data_type: <<#ty as ImageInterface>::TextureType as TextureInterface>::get_data_type(),
+                dimensions: <<#ty as ImageInterface>::TextureType as TextureInterface>::get_dimensions()
+            }
+        });
+        image_bind_statements.push(quote! {
+            {
+                let image = &interface.#orig_name;
+                let tex = image.to_texture_any();
+                state_cache.set_image(
+                    (#index_tokens).unwrap(),
+                    &tex,
+                    image.get_mip_level(),
+                    image.get_layer(),
+                    image.get_access());
+                frame.ref_texture(tex);
+            }
+        });
+    }
+
+    let num_image_items = image_items.len();
+
     //
     // render targets
     //
@@ -508,6 +629,8 @@ fn process_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenStream {
                 static ref TEXTURE_BINDINGS: [TextureBindingDesc;#num_texture_binding_items] = [#(#texture_binding_items),*];
                 static ref VERTEX_BUFFERS: [VertexBufferDesc;#num_vertex_buffer_items] = [#(#vertex_buffer_items),*];
                 static ref UNIFORM_BUFFERS: [UniformBufferDesc;#num_uniform_buffer_items] = [#(#uniform_buffer_items),*];
+                static ref STORAGE_BUFFERS: [UniformBufferDesc;#num_storage_buffer_items] = [#(#storage_buffer_items),*];
+                static ref IMAGES: [TextureBindingDesc;#num_image_items] = [#(#image_items),*];
                 static ref RENDER_TARGETS: [RenderTargetDesc;#num_render_target_items] = [#(#render_target_items),*];
                 static ref INDEX_BUFFER: Option<IndexBufferDesc> = #index_buffer_item;
             }
@@ -531,6 +654,12 @@ fn process_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenStream {
                 fn get_uniform_buffers(&self) -> &'static [UniformBufferDesc] {
                     &*UNIFORM_BUFFERS
                 }
+                fn get_storage_buffers(&self) -> &'static [UniformBufferDesc] {
+                    &*STORAGE_BUFFERS
+                }
+                fn get_images(&self) -> &'static [TextureBindingDesc] {
+                    &*IMAGES
+                }
                 //fn get_framebuffer(&self) ->
             }
 
@@ -538,8 +667,11 @@ fn process_struct(ast: &syn::DeriveInput, fields: &syn::Fields) -> TokenStream {
                 unsafe fn bind_unchecked(&self, interface: &#struct_name, frame: &::autograph::gfx::Frame, state_cache: &mut ::autograph::gfx::StateCache) {
                     use ::autograph::gfx::ToBufferSliceAny;
                     use ::autograph::gfx::SampledTextureInterface;
+                    use ::autograph::gfx::ImageInterface;
                     unsafe {
                         #(#uniform_buffer_bind_statements)*
+                        #(#storage_buffer_bind_statements)*
+                        #(#image_bind_statements)*
                     }
                 }
             }
@@ -39,9 +39,10 @@ fn load_pipeline(gctx: &gfx::Context, path: &Path) -> Result<gfx::GraphicsPipeli
 
 impl Renderer {
     pub fn new(imgui: &mut imgui::ImGui, gctx: &gfx::Context, cache: &Cache) -> Renderer {
+        let pipeline_gctx = gctx.clone();
         let pipeline = cache
-            .add_and_watch(IMGUI_SHADER_PATH.to_owned(), |path, reload_reason| {
-                load_pipeline(gctx, Path::new(path)).ok()
+            .add_and_watch(IMGUI_SHADER_PATH.to_owned(), move |path, reload_reason| {
+                load_pipeline(&pipeline_gctx, Path::new(path)).ok()
             })
             .unwrap();
 
@@ -43,9 +43,10 @@ impl Renderer {
         gctx: &gfx::Context,
         cache: &Arc<Cache>,
     ) -> Renderer {
+        let pipeline_gctx = gctx.clone();
         let pipeline = cache
-            .add_and_watch(IMGUI_SHADER_PATH.to_owned(), |path, reload_reason| {
-                load_pipeline(gctx, Path::new(path)).ok()
+            .add_and_watch(IMGUI_SHADER_PATH.to_owned(), move |path, reload_reason| {
+                load_pipeline(&pipeline_gctx, Path::new(path)).ok()
             })
             .unwrap();
 
@@ -318,8 +318,8 @@ fn main() {
             let mut fg = FrameGraph::new();
 
             // new node
-            let ectx = fg.finalize(main_loop.context(), &mut fg_allocator).unwrap();
-            ectx.execute(frame);
+            let compiled = fg.finalize(main_loop.context(), &mut fg_allocator).unwrap();
+            compiled.execute::<()>(frame, &fg_allocator, &());
 
             // texture test
             if let Ok(ref tex) = test_tex {
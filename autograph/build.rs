@@ -19,5 +19,16 @@ fn main() {
     ).write_bindings(GlobalGenerator, &mut file)
         .unwrap();
 
+    // EGL, for headless contexts (loaded at runtime, see gfx::headless)
+    let mut file = File::create(&Path::new(&dest).join("egl_bindings.rs")).unwrap();
+    Registry::new(
+        Api::Egl,
+        (1, 5),
+        Profile::Core,
+        Fallbacks::All,
+        ["EGL_KHR_surfaceless_context", "EGL_MESA_platform_surfaceless"],
+    ).write_bindings(GlobalGenerator, &mut file)
+        .unwrap();
+
     embed_resource::compile("hidpi.rc");
 }
@@ -3,6 +3,8 @@ use notify::Watcher;
 use std::any::Any;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::mpsc::{channel, Receiver};
 use std::sync::{Arc, Weak};
 use std::time::Duration;
@@ -21,6 +23,8 @@ impl<T> CacheCell<T> {
 
 pub struct Cache {
     cached_objects: RefCell<HashMap<String, Box<CacheCell<Any>>>>,
+    /// Functions that reload watched objects, by canonical path of the watched file.
+    reloaders: RefCell<HashMap<PathBuf, (String, Rc<Fn(&Cache, ReloadReason)>)>>,
     fs_watcher: RefCell<notify::RecommendedWatcher>,
     fs_events: Receiver<notify::DebouncedEvent>,
 }
@@ -46,15 +50,59 @@ impl Cache {
 
         Cache {
             cached_objects: RefCell::new(HashMap::new()),
+            reloaders: RefCell::new(HashMap::new()),
             fs_events: rx,
             fs_watcher: RefCell::new(watcher),
         }
     }
 
-    pub fn process_filesystem_events(&self) {
+    /// Reloads the objects whose files were modified since the last call.
+    /// Returns the paths of the reloaded objects.
+    pub fn process_filesystem_events(&self) -> Vec<String> {
         // go through all filesystem events and see if one concerns an object in the cache
-        for ev in self.fs_events.try_iter() {
-            debug!("watch event: {:?}", ev);
+        self.fs_events
+            .try_iter()
+            .filter_map(|ev| self.dispatch_filesystem_event(ev))
+            .collect()
+    }
+
+    /// Reloads the object watched at the path of a filesystem event, if any.
+    /// Returns the path of the reloaded object.
+    fn dispatch_filesystem_event(&self, ev: notify::DebouncedEvent) -> Option<String> {
+        debug!("watch event: {:?}", ev);
+        let (path, reason, rewatch) = match ev {
+            notify::DebouncedEvent::Create(path) => (path, ReloadReason::FileCreated, true),
+            notify::DebouncedEvent::Write(path) => (path, ReloadReason::FileModified, false),
+            notify::DebouncedEvent::Remove(path) => (path, ReloadReason::FileRemoved, true),
+            _ => return None,
+        };
+        // files are watched by their canonical path, which is also the path of their events:
+        // the lookup does not need the file to exist anymore.
+        // Release the borrow before calling the reloader, which may watch other files.
+        let reloader = self
+            .reloaders
+            .borrow()
+            .get(&path)
+            .map(|&(ref id, ref reload)| (id.clone(), reload.clone()));
+        let (id, reload) = match reloader {
+            Some(reloader) => reloader,
+            None => return None,
+        };
+        if rewatch {
+            // the watch does not follow the file once it is removed or replaced
+            self.watch(&path);
+        }
+        reload(self, reason);
+        Some(id)
+    }
+
+    fn watch(&self, path: &Path) {
+        if let Err(err) = self
+            .fs_watcher
+            .borrow_mut()
+            .watch(path, notify::RecursiveMode::NonRecursive)
+        {
+            warn!("could not watch {}: {}", path.display(), err);
         }
     }
 
@@ -71,16 +119,30 @@ impl Cache {
         obj
     }
 
+    /// Loads an object with `f` and adds it to the cache, then calls `f` again to replace
+    /// the object each time the file at `path` changes (see `process_filesystem_events`).
+    /// If `f` returns `None` on reload, the previous version of the object is kept.
     pub fn add_and_watch<T, F>(&self, path: String, f: F) -> Option<T>
     where
         T: Any + Clone,
-        F: Fn(&str, ReloadReason) -> Option<T>,
+        F: Fn(&str, ReloadReason) -> Option<T> + 'static,
     {
         let result = f(&path, ReloadReason::Initial).map(|val| self.add(path.clone(), val));
         // setup watch
-        self.fs_watcher
-            .borrow_mut()
-            .watch(&path, notify::RecursiveMode::NonRecursive);
+        let canonical = canonical_path(Path::new(&path));
+        self.watch(&canonical);
+        let id = path.clone();
+        self.reloaders.borrow_mut().insert(
+            canonical,
+            (
+                path,
+                Rc::new(move |cache: &Cache, reason| {
+                    if let Some(val) = f(&id, reason) {
+                        cache.add(id.clone(), val);
+                    }
+                }),
+            ),
+        );
         result
     }
 
@@ -110,3 +172,102 @@ impl Cache {
             .and_then(|obj| obj.inner.downcast_ref::<T>().map(|v| v.clone()))
     }
 }
+
+/// Returns the canonical form of a path, or the path itself if it does not exist anymore.
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+    use std::thread;
+    use std::time::Instant;
+
+    fn write_file(path: &Path, contents: &str) {
+        fs::File::create(path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+
+    /// Returns a path in the temporary directory that is unique to a test of this process.
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("autograph_{}_{}.txt", name, process::id()))
+    }
+
+    fn watch_file(cache: &Cache, path: &str) -> Option<Arc<String>> {
+        cache.add_and_watch(path.to_owned(), |path, _| {
+            fs::read_to_string(path).ok().map(Arc::new)
+        })
+    }
+
+    #[test]
+    fn test_cache_reload_dispatch() {
+        let path = temp_path("cache_reload_dispatch");
+        write_file(&path, "initial");
+        let path_str = path.to_str().unwrap().to_owned();
+        let canonical = path.canonicalize().unwrap();
+
+        let cache = Cache::new();
+        assert_eq!(watch_file(&cache, &path_str).unwrap().as_str(), "initial");
+
+        write_file(&path, "modified");
+        assert_eq!(
+            cache.dispatch_filesystem_event(notify::DebouncedEvent::Write(canonical.clone())),
+            Some(path_str.clone())
+        );
+        assert_eq!(cache.get::<Arc<String>>(&path_str).unwrap().as_str(), "modified");
+
+        // removed files are still matched to their object, which keeps its last version
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            cache.dispatch_filesystem_event(notify::DebouncedEvent::Remove(canonical.clone())),
+            Some(path_str.clone())
+        );
+        assert_eq!(cache.get::<Arc<String>>(&path_str).unwrap().as_str(), "modified");
+
+        write_file(&path, "created");
+        assert_eq!(
+            cache.dispatch_filesystem_event(notify::DebouncedEvent::Create(canonical)),
+            Some(path_str.clone())
+        );
+        fs::remove_file(&path).unwrap();
+        assert_eq!(cache.get::<Arc<String>>(&path_str).unwrap().as_str(), "created");
+
+        // events for files that are not watched are ignored
+        assert_eq!(
+            cache.dispatch_filesystem_event(notify::DebouncedEvent::Write(temp_path("unwatched"))),
+            None
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_cache_reload_on_write() {
+        let path = temp_path("cache_reload_on_write");
+        write_file(&path, "initial");
+        let path_str = path.to_str().unwrap().to_owned();
+
+        let cache = Cache::new();
+        assert_eq!(watch_file(&cache, &path_str).unwrap().as_str(), "initial");
+
+        write_file(&path, "modified");
+        // events are debounced by the watcher: poll until the reload happens
+        let start = Instant::now();
+        let mut reloaded = Vec::new();
+        while reloaded.is_empty() && start.elapsed() < Duration::from_secs(10) {
+            thread::sleep(Duration::from_millis(100));
+            reloaded = cache.process_filesystem_events();
+        }
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(reloaded, vec![path_str.clone()]);
+        let current = cache.get::<Arc<String>>(&path_str).unwrap();
+        assert_eq!(current.as_str(), "modified");
+    }
+}
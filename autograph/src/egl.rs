@@ -0,0 +1,18 @@
+//! EGL bindings, used to create headless OpenGL contexts.
+//! The functions are loaded at runtime with `load_with`.
+#![allow(non_camel_case_types)]
+
+use std::os::raw::{c_long, c_void};
+
+pub type khronos_utime_nanoseconds_t = khronos_uint64_t;
+pub type khronos_uint64_t = u64;
+pub type khronos_ssize_t = c_long;
+pub type EGLint = i32;
+pub type EGLNativeDisplayType = *const c_void;
+pub type EGLNativePixmapType = *const c_void;
+pub type EGLNativeWindowType = *const c_void;
+pub type NativeDisplayType = EGLNativeDisplayType;
+pub type NativePixmapType = EGLNativePixmapType;
+pub type NativeWindowType = EGLNativeWindowType;
+
+include!(concat!(env!("OUT_DIR"), "/egl_bindings.rs"));
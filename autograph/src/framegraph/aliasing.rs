@@ -0,0 +1,237 @@
+//! Aliasing strategies: how frame graph resources are assigned to allocations.
+//!
+use super::*;
+use gfx;
+use std::i32;
+
+/// A request for an allocation, made by a frame graph resource.
+#[derive(Copy, Clone, Debug)]
+pub struct AllocationRequest {
+    /// Description of the allocation needed by the resource.
+    /// Buffer sizes are already rounded up to the size of their pool.
+    pub info: ResourceInfo,
+    /// When the resource is alive during the execution of the frame graph.
+    pub lifetime: Lifetime,
+}
+
+/// Decides which resources share the same allocation.
+pub trait AliasingStrategy {
+    /// Assigns an allocation to each request.
+    /// `allocations` contains the descriptions of the allocations that already exist;
+    /// new allocations can be appended to it.
+    /// Returns the allocation of each request, in the same order as `requests`.
+    /// Two requests with overlapping lifetimes must not be assigned the same allocation.
+    fn assign(
+        &self,
+        requests: &[AllocationRequest],
+        allocations: &mut Vec<ResourceInfo>,
+    ) -> Vec<AliasedResourceIndex>;
+}
+
+/// Returns whether an allocation can hold the contents of a resource.
+/// Textures must have identical descriptions; buffers must be large enough.
+fn fits(alloc: &ResourceInfo, request: &ResourceInfo) -> bool {
+    match (*alloc, *request) {
+        (ResourceInfo::Texture { desc: a }, ResourceInfo::Texture { desc: b }) => a == b,
+        (ResourceInfo::Buffer { byte_size: a }, ResourceInfo::Buffer { byte_size: b }) => a >= b,
+        _ => false,
+    }
+}
+
+/// Greedily assigns allocations to the requests, visiting them in the specified order.
+/// Among the allocations that can be used for a request, the one with the smallest key is chosen.
+/// A new allocation is created if there is none.
+fn assign_greedy<K, F>(
+    requests: &[AllocationRequest],
+    order: &[usize],
+    allocations: &mut Vec<ResourceInfo>,
+    key: F,
+) -> Vec<AliasedResourceIndex>
+where
+    K: Ord,
+    F: Fn(usize, &ResourceInfo, &[Lifetime]) -> K,
+{
+    // lifetimes of the requests assigned to each allocation
+    let mut users: Vec<Vec<Lifetime>> = vec![Vec::new(); allocations.len()];
+    let mut assigned = vec![AliasedResourceIndex::new(0); requests.len()];
+
+    for &i in order.iter() {
+        let request = &requests[i];
+        let candidate = allocations
+            .iter()
+            .enumerate()
+            .filter(|&(a, info)| {
+                fits(info, &request.info)
+                    && users[a].iter().all(|l| !l.overlaps(&request.lifetime))
+            })
+            .min_by_key(|&(a, info)| key(a, info, &users[a]))
+            .map(|(a, _)| a);
+
+        let a = match candidate {
+            Some(a) => a,
+            None => {
+                allocations.push(request.info);
+                users.push(Vec::new());
+                allocations.len() - 1
+            }
+        };
+        users[a].push(request.lifetime);
+        assigned[i] = AliasedResourceIndex::new(a);
+    }
+
+    assigned
+}
+
+/// Assigns each resource (in declaration order) to the first allocation that can hold it.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FirstFit;
+
+impl AliasingStrategy for FirstFit {
+    fn assign(
+        &self,
+        requests: &[AllocationRequest],
+        allocations: &mut Vec<ResourceInfo>,
+    ) -> Vec<AliasedResourceIndex> {
+        let order: Vec<usize> = (0..requests.len()).collect();
+        assign_greedy(requests, &order, allocations, |a, _, _| a)
+    }
+}
+
+/// Assigns each resource (in declaration order) to the smallest allocation that can hold it.
+/// Mostly useful for buffers, since textures can only share allocations with identical descriptions.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BestFit;
+
+impl AliasingStrategy for BestFit {
+    fn assign(
+        &self,
+        requests: &[AllocationRequest],
+        allocations: &mut Vec<ResourceInfo>,
+    ) -> Vec<AliasedResourceIndex> {
+        let order: Vec<usize> = (0..requests.len()).collect();
+        assign_greedy(requests, &order, allocations, |_, info, _| {
+            resource_byte_size(info)
+        })
+    }
+}
+
+/// Interval graph colouring: resources are visited by order of their first use, and placed in
+/// the compatible allocation that was released most recently.
+/// This minimizes the number of allocations for each kind of resource.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct IntervalColoring;
+
+impl AliasingStrategy for IntervalColoring {
+    fn assign(
+        &self,
+        requests: &[AllocationRequest],
+        allocations: &mut Vec<ResourceInfo>,
+    ) -> Vec<AliasedResourceIndex> {
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| (requests[i].lifetime.begin, i));
+        assign_greedy(requests, &order, allocations, |_, _, users| {
+            users
+                .iter()
+                .map(|l| l.end)
+                .max()
+                .map_or(i32::MAX, |end| -end)
+        })
+    }
+}
+
+/// Returns the size in bytes of a resource, or of an allocation.
+///
+/// Texture sizes include all mip levels, array layers, cube faces and samples.
+/// They are computed from `FormatInfo::byte_size`, and are thus only an estimate of the
+/// actual memory used by the driver (compressed formats count as zero bytes).
+pub fn resource_byte_size(info: &ResourceInfo) -> usize {
+    match *info {
+        ResourceInfo::Buffer { byte_size } => byte_size,
+        ResourceInfo::Texture { ref desc } => {
+            let texel_size = desc.format.get_format_info().byte_size();
+            let mip_count = match desc.mip_map_count {
+                gfx::MipMaps::Count(count) => count as u32,
+                gfx::MipMaps::Auto => 32 - cmp::max(cmp::max(desc.width, desc.height), 1).leading_zeros(),
+            };
+            let samples = cmp::max(desc.sample_count, 1) as usize;
+            let height = cmp::max(desc.height, 1);
+            let depth = cmp::max(desc.depth, 1);
+            // (height, depth) of the base level, whether they are reduced in the mip chain,
+            // and number of layers
+            let (height, depth, mip_height, mip_depth, layers) = match desc.dimensions {
+                gfx::TextureDimensions::Tex1D => (1, 1, false, false, 1),
+                gfx::TextureDimensions::Tex1DArray => (1, 1, false, false, height),
+                gfx::TextureDimensions::Tex2D => (height, 1, true, false, 1),
+                gfx::TextureDimensions::Tex2DArray => (height, 1, true, false, depth),
+                gfx::TextureDimensions::TexCube => (height, 1, true, false, 6),
+                gfx::TextureDimensions::Tex3D => (height, depth, true, true, 1),
+            };
+            let mut size = 0;
+            for level in 0..mip_count {
+                let w = cmp::max(desc.width >> level, 1);
+                let h = if mip_height { cmp::max(height >> level, 1) } else { height };
+                let d = if mip_depth { cmp::max(depth >> level, 1) } else { depth };
+                size += w as usize * h as usize * d as usize * texel_size * samples;
+            }
+            size * layers as usize
+        }
+    }
+}
+
+/// Memory usage of the transient resources of a compiled frame graph.
+/// Imported resources are not counted.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryReport {
+    /// Number of transient resources.
+    pub resource_count: usize,
+    /// Number of allocations used by the transient resources.
+    pub allocation_count: usize,
+    /// Memory that would be needed without aliasing (one allocation per resource).
+    pub no_aliasing_bytes: usize,
+    /// Total size of the allocations used by the graph.
+    pub allocated_bytes: usize,
+    /// Maximum of the memory used by the resources alive at the same time during the frame.
+    /// This is a lower bound for `allocated_bytes`.
+    pub peak_live_bytes: usize,
+}
+
+impl MemoryReport {
+    /// Computes the memory report of a set of resources.
+    /// `resources` contains, for each transient resource, its description, lifetime and allocation.
+    pub(super) fn new(
+        resources: &[(ResourceInfo, Lifetime, AliasedResourceIndex)],
+        allocations: &[ResourceInfo],
+    ) -> MemoryReport {
+        let mut used_allocations = HashSet::new();
+        let mut no_aliasing_bytes = 0;
+        for &(ref info, _, arindex) in resources.iter() {
+            no_aliasing_bytes += resource_byte_size(info);
+            used_allocations.insert(arindex);
+        }
+        let allocated_bytes = used_allocations
+            .iter()
+            .map(|arindex| resource_byte_size(&allocations[arindex.index()]))
+            .sum::<usize>();
+
+        // the live set only grows at the beginning of a lifetime: only evaluate it there
+        let peak_live_bytes = resources
+            .iter()
+            .map(|&(_, ref at, _)| {
+                resources
+                    .iter()
+                    .filter(|&&(_, ref l, _)| l.begin <= at.begin && at.begin <= l.end)
+                    .map(|&(ref info, _, _)| resource_byte_size(info))
+                    .sum::<usize>()
+            })
+            .max()
+            .unwrap_or(0);
+
+        MemoryReport {
+            resource_count: resources.len(),
+            allocation_count: used_allocations.len(),
+            no_aliasing_bytes,
+            allocated_bytes,
+            peak_live_bytes,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Automatic placement of memory barriers between passes.
+//!
+//! Writes to images (`RWImage`) and shader storage buffers (`ShaderStorageBuffer`) are incoherent:
+//! a memory barrier is needed before another pass can see them. The barrier bits depend on
+//! how the next pass accesses the resource.
+//! Other writes (render targets, transform feedback) are synchronized automatically by GL.
+//!
+use super::*;
+use gfx::{MemoryBarriers, MB_FRAMEBUFFER, MB_SHADER_IMAGE_ACCESS, MB_SHADER_STORAGE,
+          MB_TEXTURE_FETCH, MB_TRANSFORM_FEEDBACK, MB_UNIFORM};
+
+impl ResourceUsage {
+    /// Returns whether writes with this usage need a memory barrier to be visible to subsequent passes.
+    fn is_incoherent_write(&self) -> bool {
+        match *self {
+            ResourceUsage::RWImage | ResourceUsage::ShaderStorageBuffer => true,
+            _ => false,
+        }
+    }
+
+    /// Barrier bits needed before accessing a resource with this usage after an incoherent write.
+    fn barrier_bits(&self) -> MemoryBarriers {
+        match *self {
+            ResourceUsage::SampledImage => MB_TEXTURE_FETCH,
+            ResourceUsage::RWImage => MB_SHADER_IMAGE_ACCESS,
+            ResourceUsage::RenderTarget => MB_FRAMEBUFFER,
+            ResourceUsage::UniformBuffer => MB_UNIFORM,
+            ResourceUsage::ShaderStorageBuffer => MB_SHADER_STORAGE,
+            ResourceUsage::TransformFeedbackOutput => MB_TRANSFORM_FEEDBACK,
+            // we don't know how the resource is accessed
+            ResourceUsage::Default => MemoryBarriers::all(),
+        }
+    }
+}
+
+impl<'node> FrameGraph<'node> {
+    /// Computes the memory barriers to issue before each pass, given the execution order.
+    /// Returns, for each pass that needs one, the barrier bits to issue before executing it.
+    ///
+    /// A pass needs a barrier if it accesses subresources that were written incoherently by
+    /// a previous pass (in this version of the resource or in an earlier one).
+    /// A barrier bit is not issued again if it was already issued after the write
+    /// and before the pass.
+    pub(super) fn compute_barriers(&self, toposort: &[NodeIndex]) -> Vec<(NodeIndex, MemoryBarriers)> {
+        // position of each pass in the execution order
+        let mut pass_position = HashMap::new();
+        for &n in toposort.iter() {
+            if self.graph.node_weight(n).unwrap().is_pass() {
+                let pos = pass_position.len();
+                pass_position.insert(n, pos);
+            }
+        }
+
+        // all incoherent writes: (writer, resource, version produced, range)
+        let incoherent_writes: Vec<(NodeIndex, UnversionedResourceIndex, i32, SubresourceRange)> = self
+            .graph
+            .edge_indices()
+            .filter_map(|e| {
+                let (src, dst) = self.graph.edge_endpoints(e).unwrap();
+                let edge = self.graph.edge_weight(e).unwrap();
+                if !self.graph.node_weight(src).unwrap().is_pass() || !edge.usage.is_incoherent_write() {
+                    return None;
+                }
+                let (index, version) = self.resource_version(dst);
+                Some((src, index, version, edge.range))
+            })
+            .collect();
+
+        // barriers issued so far, with the position of the pass that issued them
+        let mut issued: Vec<(usize, MemoryBarriers)> = Vec::new();
+        let mut barriers = Vec::new();
+
+        for &pass in toposort.iter() {
+            let pos = match pass_position.get(&pass) {
+                Some(&pos) => pos,
+                None => continue,
+            };
+            let mut needed = MemoryBarriers::empty();
+            // for all subresources accessed by the pass...
+            for input in self.graph.edges_directed(pass, Direction::Incoming) {
+                let (index, version) = self.resource_version(input.source());
+                // ...look at how they were written by previous passes
+                for &(writer, _, _, _) in incoherent_writes.iter().filter(|&&(w, i, v, r)| {
+                    w != pass && i == index && v <= version && r.overlaps(&input.weight().range)
+                }) {
+                    let writer_pos = pass_position[&writer];
+                    let already_issued = issued
+                        .iter()
+                        .filter(|&&(issued_pos, _)| issued_pos > writer_pos)
+                        .fold(MemoryBarriers::empty(), |acc, &(_, bits)| acc | bits);
+                    needed |= input.weight().usage.barrier_bits() - already_issued;
+                }
+            }
+            if !needed.is_empty() {
+                issued.push((pos, needed));
+                barriers.push((pass, needed));
+            }
+        }
+
+        barriers
+    }
+}
@@ -0,0 +1,349 @@
+//! Compute passes
+//!
+use super::*;
+use gfx::DrawExt;
+use gl;
+use gl::types::*;
+
+/// Compute pass callbacks.
+/// `cmd` is a dispatch command with the pipeline and the resources declared in the pass
+/// already bound: callbacks can bind additional data (uniform buffers, ...) before submitting it.
+pub trait ComputePassCallbacks {
+    fn execute(&self, frame: &gfx::Frame, ectx: &ExecutionContext, cmd: gfx::DispatchCmdBuilder);
+}
+
+impl<F> ComputePassCallbacks for F
+where
+    F: Fn(&gfx::Frame, &ExecutionContext, gfx::DispatchCmdBuilder),
+{
+    fn execute(&self, frame: &gfx::Frame, ectx: &ExecutionContext, cmd: gfx::DispatchCmdBuilder) {
+        self(frame, ectx, cmd)
+    }
+}
+
+/// Number of work groups launched by a compute pass.
+#[derive(Copy, Clone, Debug, Hash)]
+pub enum DispatchSize {
+    /// A fixed number of work groups.
+    Groups(u32, u32, u32),
+    /// A fixed number of invocations, rounded up to a multiple of the work group size.
+    Invocations(u32, u32, u32),
+    /// One invocation per texel of the specified mip level of a texture resource.
+    /// Array layers and cube faces are dispatched along Z.
+    TextureSize { resource: ResourceVersion, mip_level: u32 },
+    /// One invocation (along X) per element of a buffer resource.
+    /// `element_size` must not be zero.
+    BufferElements {
+        resource: ResourceVersion,
+        element_size: usize,
+    },
+}
+
+/// A resource bound to a compute pass.
+#[derive(Copy, Clone, Debug, Hash)]
+pub(super) enum ComputeBinding {
+    Image {
+        slot: u32,
+        resource: ResourceVersion,
+        mip_level: u32,
+        access: GLenum,
+    },
+    Texture {
+        slot: u32,
+        resource: ResourceVersion,
+        sampler: gfx::SamplerDesc,
+    },
+    UniformBuffer { slot: u32, resource: ResourceVersion },
+    StorageBuffer { slot: u32, resource: ResourceVersion },
+}
+
+fn div_round_up(n: u32, d: u32) -> u32 {
+    (n + d - 1) / d
+}
+
+/// Compute pass builder
+pub struct ComputePassBuilder<'fg, 'node: 'fg> {
+    pass: RenderPass,
+    framegraph: &'fg mut FrameGraph<'node>,
+}
+
+impl<'fg, 'node> ComputePassBuilder<'fg, 'node> {
+    fn bind(&mut self, binding: ComputeBinding) {
+        if let Some(&mut Node::ComputePass {
+            ref mut bindings, ..
+        }) = self.framegraph.graph.node_weight_mut(self.pass.0)
+        {
+            bindings.push(binding);
+        }
+    }
+
+    /// Reads the first mip level of a texture through an image unit.
+    pub fn read_image(&mut self, slot: u32, res: ResourceVersion) {
+        self.read_image_mip(slot, res, 0)
+    }
+
+    /// Reads a mip level of a texture through an image unit.
+    pub fn read_image_mip(&mut self, slot: u32, res: ResourceVersion, mip_level: u32) {
+        self.framegraph.link_read(
+            self.pass,
+            res,
+            ResourceUsage::RWImage,
+            SubresourceRange::mip(mip_level),
+        );
+        self.bind(ComputeBinding::Image {
+            slot,
+            resource: res,
+            mip_level,
+            access: gl::READ_ONLY,
+        });
+    }
+
+    /// Reads and writes the first mip level of a texture through an image unit.
+    /// Returns the new version of the resource.
+    pub fn write_image(&mut self, slot: u32, res: ResourceVersion) -> ResourceVersion {
+        self.write_image_mip(slot, res, 0)
+    }
+
+    /// Reads and writes a mip level of a texture through an image unit.
+    /// Returns the new version of the resource.
+    pub fn write_image_mip(&mut self, slot: u32, res: ResourceVersion, mip_level: u32) -> ResourceVersion {
+        let res_v2 = self.framegraph.link_write(
+            self.pass,
+            res,
+            ResourceUsage::RWImage,
+            SubresourceRange::mip(mip_level),
+        );
+        self.bind(ComputeBinding::Image {
+            slot,
+            resource: res_v2,
+            mip_level,
+            access: gl::READ_WRITE,
+        });
+        res_v2
+    }
+
+    /// Samples a texture.
+    pub fn read_texture(&mut self, slot: u32, res: ResourceVersion, sampler: &gfx::SamplerDesc) {
+        self.framegraph
+            .link_read(self.pass, res, ResourceUsage::SampledImage, SubresourceRange::all());
+        self.bind(ComputeBinding::Texture {
+            slot,
+            resource: res,
+            sampler: *sampler,
+        });
+    }
+
+    /// Reads a buffer as a uniform buffer.
+    pub fn read_uniform_buffer(&mut self, slot: u32, res: ResourceVersion) {
+        self.framegraph
+            .link_read(self.pass, res, ResourceUsage::UniformBuffer, SubresourceRange::all());
+        self.bind(ComputeBinding::UniformBuffer { slot, resource: res });
+    }
+
+    /// Reads a buffer as a shader storage buffer.
+    pub fn read_storage_buffer(&mut self, slot: u32, res: ResourceVersion) {
+        self.framegraph
+            .link_read(self.pass, res, ResourceUsage::ShaderStorageBuffer, SubresourceRange::all());
+        self.bind(ComputeBinding::StorageBuffer { slot, resource: res });
+    }
+
+    /// Reads and writes a buffer as a shader storage buffer.
+    /// Returns the new version of the resource.
+    pub fn write_storage_buffer(&mut self, slot: u32, res: ResourceVersion) -> ResourceVersion {
+        let res_v2 = self.framegraph.link_write(
+            self.pass,
+            res,
+            ResourceUsage::ShaderStorageBuffer,
+            SubresourceRange::all(),
+        );
+        self.bind(ComputeBinding::StorageBuffer {
+            slot,
+            resource: res_v2,
+        });
+        res_v2
+    }
+
+    /// Creates a texture written by the pass through an image unit.
+    pub fn create_image<S: Into<String>>(
+        &mut self,
+        slot: u32,
+        name: S,
+        desc: &gfx::TextureDesc,
+    ) -> ResourceVersion {
+        let res = self
+            .framegraph
+            .create_resource(name.into(), ResourceInfo::Texture { desc: *desc });
+        self.framegraph
+            .link_output(self.pass, res, ResourceUsage::RWImage);
+        self.bind(ComputeBinding::Image {
+            slot,
+            resource: res,
+            mip_level: 0,
+            access: gl::WRITE_ONLY,
+        });
+        res
+    }
+
+    /// Creates a texture written by the pass through an image unit, with a size relative
+    /// to a viewport or another texture. The width and height of `desc` are ignored.
+    pub fn create_image_relative<S: Into<String>>(
+        &mut self,
+        slot: u32,
+        name: S,
+        desc: &gfx::TextureDesc,
+        size: RelativeSize,
+    ) -> ResourceVersion {
+        let res = self
+            .framegraph
+            .create_relative_texture(name.into(), desc, size);
+        self.framegraph
+            .link_output(self.pass, res, ResourceUsage::RWImage);
+        self.bind(ComputeBinding::Image {
+            slot,
+            resource: res,
+            mip_level: 0,
+            access: gl::WRITE_ONLY,
+        });
+        res
+    }
+
+    /// Creates a buffer written by the pass as a shader storage buffer.
+    pub fn create_storage_buffer<S: Into<String>>(
+        &mut self,
+        slot: u32,
+        name: S,
+        byte_size: usize,
+    ) -> ResourceVersion {
+        let res = self
+            .framegraph
+            .create_resource(name.into(), ResourceInfo::Buffer { byte_size });
+        self.framegraph
+            .link_output(self.pass, res, ResourceUsage::ShaderStorageBuffer);
+        self.bind(ComputeBinding::StorageBuffer { slot, resource: res });
+        res
+    }
+
+    /// Sets the number of work groups launched by the pass.
+    /// The default is a single work group.
+    ///
+    /// Panics if the size is `BufferElements` with an `element_size` of zero.
+    pub fn dispatch(&mut self, size: DispatchSize) {
+        if let DispatchSize::BufferElements { element_size, .. } = size {
+            assert!(
+                element_size != 0,
+                "compute pass dispatched over buffer elements of size zero"
+            );
+        }
+        if let Some(&mut Node::ComputePass { ref mut dispatch, .. }) =
+            self.framegraph.graph.node_weight_mut(self.pass.0)
+        {
+            *dispatch = size;
+        }
+    }
+
+    /// Runs the pass only if `predicate` returns true when the graph is compiled.
+    /// See `RenderPassBuilder::enable_if`.
+    pub fn enable_if<P: Fn() -> bool + 'node>(&mut self, predicate: P) {
+        self.framegraph
+            .set_pass_condition(self.pass, Box::new(predicate));
+    }
+
+    pub fn build(self) -> RenderPass {
+        self.pass
+    }
+}
+
+impl<'node> FrameGraph<'node> {
+    /// Creates a compute pass, which dispatches the specified pipeline once per execution
+    /// of the frame graph.
+    pub fn create_compute_pass<'fg, S: Into<String>, C: ComputePassCallbacks + 'node>(
+        &'fg mut self,
+        name: S,
+        pipeline: &gfx::ComputePipeline,
+        callbacks: C,
+    ) -> ComputePassBuilder<'fg, 'node> {
+        let name = self.prefixed_name(name.into());
+        let pass = RenderPass(self.graph.add_node(Node::ComputePass {
+            name,
+            pipeline: pipeline.clone(),
+            callbacks: Box::new(callbacks),
+            dispatch: DispatchSize::Groups(1, 1, 1),
+            bindings: Vec::new(),
+        }));
+        ComputePassBuilder {
+            framegraph: self,
+            pass,
+        }
+    }
+
+    /// Returns the number of work groups to launch for the given dispatch size and
+    /// work group size.
+    pub(super) fn dispatch_groups(
+        &self,
+        dispatch: &DispatchSize,
+        workgroup_size: (u32, u32, u32),
+    ) -> (u32, u32, u32) {
+        let invocations = match *dispatch {
+            DispatchSize::Groups(x, y, z) => return (x, y, z),
+            DispatchSize::Invocations(x, y, z) => (x, y, z),
+            DispatchSize::TextureSize {
+                resource,
+                mip_level,
+            } => match *self.resource_info(resource) {
+                // array layers and cube faces are not reduced in the mip chain
+                ResourceInfo::Texture { ref desc } => {
+                    gfx::readback::mip_level_size(desc, mip_level)
+                }
+                _ => panic!("not a texture resource"),
+            },
+            DispatchSize::BufferElements {
+                resource,
+                element_size,
+            } => match *self.resource_info(resource) {
+                ResourceInfo::Buffer { byte_size } => ((byte_size / element_size) as u32, 1, 1),
+                _ => panic!("not a buffer resource"),
+            },
+        };
+        (
+            div_round_up(invocations.0, cmp::max(workgroup_size.0, 1)),
+            div_round_up(invocations.1, cmp::max(workgroup_size.1, 1)),
+            div_round_up(invocations.2, cmp::max(workgroup_size.2, 1)),
+        )
+    }
+}
+
+impl<'a, 'p> ExecutionContext<'a, 'p> {
+    /// Builds the dispatch command of a compute pass and binds its resources.
+    pub(super) fn begin_dispatch<'frame, 'queue: 'frame>(
+        &self,
+        frame: &'frame gfx::Frame<'queue>,
+        pipeline: &gfx::ComputePipeline,
+        dispatch: &DispatchSize,
+        bindings: &[ComputeBinding],
+    ) -> gfx::DispatchCmdBuilder<'frame, 'queue> {
+        let num_groups = self.fg.dispatch_groups(dispatch, pipeline.workgroup_size());
+        let mut cmd = frame.dispatch(pipeline, num_groups);
+        for binding in bindings.iter() {
+            cmd = match *binding {
+                ComputeBinding::Image {
+                    slot,
+                    resource,
+                    mip_level,
+                    access,
+                } => cmd.with_image(slot, &self.texture_resource(resource), mip_level, access),
+                ComputeBinding::Texture {
+                    slot,
+                    resource,
+                    ref sampler,
+                } => cmd.with_texture(slot, &self.texture_resource(resource), sampler),
+                ComputeBinding::UniformBuffer { slot, resource } => {
+                    cmd.with_uniform_buffer_any(slot, &self.buffer_resource(resource))
+                }
+                ComputeBinding::StorageBuffer { slot, resource } => {
+                    cmd.with_storage_buffer_any(slot, &self.buffer_resource(resource))
+                }
+            };
+        }
+        cmd
+    }
+}
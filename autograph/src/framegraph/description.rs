@@ -0,0 +1,383 @@
+//! Frame graphs described in data files.
+//!
+//! A description lists passes in execution order. Passes refer to resources by name,
+//! and always access the latest version of a resource. For instance:
+//!
+//! ```toml
+//! outputs = ["color"]
+//!
+//! [[passes]]
+//! name = "gbuffer"
+//! kind = "gbuffer"
+//! pipeline = "data/shaders/gbuffer.glsl"
+//! create = [
+//!     { name = "normals", format = "A2R10G10B10_UNORM_PACK32", viewport = "main", usage = "RenderTarget" },
+//!     { name = "depth", format = "D32_SFLOAT", viewport = "main", usage = "RenderTarget" },
+//! ]
+//!
+//! [[passes]]
+//! name = "lighting"
+//! kind = "deferred_lighting"
+//! read = [{ resource = "normals", usage = "SampledImage" }, { resource = "depth", usage = "SampledImage" }]
+//! create = [{ name = "color", format = "R16G16B16A16_SFLOAT", viewport = "main", usage = "RenderTarget" }]
+//! params = { ambient = 0.1 }
+//! ```
+//!
+//! The callbacks of each pass are created by the factory registered for its `kind`
+//! in a `PassRegistry`.
+//!
+use super::*;
+use cache::{Cache, ReloadReason};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use toml;
+
+/// Description of a frame graph.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GraphDescription {
+    #[serde(default)]
+    pub passes: Vec<PassDescription>,
+    /// Names of the resources to mark as outputs of the graph.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+/// Description of a pass.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PassDescription {
+    pub name: String,
+    /// The factory used to create the callbacks of the pass.
+    pub kind: String,
+    /// Path to the pipeline used by the pass, loaded by the factory.
+    #[serde(default)]
+    pub pipeline: Option<String>,
+    #[serde(default)]
+    pub create: Vec<ResourceDescription>,
+    #[serde(default)]
+    pub read: Vec<AccessDescription>,
+    #[serde(default)]
+    pub write: Vec<AccessDescription>,
+    /// Additional parameters for the factory.
+    #[serde(default)]
+    pub params: toml::value::Table,
+}
+
+/// Description of a resource created by a pass.
+///
+/// Resources with a `byte_size` are buffers. Textures have either a fixed size (`width`
+/// and `height`), or a size relative to a viewport (`viewport`, multiplied by `scale`).
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResourceDescription {
+    pub name: String,
+    pub usage: ResourceUsage,
+    #[serde(default)]
+    pub byte_size: Option<usize>,
+    #[serde(default)]
+    pub format: Option<gfx::Format>,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub viewport: Option<String>,
+    /// Numerator and denominator of the scale applied to the size of the viewport.
+    #[serde(default)]
+    pub scale: Option<(u32, u32)>,
+    #[serde(default)]
+    pub mip_levels: Option<u8>,
+}
+
+/// Description of a resource read or modified by a pass.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AccessDescription {
+    pub resource: String,
+    pub usage: ResourceUsage,
+    /// Access a single mip level instead of the whole texture.
+    #[serde(default)]
+    pub mip: Option<u32>,
+    /// Access a single layer instead of the whole texture.
+    #[serde(default)]
+    pub layer: Option<u32>,
+}
+
+impl AccessDescription {
+    fn range(&self) -> SubresourceRange {
+        let range = self.mip.map_or(SubresourceRange::all(), SubresourceRange::mip);
+        match self.layer {
+            Some(layer) => range.with_layer(layer),
+            None => range,
+        }
+    }
+}
+
+/// Errors that can occur when loading a frame graph description.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// No factory is registered for the kind of a pass.
+    UnknownPassKind { pass: String, kind: String },
+    /// A pass accesses a resource that was not created by a previous pass.
+    UnknownResource { pass: String, resource: String },
+    /// A resource description is incomplete.
+    InvalidResource {
+        pass: String,
+        resource: String,
+        reason: &'static str,
+    },
+    /// An output of the graph is not created by any pass.
+    UnknownOutput { resource: String },
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for LoadError {
+    fn from(err: toml::de::Error) -> LoadError {
+        LoadError::Parse(err)
+    }
+}
+
+impl ::std::error::Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::Io(_) => "I/O error",
+            LoadError::Parse(_) => "invalid frame graph description",
+            LoadError::UnknownPassKind { .. } => "unknown pass kind",
+            LoadError::UnknownResource { .. } => "unknown resource",
+            LoadError::InvalidResource { .. } => "invalid resource description",
+            LoadError::UnknownOutput { .. } => "unknown output resource",
+        }
+    }
+}
+
+impl ::std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            LoadError::Io(ref err) => write!(f, "I/O error: {}", err),
+            LoadError::Parse(ref err) => write!(f, "invalid frame graph description: {}", err),
+            LoadError::UnknownPassKind { ref pass, ref kind } => {
+                write!(f, "pass `{}` has unknown kind `{}`", pass, kind)
+            }
+            LoadError::UnknownResource {
+                ref pass,
+                ref resource,
+            } => write!(
+                f,
+                "pass `{}` accesses resource `{}`, which is not created by a previous pass",
+                pass, resource
+            ),
+            LoadError::InvalidResource {
+                ref pass,
+                ref resource,
+                reason,
+            } => write!(
+                f,
+                "invalid resource `{}` created by pass `{}`: {}",
+                resource, pass, reason
+            ),
+            LoadError::UnknownOutput { ref resource } => {
+                write!(f, "output `{}` is not created by any pass", resource)
+            }
+        }
+    }
+}
+
+impl GraphDescription {
+    /// Parses a description in the TOML format.
+    pub fn from_str(src: &str) -> Result<GraphDescription, LoadError> {
+        Ok(toml::from_str(src)?)
+    }
+
+    /// Loads a description from a TOML file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GraphDescription, LoadError> {
+        let mut src = String::new();
+        File::open(path)?.read_to_string(&mut src)?;
+        GraphDescription::from_str(&src)
+    }
+
+    /// Loads a description from a TOML file, and reloads it when the file is modified
+    /// (see `Cache::process_filesystem_events`). Get the latest version with `cache.get`.
+    /// If the modified file is invalid, the previous description is kept.
+    pub fn load_and_watch(cache: &Cache, path: &str) -> Option<Arc<GraphDescription>> {
+        cache.add_and_watch(path.to_owned(), |path, reason| match reason {
+            ReloadReason::FileRemoved => None,
+            _ => match GraphDescription::load(path) {
+                Ok(desc) => Some(Arc::new(desc)),
+                Err(err) => {
+                    error!("failed to load frame graph description {}: {}", path, err);
+                    None
+                }
+            },
+        })
+    }
+}
+
+/// The versions of the resources accessed by a pass, by name.
+/// Read and created resources map to the version seen by the pass, modified resources
+/// to the version produced by the pass.
+pub type PassResources = HashMap<String, ResourceVersion>;
+
+/// Creates the callbacks of a pass, given its description.
+pub trait PassFactory<'node> {
+    fn create(
+        &self,
+        desc: &PassDescription,
+        resources: &PassResources,
+    ) -> Box<RenderPassCallbacks + 'node>;
+}
+
+impl<'node, F> PassFactory<'node> for F
+where
+    F: Fn(&PassDescription, &PassResources) -> Box<RenderPassCallbacks + 'node>,
+{
+    fn create(
+        &self,
+        desc: &PassDescription,
+        resources: &PassResources,
+    ) -> Box<RenderPassCallbacks + 'node> {
+        self(desc, resources)
+    }
+}
+
+/// Pass factories, by kind.
+pub struct PassRegistry<'node> {
+    factories: HashMap<String, Box<PassFactory<'node> + 'node>>,
+}
+
+impl<'node> PassRegistry<'node> {
+    pub fn new() -> PassRegistry<'node> {
+        PassRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Registers the factory of a kind of pass, replacing the previous one.
+    pub fn register<S: Into<String>, F: PassFactory<'node> + 'node>(&mut self, kind: S, factory: F) {
+        self.factories.insert(kind.into(), Box::new(factory));
+    }
+}
+
+fn noop_callbacks(_frame: &gfx::Frame, _ectx: &ExecutionContext) {}
+
+impl<'node> FrameGraph<'node> {
+    /// Adds the passes of a description to the frame graph, and marks its outputs.
+    /// Returns the latest version of each resource created by the description.
+    pub fn load_description(
+        &mut self,
+        desc: &GraphDescription,
+        registry: &PassRegistry<'node>,
+    ) -> Result<HashMap<String, ResourceVersion>, LoadError> {
+        let mut versions: HashMap<String, ResourceVersion> = HashMap::new();
+
+        for pass_desc in desc.passes.iter() {
+            let factory = registry.factories.get(&pass_desc.kind).ok_or_else(|| {
+                LoadError::UnknownPassKind {
+                    pass: pass_desc.name.clone(),
+                    kind: pass_desc.kind.clone(),
+                }
+            })?;
+            let lookup = |versions: &HashMap<String, ResourceVersion>, name: &String| {
+                versions
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| LoadError::UnknownResource {
+                        pass: pass_desc.name.clone(),
+                        resource: name.clone(),
+                    })
+            };
+
+            // the callbacks need the versions produced by the pass: create them once
+            // the pass is linked
+            let pass = self.create_render_pass_node(pass_desc.name.clone(), Box::new(noop_callbacks));
+            let mut resources = PassResources::new();
+            for access in pass_desc.read.iter() {
+                let res = lookup(&versions, &access.resource)?;
+                self.link_read(pass, res, access.usage, access.range());
+                resources.insert(access.resource.clone(), res);
+            }
+            for access in pass_desc.write.iter() {
+                let res = lookup(&versions, &access.resource)?;
+                let res = self.link_write(pass, res, access.usage, access.range());
+                versions.insert(access.resource.clone(), res);
+                resources.insert(access.resource.clone(), res);
+            }
+            for res_desc in pass_desc.create.iter() {
+                let res = self.create_described_resource(&pass_desc.name, res_desc)?;
+                self.link_output(pass, res, res_desc.usage);
+                versions.insert(res_desc.name.clone(), res);
+                resources.insert(res_desc.name.clone(), res);
+            }
+
+            let new_callbacks = factory.create(pass_desc, &resources);
+            if let Some(&mut Node::RenderPass {
+                ref mut callbacks, ..
+            }) = self.graph.node_weight_mut(pass.0)
+            {
+                *callbacks = new_callbacks;
+            }
+        }
+
+        for output in desc.outputs.iter() {
+            let res = versions
+                .get(output)
+                .cloned()
+                .ok_or_else(|| LoadError::UnknownOutput {
+                    resource: output.clone(),
+                })?;
+            self.mark_output(res);
+        }
+
+        Ok(versions)
+    }
+
+    fn create_described_resource(
+        &mut self,
+        pass: &str,
+        desc: &ResourceDescription,
+    ) -> Result<ResourceVersion, LoadError> {
+        let invalid = |reason| LoadError::InvalidResource {
+            pass: pass.to_owned(),
+            resource: desc.name.clone(),
+            reason,
+        };
+
+        if let Some(byte_size) = desc.byte_size {
+            return Ok(self.create_resource(desc.name.clone(), ResourceInfo::Buffer { byte_size }));
+        }
+
+        let tex_desc = gfx::TextureDesc {
+            format: desc.format.ok_or_else(|| invalid("missing texture format"))?,
+            width: desc.width.unwrap_or(0),
+            height: desc.height.unwrap_or(0),
+            mip_map_count: gfx::MipMaps::Count(desc.mip_levels.unwrap_or(1)),
+            ..Default::default()
+        };
+        match (&desc.viewport, desc.width, desc.height) {
+            (&Some(ref viewport), None, None) => {
+                let (num, den) = desc.scale.unwrap_or((1, 1));
+                if num == 0 || den == 0 {
+                    return Err(invalid("invalid scale"));
+                }
+                Ok(self.create_relative_texture(
+                    desc.name.clone(),
+                    &tex_desc,
+                    RelativeSize::viewport(viewport.clone()).scaled(num, den),
+                ))
+            }
+            (&None, Some(_), Some(_)) => Ok(self.create_resource(
+                desc.name.clone(),
+                ResourceInfo::Texture { desc: tex_desc },
+            )),
+            _ => Err(invalid(
+                "textures must have either a width and a height, or a viewport",
+            )),
+        }
+    }
+}
@@ -1,45 +1,259 @@
 //! Compiled frame graphs
 //!
 use super::*;
+use gfx::DrawExt;
+use std::any::TypeId;
+use std::marker::PhantomData;
 
-/// Context for evaluating a frame graph (i.e. send the actual rendering commands to the GPU)
-pub struct ExecutionContext<'node> {
-    fg: FrameGraph<'node>,
-    allocator: &'node mut FrameGraphAllocator,
-    toposort: Vec<NodeIndex>,
+/// Per-frame parameters (camera, scene, ...) passed to `CompiledFrameGraph::execute`.
+///
+/// This trait is implemented by a `'static` marker type, and maps each frame lifetime `'p`
+/// to the actual parameter type, which can borrow data that only lives during the frame.
+/// The marker is usually the parameter type itself with `'static` lifetimes: for a
+/// `SceneParams<'a>` struct, implement `FrameParams<'p>` for `SceneParams<'static>` with
+/// `type Params = SceneParams<'p>`.
+pub trait FrameParams<'p> {
+    type Params: 'p;
 }
 
-impl<'a> ExecutionContext<'a> {
-    // Should the compiled graph be consumed during the frame? or can it outlive a frame?
-    pub fn new(
-        fg: FrameGraph<'a>,
-        allocator: &'a mut FrameGraphAllocator,
+impl<'p> FrameParams<'p> for () {
+    type Params = ();
+}
+
+/// Type-erased reference to the per-frame parameters.
+///
+/// `'p` is invariant, so that the parameters cannot be retrieved with a shorter lifetime
+/// than the one they were created with.
+struct RawParams<'p> {
+    ptr: *const (),
+    family: TypeId,
+    _lifetime: PhantomData<Cell<&'p ()>>,
+}
+
+impl<'p> RawParams<'p> {
+    fn new<F: FrameParams<'p> + 'static>(params: &'p F::Params) -> RawParams<'p> {
+        RawParams {
+            ptr: params as *const F::Params as *const (),
+            family: TypeId::of::<F>(),
+            _lifetime: PhantomData,
+        }
+    }
+}
+
+/// A frame graph that has been scheduled and allocated, ready to be executed.
+///
+/// A compiled frame graph can be executed any number of times, provided that the allocator
+/// used to compile it is still alive.
+/// It can only be executed with that allocator.
+/// To avoid recompiling the graph every frame, compare `FrameGraph::structural_hash`
+/// of the newly built graph with the hash of the compiled graph (`CompiledFrameGraph::structural_hash`),
+/// and only call `finalize` if they differ.
+pub struct CompiledFrameGraph<'node> {
+    pub(super) fg: FrameGraph<'node>,
+    pub(super) toposort: Vec<NodeIndex>,
+    structural_hash: u64,
+    memory_report: MemoryReport,
+    /// Memory barriers to issue before each pass.
+    barriers: Vec<(NodeIndex, gfx::MemoryBarriers)>,
+    /// Identifier of the allocator that holds the resources of the graph
+    /// (`None` if the graph was only planned).
+    allocator_id: Option<usize>,
+}
+
+impl<'node> CompiledFrameGraph<'node> {
+    pub(super) fn new(
+        fg: FrameGraph<'node>,
         toposort: Vec<NodeIndex>,
-    ) -> ExecutionContext<'a> {
-        ExecutionContext {
+        structural_hash: u64,
+        memory_report: MemoryReport,
+        barriers: Vec<(NodeIndex, gfx::MemoryBarriers)>,
+        allocator_id: Option<usize>,
+    ) -> CompiledFrameGraph<'node> {
+        CompiledFrameGraph {
             fg,
-            allocator,
             toposort,
+            structural_hash,
+            memory_report,
+            barriers,
+            allocator_id,
         }
     }
 
-    // Q: Should execute consume the compiled graph?
-    // => be able to reuse the execution plan from frame to frame
-    pub fn execute(self, frame: &gfx::Frame) {
+    /// Returns the structural hash of the frame graph that was compiled.
+    pub fn structural_hash(&self) -> u64 {
+        self.structural_hash
+    }
+
+    /// Returns the memory used by the transient resources of the graph, with and without aliasing.
+    pub fn memory_report(&self) -> &MemoryReport {
+        &self.memory_report
+    }
+
+    /// Returns the memory barriers issued before passes, in execution order.
+    /// Passes that do not need a barrier are not listed.
+    pub fn barriers(&self) -> Vec<(&str, gfx::MemoryBarriers)> {
+        self.barriers
+            .iter()
+            .map(|&(pass, bits)| (self.fg.pass_name(pass), bits))
+            .collect()
+    }
+
+    /// Returns the memory barriers to issue before the specified pass.
+    pub(super) fn barriers_before(&self, pass: NodeIndex) -> gfx::MemoryBarriers {
+        self.barriers
+            .iter()
+            .find(|&&(p, _)| p == pass)
+            .map_or(gfx::MemoryBarriers::empty(), |&(_, bits)| bits)
+    }
+
+    /// Executes all passes in order.
+    ///
+    /// Panics if `allocator` is not the allocator that was passed to `FrameGraph::finalize`.
+    /// `params` holds per-frame data (camera, scene, ...) that passes can retrieve with
+    /// `ExecutionContext::params::<F>`.
+    pub fn execute<'p, F: FrameParams<'p> + 'static>(
+        &self,
+        frame: &gfx::Frame,
+        allocator: &FrameGraphAllocator,
+        params: &'p F::Params,
+    ) {
+        self.execute_impl(frame, allocator, RawParams::new::<F>(params), None, None)
+    }
+
+    /// Same as `execute`, but also measures the time spent in each pass.
+    /// See `FrameProfiler` for the results.
+    pub fn execute_profiled<'p, F: FrameParams<'p> + 'static>(
+        &self,
+        frame: &gfx::Frame,
+        allocator: &FrameGraphAllocator,
+        params: &'p F::Params,
+        profiler: &mut FrameProfiler,
+    ) {
+        self.execute_impl(
+            frame,
+            allocator,
+            RawParams::new::<F>(params),
+            Some(profiler),
+            None,
+        )
+    }
+
+    /// Same as `execute`, but checks the resources used by each pass against the resources
+    /// that it declared. This is slow, and meant for debugging.
+    ///
+    /// Looking up an undeclared resource through the `ExecutionContext` panics. Undeclared
+    /// resources bound by the commands of a pass are listed in the returned report.
+    pub fn execute_validated<'p, F: FrameParams<'p> + 'static>(
+        &self,
+        frame: &gfx::Frame,
+        allocator: &FrameGraphAllocator,
+        params: &'p F::Params,
+    ) -> ValidationReport {
+        let validator = RefCell::new(validation::Validator::new());
+        self.execute_impl(
+            frame,
+            allocator,
+            RawParams::new::<F>(params),
+            None,
+            Some(&validator),
+        );
+        validator.into_inner().finish()
+    }
+
+    fn execute_impl<'p>(
+        &self,
+        frame: &gfx::Frame,
+        allocator: &FrameGraphAllocator,
+        params: RawParams<'p>,
+        mut profiler: Option<&mut FrameProfiler>,
+        validator: Option<&RefCell<validation::Validator>>,
+    ) {
+        assert!(
+            self.allocator_id.is_some(),
+            "the frame graph was planned, not finalized: it cannot be executed"
+        );
+        assert_eq!(
+            Some(allocator.id),
+            self.allocator_id,
+            "the frame graph was compiled with another allocator"
+        );
+        let ectx = ExecutionContext {
+            fg: &self.fg,
+            allocator,
+            params,
+            validator,
+        };
+        if let Some(ref mut profiler) = profiler {
+            profiler.begin_frame();
+        }
         // Go through the execution plan and call the execute() closure
-        for node in self.toposort.iter() {
-            let node = self.fg.graph.node_weight(*node).unwrap();
+        for (position, &n) in self.toposort.iter().enumerate() {
+            let node = self.fg.graph.node_weight(n).unwrap();
+            if !node.is_pass() {
+                continue;
+            }
+            frame.memory_barrier(self.barriers_before(n));
+            if let Some(ref mut profiler) = profiler {
+                profiler.begin_pass(self.fg.pass_name(n));
+            }
+            if let Some(validator) = validator {
+                validator.borrow_mut().begin_pass(n, position);
+                frame.record_used_objects();
+            }
             match node {
-                &Node::RenderPass {
-                    ref name,
+                &Node::RenderPass { ref callbacks, .. } => {
+                    callbacks.execute(frame, &ectx);
+                }
+                &Node::ComputePass {
+                    ref pipeline,
                     ref callbacks,
+                    ref dispatch,
+                    ref bindings,
+                    ..
                 } => {
-                    callbacks.execute(frame, &self);
+                    let cmd = ectx.begin_dispatch(frame, pipeline, dispatch, bindings);
+                    callbacks.execute(frame, &ectx, cmd);
                 }
-                _ => continue,
+                _ => unreachable!(),
+            }
+            if let Some(validator) = validator {
+                validator
+                    .borrow_mut()
+                    .end_pass(&self.fg, allocator, &frame.take_used_objects());
             }
+            if let Some(ref mut profiler) = profiler {
+                profiler.end_pass();
+            }
+        }
+        self.fg.rotate_history(allocator);
+        if let Some(ref mut profiler) = profiler {
+            profiler.end_frame();
         }
     }
+}
+
+/// Context for evaluating a frame graph (i.e. send the actual rendering commands to the GPU).
+/// Passed to the callbacks of each pass.
+pub struct ExecutionContext<'a, 'p> {
+    pub(super) fg: &'a FrameGraph<'a>,
+    pub(super) allocator: &'a FrameGraphAllocator,
+    params: RawParams<'p>,
+    /// Records resource lookups, in validated executions.
+    validator: Option<&'a RefCell<validation::Validator>>,
+}
+
+impl<'a, 'p> ExecutionContext<'a, 'p> {
+    /// Returns the per-frame parameters passed to `CompiledFrameGraph::execute`.
+    ///
+    /// Panics if the graph was not executed with the same `FrameParams` type `F`.
+    pub fn params<F: FrameParams<'p> + 'static>(&self) -> &'p F::Params {
+        assert!(
+            self.params.family == TypeId::of::<F>(),
+            "unexpected type for the frame graph parameters"
+        );
+        // same family and same (invariant) lifetime: this is the type the pointer was made from
+        unsafe { &*(self.params.ptr as *const F::Params) }
+    }
 
     pub fn texture_resource(&self, res: ResourceVersion) -> gfx::TextureAny {
         let aliasedres = self.aliased_resource(res);
@@ -50,13 +264,42 @@ impl<'a> ExecutionContext<'a> {
         }
     }
 
+    /// Returns the buffer backing a buffer resource.
+    /// The buffer allocation may be larger than the resource: the returned slice
+    /// has the size that was requested when creating the resource.
+    pub fn buffer_resource(&self, res: ResourceVersion) -> gfx::BufferSliceAny {
+        let byte_size = match *self.fg.resource_info(res) {
+            ResourceInfo::Buffer { byte_size } => byte_size,
+            _ => panic!("not a valid buffer resource"),
+        };
+        if let &AliasedResource::Buffer { ref buf } = self.aliased_resource(res) {
+            // the frame graph guarantees that no other resource uses the buffer
+            // during the lifetime of this one
+            unsafe { buf.get_slice(0, byte_size) }
+        } else {
+            panic!("not a valid buffer resource")
+        }
+    }
+
     pub fn aliased_resource(&self, res: ResourceVersion) -> &AliasedResource {
         // fetch node
         // get resource index
         // lookup resource index in allocator.allocations
         let node = self.fg.graph.node_weight(res.0).unwrap();
         if let &Node::Resource { index, .. } = node {
+            if let Some(validator) = self.validator {
+                // only hand out the resources declared by the pass
+                if let Err(access) = validator.borrow_mut().record_access(self.fg, index) {
+                    panic!("{}", access);
+                }
+            }
             let res = &self.fg.resources[index.index()];
+            if let Some(ref imported) = res.imported {
+                return imported;
+            }
+            if let Some(ref slot) = res.history {
+                return self.allocator.history[&slot.key].texture(slot.frames_ago);
+            }
             &self.allocator.allocations[res
                                             .aliased_index
                                             .get()
@@ -0,0 +1,200 @@
+//! Export of compiled frame graphs to DOT (graphviz) and JSON, for debugging and
+//! for diffing the scheduling and aliasing decisions in code review.
+//!
+use super::*;
+use petgraph::visit::EdgeRef;
+use std::io::{self, Write};
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn write_resource_info_json<W: Write>(w: &mut W, info: &ResourceInfo) -> io::Result<()> {
+    match *info {
+        ResourceInfo::Buffer { byte_size } => {
+            write!(w, "{{\"kind\": \"buffer\", \"byte_size\": {}}}", byte_size)
+        }
+        ResourceInfo::Texture { ref desc } => write!(
+            w,
+            "{{\"kind\": \"texture\", \"dimensions\": \"{:?}\", \"format\": \"{:?}\", \"width\": {}, \"height\": {}, \"depth\": {}, \"sample_count\": {}, \"mip_map_count\": \"{:?}\"}}",
+            desc.dimensions,
+            desc.format,
+            desc.width,
+            desc.height,
+            desc.depth,
+            desc.sample_count,
+            desc.mip_map_count
+        ),
+    }
+}
+
+/// Writes the resources read (`Incoming`) or written (`Outgoing`) by a pass.
+fn write_links_json<W: Write>(
+    w: &mut W,
+    fg: &FrameGraph,
+    pass: NodeIndex,
+    dir: Direction,
+) -> io::Result<()> {
+    write!(w, "[")?;
+    for (i, e) in fg.graph.edges_directed(pass, dir).enumerate() {
+        let other = if dir == Direction::Incoming {
+            e.source()
+        } else {
+            e.target()
+        };
+        if let &Node::Resource { index, version } = fg.graph.node_weight(other).unwrap() {
+            if i > 0 {
+                write!(w, ", ")?;
+            }
+            write!(
+                w,
+                "{{\"resource\": \"{}\", \"version\": {}, \"usage\": \"{:?}\"",
+                escape(&fg.resources[index.index()].name),
+                version,
+                e.weight().usage
+            )?;
+            // subresources, only for partial accesses
+            if !e.weight().range.is_all() {
+                write!(w, ", \"range\": \"{}\"", e.weight().range)?;
+            }
+            write!(w, "}}")?;
+        } else {
+            panic!("malformed frame graph")
+        }
+    }
+    write!(w, "]")
+}
+
+impl<'node> CompiledFrameGraph<'node> {
+    /// Writes the scheduled frame graph in the graphviz DOT format.
+    ///
+    /// Passes are drawn as boxes, resource versions as ellipses annotated with
+    /// the lifetime and allocation of the resource. Edges are labeled with the resource usage,
+    /// and with the accessed subresources if the pass does not access the whole resource.
+    pub fn write_dot<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let fg = &self.fg;
+        writeln!(w, "digraph framegraph {{")?;
+        for (order, &n) in self.toposort.iter().enumerate() {
+            match fg.graph.node_weight(n).unwrap() {
+                &Node::RenderPass { ref name, .. } => {
+                    writeln!(
+                        w,
+                        "    n{} [shape=box, label=\"{}\\n#{}\"];",
+                        n.index(),
+                        escape(name),
+                        order
+                    )?;
+                }
+                &Node::ComputePass { ref name, .. } => {
+                    writeln!(
+                        w,
+                        "    n{} [shape=box, style=rounded, label=\"{}\\n#{} (compute)\"];",
+                        n.index(),
+                        escape(name),
+                        order
+                    )?;
+                }
+                &Node::Resource { index, version } => {
+                    let res = &fg.resources[index.index()];
+                    let lifetime = res
+                        .lifetime
+                        .map_or("-".to_owned(), |l| format!("{}-{}", l.begin, l.end));
+                    let alloc = if res.imported.is_some() {
+                        "imported".to_owned()
+                    } else if res.history.is_some() {
+                        "history".to_owned()
+                    } else {
+                        res.aliased_index
+                            .get()
+                            .map_or("unallocated".to_owned(), |i| format!("alloc #{}", i.index()))
+                    };
+                    writeln!(
+                        w,
+                        "    n{} [shape=ellipse, label=\"{} v{}\\n{} [{}]\"];",
+                        n.index(),
+                        escape(&res.name),
+                        version,
+                        alloc,
+                        lifetime
+                    )?;
+                }
+            }
+        }
+        for &n in self.toposort.iter() {
+            for e in fg.graph.edges_directed(n, Direction::Outgoing) {
+                let range = if e.weight().range.is_all() {
+                    String::new()
+                } else {
+                    format!("\\n{}", e.weight().range)
+                };
+                writeln!(
+                    w,
+                    "    n{} -> n{} [label=\"{:?}{}\"];",
+                    e.source().index(),
+                    e.target().index(),
+                    e.weight().usage,
+                    range
+                )?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Writes the passes (in execution order) and resources of the scheduled frame graph as JSON.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let fg = &self.fg;
+
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"passes\": [")?;
+        let mut first = true;
+        for &n in self.toposort.iter() {
+            if fg.graph.node_weight(n).unwrap().is_pass() {
+                let name = fg.pass_name(n);
+                if !first {
+                    writeln!(w, ",")?;
+                }
+                first = false;
+                write!(w, "    {{\"name\": \"{}\", \"inputs\": ", escape(name))?;
+                write_links_json(w, fg, n, Direction::Incoming)?;
+                write!(w, ", \"outputs\": ")?;
+                write_links_json(w, fg, n, Direction::Outgoing)?;
+                write!(w, ", \"barriers\": \"{:?}\"}}", self.barriers_before(n))?;
+            }
+        }
+        writeln!(w)?;
+        writeln!(w, "  ],")?;
+
+        writeln!(w, "  \"resources\": [")?;
+        for (i, res) in fg.resources.iter().enumerate() {
+            write!(w, "    {{\"name\": \"{}\", \"info\": ", escape(&res.name))?;
+            write_resource_info_json(w, &res.info)?;
+            write!(w, ", \"imported\": {}, \"lifetime\": ", res.imported.is_some())?;
+            match res.lifetime {
+                Some(l) => write!(w, "{{\"begin\": {}, \"end\": {}}}", l.begin, l.end)?,
+                None => write!(w, "null")?,
+            }
+            write!(w, ", \"aliased_index\": ")?;
+            match res.aliased_index.get() {
+                Some(index) => write!(w, "{}", index.index())?,
+                None => write!(w, "null")?,
+            }
+            write!(w, "}}")?;
+            if i + 1 < fg.resources.len() {
+                write!(w, ",")?;
+            }
+            writeln!(w)?;
+        }
+        writeln!(w, "  ]")?;
+        writeln!(w, "}}")
+    }
+}
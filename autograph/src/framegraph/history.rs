@@ -0,0 +1,217 @@
+//! History resources: textures that keep their contents across frames, for temporal effects.
+//!
+//! A history texture with N history slots is backed by N+1 textures owned by the allocator:
+//! one for the current frame, and one for each of the N previous frames.
+//! The textures are rotated after each execution of the graph.
+//! Histories are identified by name: a name can only be used by one history texture of a graph,
+//! and the textures of histories that the graph does not declare are released when it is
+//! finalized.
+//!
+use super::*;
+
+/// Links a frame graph resource to the textures of a history resource.
+#[derive(Clone, Debug, Hash)]
+pub(super) struct HistorySlot {
+    /// Name of the history resource, used to look up its textures in the allocator.
+    pub(super) key: String,
+    /// Number of previous frames that are kept.
+    pub(super) len: u32,
+    /// 0 for the contents of the current frame.
+    pub(super) frames_ago: u32,
+}
+
+/// The textures of a history resource, owned by a `FrameGraphAllocator`.
+pub(super) struct HistoryTextures {
+    desc: gfx::TextureDesc,
+    textures: Vec<AliasedResource>,
+    /// Index of the texture of the current frame.
+    current: Cell<usize>,
+    /// Number of previous frames whose contents are valid.
+    valid_frames: Cell<u32>,
+}
+
+impl HistoryTextures {
+    pub(super) fn texture(&self, frames_ago: u32) -> &AliasedResource {
+        let n = self.textures.len();
+        &self.textures[(self.current.get() + n - (frames_ago as usize % n)) % n]
+    }
+
+    pub(super) fn is_valid(&self, frames_ago: u32) -> bool {
+        frames_ago <= self.valid_frames.get()
+    }
+
+    /// Forgets the contents of the previous frames.
+    fn invalidate(&self) {
+        self.valid_frames.set(0);
+    }
+
+    /// Moves to the next frame: the current texture becomes the previous one.
+    fn rotate(&self) {
+        let n = self.textures.len();
+        self.current.set((self.current.get() + 1) % n);
+        self.valid_frames
+            .set(cmp::min(self.valid_frames.get() + 1, n as u32 - 1));
+    }
+}
+
+impl FrameGraphAllocator {
+    /// Creates the textures of a history resource.
+    /// Existing textures are kept if their description did not change; otherwise
+    /// (e.g. when the viewport is resized), the history is lost.
+    fn create_history(&mut self, gctx: &gfx::Context, key: &str, desc: &gfx::TextureDesc, len: u32) {
+        if let Some(history) = self.history.get(key) {
+            if history.desc == *desc && history.textures.len() == len as usize + 1 {
+                return;
+            }
+        }
+        debug!("creating history textures for {} ({} frames)", key, len);
+        self.history.insert(
+            key.to_owned(),
+            HistoryTextures {
+                desc: *desc,
+                textures: (0..len + 1)
+                    .map(|_| AliasedResource::Texture {
+                        tex: gfx::TextureAny::new(gctx, desc),
+                    })
+                    .collect(),
+                current: Cell::new(0),
+                valid_frames: Cell::new(0),
+            },
+        );
+    }
+
+    /// Invalidates the contents of all history resources (e.g. after a camera cut).
+    pub fn reset_history(&self) {
+        for history in self.history.values() {
+            history.invalidate();
+        }
+    }
+}
+
+impl<'node> FrameGraph<'node> {
+    /// Turns a newly created texture resource into a history resource.
+    pub(super) fn make_history(&mut self, res: ResourceVersion, len: u32) {
+        assert!(len >= 1, "history resources need at least one history slot");
+        let (index, _) = self.resource_version(res.0);
+        let resource = &mut self.resources[index.index()];
+        resource.history = Some(HistorySlot {
+            key: resource.name.clone(),
+            len,
+            frames_ago: 0,
+        });
+    }
+
+    /// Creates a resource holding the contents of a history resource `frames_ago` frames ago.
+    pub(super) fn previous_version(&mut self, res: ResourceVersion, frames_ago: u32) -> ResourceVersion {
+        let (index, _) = self.resource_version(res.0);
+        let (name, info, relative_size, slot) = {
+            let resource = &self.resources[index.index()];
+            let slot = resource.history.clone().expect("not a history resource");
+            assert!(
+                frames_ago >= 1 && frames_ago <= slot.len,
+                "history resource `{}` keeps {} frames, cannot read {} frames ago",
+                resource.name,
+                slot.len,
+                frames_ago
+            );
+            (
+                format!("{}[-{}]", resource.name, frames_ago),
+                resource.info,
+                resource.relative_size.clone(),
+                slot,
+            )
+        };
+        let prev = self.add_resource(name.clone(), info, None);
+        let prev_resource = self.resources.last_mut().unwrap();
+        // the name was already prefixed
+        prev_resource.name = name;
+        prev_resource.relative_size = relative_size;
+        prev_resource.history = Some(HistorySlot { frames_ago, ..slot });
+        prev
+    }
+
+    /// Returns whether a resource holds the contents of the current frame of a history resource.
+    pub(super) fn is_current_history(&self, index: UnversionedResourceIndex) -> bool {
+        self.resources[index.index()]
+            .history
+            .as_ref()
+            .map_or(false, |slot| slot.frames_ago == 0)
+    }
+
+    /// Returns an error for each history name that is used by more than one history texture.
+    pub(super) fn check_history_names(&self) -> Vec<Error> {
+        let mut names = HashSet::new();
+        let mut errors = Vec::new();
+        for resource in self.resources.iter() {
+            match resource.history {
+                Some(ref slot) if slot.frames_ago == 0 => {
+                    if !names.insert(&slot.key) {
+                        errors.push(Error::DuplicateHistory {
+                            resource: slot.key.clone(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        errors
+    }
+
+    /// Creates the textures of the history resources used by the graph, and releases the
+    /// textures of the histories that the graph does not declare.
+    pub(super) fn create_history_textures(&self, gctx: &gfx::Context, allocator: &mut FrameGraphAllocator) {
+        let declared = self
+            .resources
+            .iter()
+            .filter_map(|resource| resource.history.as_ref().map(|slot| slot.key.clone()))
+            .collect::<HashSet<_>>();
+        allocator.history.retain(|key, _| {
+            let keep = declared.contains(key);
+            if !keep {
+                debug!("releasing history textures for {}", key);
+            }
+            keep
+        });
+        for resource in self.resources.iter() {
+            if let (&Some(ref slot), Some(_), ResourceInfo::Texture { ref desc }) =
+                (&resource.history, resource.lifetime, resource.info)
+            {
+                allocator.create_history(gctx, &slot.key, desc, slot.len);
+            }
+        }
+    }
+
+    /// Rotates the textures of the history resources written by the graph.
+    /// Histories that were not written during this frame (their pass was culled or disabled,
+    /// or they belong to another graph) lose their previous contents.
+    pub(super) fn rotate_history(&self, allocator: &FrameGraphAllocator) {
+        let mut written = HashSet::new();
+        for resource in self.resources.iter() {
+            match resource.history {
+                Some(ref slot) if slot.frames_ago == 0 && resource.lifetime.is_some() => {
+                    allocator.history[&slot.key].rotate();
+                    written.insert(&slot.key);
+                }
+                _ => {}
+            }
+        }
+        for (key, history) in allocator.history.iter() {
+            if !written.contains(key) {
+                history.invalidate();
+            }
+        }
+    }
+}
+
+impl<'a, 'p> ExecutionContext<'a, 'p> {
+    /// Returns whether a resource returned by `RenderPassBuilder::read_previous` holds
+    /// valid contents. This is not the case during the first frames, and after the
+    /// history was invalidated (resized, or reset with `FrameGraphAllocator::reset_history`).
+    pub fn is_history_valid(&self, res: ResourceVersion) -> bool {
+        let (index, _) = self.fg.resource_version(res.0);
+        match self.fg.resources[index.index()].history {
+            Some(ref slot) => self.allocator.history[&slot.key].is_valid(slot.frames_ago),
+            None => panic!("not a history resource"),
+        }
+    }
+}
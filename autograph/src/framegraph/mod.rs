@@ -3,28 +3,55 @@
 //!
 use gfx;
 use petgraph::graph::*;
+use petgraph::stable_graph::StableGraph;
+use petgraph::visit::EdgeRef;
 use petgraph::*;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+mod aliasing;
+mod barrier;
+mod compute;
+mod description;
 mod execution;
-pub use self::execution::ExecutionContext;
+mod export;
+mod history;
+mod size;
+mod subgraph;
+mod subresource;
+mod timing;
+mod validation;
+pub use self::aliasing::{resource_byte_size, AliasingStrategy, AllocationRequest, BestFit,
+                         FirstFit, IntervalColoring, MemoryReport};
+pub use self::compute::{ComputePassBuilder, ComputePassCallbacks, DispatchSize};
+pub use self::description::{AccessDescription, GraphDescription, LoadError, PassDescription,
+                             PassFactory, PassRegistry, PassResources, ResourceDescription};
+pub use self::execution::{CompiledFrameGraph, ExecutionContext, FrameParams};
+pub use self::size::RelativeSize;
+pub use self::subgraph::SubGraph;
+pub use self::subresource::SubresourceRange;
+pub use self::timing::{FrameProfiler, FrameReport, PassAverage, PassTiming};
+pub use self::validation::{UndeclaredAccess, UnusedInput, ValidationReport};
 
 /// Lifetime of a frame graph resource.
-/// TODO document
-#[derive(Copy, Clone, Debug)]
-struct Lifetime {
-    begin: i32,
-    end: i32, // inclusive
+/// `begin` and `end` are positions in the execution order of the graph.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Lifetime {
+    pub begin: i32,
+    pub end: i32, // inclusive
 }
 
 impl Lifetime {
-    fn overlaps(&self, other: &Lifetime) -> bool {
+    pub fn overlaps(&self, other: &Lifetime) -> bool {
         (self.begin <= other.end) && (other.begin <= self.end)
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
 pub enum ResourceUsage {
     Default,
     RWImage,
@@ -36,6 +63,7 @@ pub enum ResourceUsage {
 }
 
 /// Describes creation details of a frame graph resource.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 pub enum ResourceInfo {
     Buffer { byte_size: usize },
     Texture { desc: gfx::TextureDesc },
@@ -46,9 +74,28 @@ struct UnversionedResource {
     lifetime: Option<Lifetime>,
     name: String,
     info: ResourceInfo,
+    /// The external object backing the resource, if it was imported into the graph.
+    /// Imported resources are never aliased, and are not released by the frame graph.
+    imported: Option<AliasedResource>,
+    /// For textures whose size depends on a viewport: the size in `info` is resolved
+    /// when the graph is finalized.
+    relative_size: Option<size::ViewportRelativeSize>,
+    /// For history resources: the textures backing the resource are kept across frames
+    /// by the allocator.
+    history: Option<history::HistorySlot>,
     aliased_index: Cell<Option<AliasedResourceIndex>>,
 }
 
+impl UnversionedResource {
+    /// Returns whether the contents of the resource exist before the first pass that uses it.
+    fn has_initial_contents(&self) -> bool {
+        self.imported.is_some() || self
+            .history
+            .as_ref()
+            .map_or(false, |slot| slot.frames_ago > 0)
+    }
+}
+
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
 struct UnversionedResourceIndex(u32);
 // TODO we don't need this
@@ -66,14 +113,30 @@ pub trait RenderPassCallbacks {
     fn execute(&self, frame: &gfx::Frame, ectx: &ExecutionContext);
 }
 
+impl<F> RenderPassCallbacks for F
+where
+    F: Fn(&gfx::Frame, &ExecutionContext),
+{
+    fn execute(&self, frame: &gfx::Frame, ectx: &ExecutionContext) {
+        self(frame, ectx)
+    }
+}
+
 /// A node of the frame graph.
-/// Can be either a `Pass` or a `Resource`.
+/// Can be either a pass (`RenderPass` or `ComputePass`) or a `Resource`.
 /// Passes can only be connected to Resources and vice-versa.
 enum Node<'a> {
     RenderPass {
         name: String,
         callbacks: Box<RenderPassCallbacks + 'a>,
     },
+    ComputePass {
+        name: String,
+        pipeline: gfx::ComputePipeline,
+        callbacks: Box<ComputePassCallbacks + 'a>,
+        dispatch: DispatchSize,
+        bindings: Vec<compute::ComputeBinding>,
+    },
     Resource {
         index: UnversionedResourceIndex,
         // lifetime is bound to the framegraph
@@ -82,9 +145,23 @@ enum Node<'a> {
 }
 
 impl<'a> ::std::fmt::Debug for Node<'a> {
-    fn fmt(&self, _formatter: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        // TODO
-        Ok(())
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        match *self {
+            Node::RenderPass { ref name, .. } => write!(formatter, "RenderPass({})", name),
+            Node::ComputePass { ref name, .. } => write!(formatter, "ComputePass({})", name),
+            Node::Resource { index, version } => {
+                write!(formatter, "Resource(#{}, v{})", index.index(), version)
+            }
+        }
+    }
+}
+
+impl<'a> Node<'a> {
+    fn is_pass(&self) -> bool {
+        match *self {
+            Node::RenderPass { .. } | Node::ComputePass { .. } => true,
+            Node::Resource { .. } => false,
+        }
     }
 }
 
@@ -100,7 +177,17 @@ pub struct RenderPass(NodeIndex);
 #[derive(Copy, Clone, Debug)]
 struct Edge {
     usage: ResourceUsage,
-    //access: ResourceAccess
+    /// The part of the resource that is accessed.
+    range: SubresourceRange,
+    access: ResourceAccess,
+}
+
+/// Whether a pass reads a resource version, or modifies it (producing a new version).
+/// Outputs of a pass are always `Write`.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+enum ResourceAccess {
+    Read,
+    Write,
 }
 
 /// An AliasedResource represents the GPU memory allocated for a frame graph resource.
@@ -111,6 +198,18 @@ pub enum AliasedResource {
     Texture { tex: gfx::TextureAny },
 }
 
+impl AliasedResource {
+    /// Returns the description of the allocation.
+    pub fn info(&self) -> ResourceInfo {
+        match *self {
+            AliasedResource::Buffer { ref buf } => ResourceInfo::Buffer {
+                byte_size: buf.byte_size(),
+            },
+            AliasedResource::Texture { ref tex } => ResourceInfo::Texture { desc: *tex.desc() },
+        }
+    }
+}
+
 #[derive(Copy, Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct AliasedResourceIndex(u32);
 impl AliasedResourceIndex {
@@ -122,6 +221,14 @@ impl AliasedResourceIndex {
     }
 }
 
+/// Size of the smallest buffer pool.
+const MIN_BUFFER_BUCKET_SIZE: usize = 256;
+
+/// Returns the size of the pooled allocation used for a buffer resource of the specified size.
+fn buffer_bucket_size(byte_size: usize) -> usize {
+    cmp::max(byte_size, MIN_BUFFER_BUCKET_SIZE).next_power_of_two()
+}
+
 const FRAMEBUFFER_CACHE_KEY_NUM_COLOR_ATTACHEMENTS: usize = 8;
 
 /// Key used to lookup an existing framebuffer in the cache
@@ -132,17 +239,41 @@ struct FramebufferCacheKey {
     depth_attachement: Option<AliasedResourceIndex>,
 }
 
+static NEXT_ALLOCATOR_ID: AtomicUsize = AtomicUsize::new(0);
+
 /// Holds Allocs for a frame graph
 pub struct FrameGraphAllocator {
+    /// Unique identifier, checked when executing a graph compiled with this allocator.
+    id: usize,
     allocations: Vec<AliasedResource>,
     fbcache: RefCell<HashMap<FramebufferCacheKey, gfx::Framebuffer>>,
+    /// Textures of history resources, by name.
+    history: HashMap<String, history::HistoryTextures>,
 }
 
 impl FrameGraphAllocator {
     pub fn new() -> FrameGraphAllocator {
         FrameGraphAllocator {
+            id: NEXT_ALLOCATOR_ID.fetch_add(1, Ordering::Relaxed),
             allocations: Vec::new(),
             fbcache: RefCell::new(HashMap::new()),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Creates the GPU resources for the allocations that were added to `allocations`
+    /// during the compilation of a frame graph.
+    fn create_allocations(&mut self, gctx: &gfx::Context, allocations: &[ResourceInfo]) {
+        let first_new = self.allocations.len();
+        for info in allocations[first_new..].iter() {
+            self.allocations.push(match *info {
+                ResourceInfo::Texture { ref desc } => AliasedResource::Texture {
+                    tex: gfx::TextureAny::new(gctx, desc),
+                },
+                ResourceInfo::Buffer { byte_size } => AliasedResource::Buffer {
+                    buf: gfx::BufferAny::new(gctx, byte_size, gfx::BufferUsage::UPLOAD),
+                },
+            });
         }
     }
 
@@ -196,21 +327,36 @@ impl FrameGraphAllocator {
 }
 
 /// Pass builder
-pub struct RenderPassBuilder<'fg> {
+pub struct RenderPassBuilder<'fg, 'node: 'fg> {
     pass: RenderPass,
-    framegraph: &'fg mut FrameGraph<'fg>,
+    framegraph: &'fg mut FrameGraph<'node>,
 }
 
-impl<'fg> RenderPassBuilder<'fg> {
+impl<'fg, 'node> RenderPassBuilder<'fg, 'node> {
     pub fn read(&mut self, res: ResourceVersion, usage: ResourceUsage) {
-        self.framegraph.link_input(self.pass, res, usage)
+        self.read_range(res, usage, SubresourceRange::all())
     }
 
     pub fn write(&mut self, res: ResourceVersion, usage: ResourceUsage) -> ResourceVersion {
-        let res_v2 = self.framegraph.clone_resource(res);
-        self.framegraph.link_input(self.pass, res, usage);
-        self.framegraph.link_output(self.pass, res_v2, usage);
-        res_v2
+        self.write_range(res, usage, SubresourceRange::all())
+    }
+
+    /// Reads only some mip levels or layers of a texture.
+    pub fn read_range(&mut self, res: ResourceVersion, usage: ResourceUsage, range: SubresourceRange) {
+        self.framegraph.link_read(self.pass, res, usage, range)
+    }
+
+    /// Modifies only some mip levels or layers of a texture.
+    /// The other subresources of the new version keep the contents of `res`.
+    /// Like with `write`, only one pass can modify a version: passes that modify other
+    /// subresources must write to the returned version.
+    pub fn write_range(
+        &mut self,
+        res: ResourceVersion,
+        usage: ResourceUsage,
+        range: SubresourceRange,
+    ) -> ResourceVersion {
+        self.framegraph.link_write(self.pass, res, usage, range)
     }
 
     pub fn create_texture<S: Into<String>>(
@@ -239,6 +385,77 @@ impl<'fg> RenderPassBuilder<'fg> {
         res
     }
 
+    /// Creates a texture with a size relative to a viewport or another texture.
+    /// The width and height of `desc` are ignored.
+    pub fn create_texture_relative<S: Into<String>>(
+        &mut self,
+        name: S,
+        desc: &gfx::TextureDesc,
+        size: RelativeSize,
+        usage: ResourceUsage,
+    ) -> ResourceVersion {
+        let res = self
+            .framegraph
+            .create_relative_texture(name.into(), desc, size);
+        self.framegraph.link_output(self.pass, res, usage);
+        res
+    }
+
+    /// Creates a history texture, whose contents are kept for `history_len` frames.
+    /// Read the contents of previous frames with `read_previous`.
+    /// The name of a history texture must be unique.
+    pub fn create_history_texture<S: Into<String>>(
+        &mut self,
+        name: S,
+        desc: &gfx::TextureDesc,
+        history_len: u32,
+        usage: ResourceUsage,
+    ) -> ResourceVersion {
+        let res = self
+            .framegraph
+            .create_resource(name.into(), ResourceInfo::Texture { desc: *desc });
+        self.framegraph.make_history(res, history_len);
+        self.framegraph.link_output(self.pass, res, usage);
+        res
+    }
+
+    /// Creates a history texture with a size relative to a viewport or another texture.
+    /// The history is lost when the size changes.
+    pub fn create_history_texture_relative<S: Into<String>>(
+        &mut self,
+        name: S,
+        desc: &gfx::TextureDesc,
+        size: RelativeSize,
+        history_len: u32,
+        usage: ResourceUsage,
+    ) -> ResourceVersion {
+        let res = self
+            .framegraph
+            .create_relative_texture(name.into(), desc, size);
+        self.framegraph.make_history(res, history_len);
+        self.framegraph.link_output(self.pass, res, usage);
+        res
+    }
+
+    /// Samples the contents that a history texture had `frames_ago` frames ago
+    /// (between 1 and the number of history slots).
+    /// Returns the resource to use in the callbacks. Its contents are undefined until
+    /// enough frames have been rendered: check `ExecutionContext::is_history_valid`.
+    pub fn read_previous(&mut self, res: ResourceVersion, frames_ago: u32) -> ResourceVersion {
+        let prev = self.framegraph.previous_version(res, frames_ago);
+        self.read(prev, ResourceUsage::SampledImage);
+        prev
+    }
+
+    /// Runs the pass only if `predicate` returns true when the graph is compiled.
+    ///
+    /// A disabled pass is removed before culling: passes that read a version it modifies
+    /// read the previous version instead.
+    pub fn enable_if<P: Fn() -> bool + 'node>(&mut self, predicate: P) {
+        self.framegraph
+            .set_pass_condition(self.pass, Box::new(predicate));
+    }
+
     pub fn build(self) -> RenderPass {
         self.pass
     }
@@ -249,18 +466,73 @@ impl<'fg> RenderPassBuilder<'fg> {
 /// TODO document
 pub struct FrameGraph<'node> {
     resources: Vec<UnversionedResource>,
-    graph: Graph<Node<'node>, Edge, Directed>,
+    // StableGraph so that culled nodes can be removed without invalidating
+    // the ResourceVersion and RenderPass handles held by the user.
+    graph: StableGraph<Node<'node>, Edge, Directed>,
+    /// Resources that must be produced by the graph (see `mark_output`).
+    outputs: Vec<ResourceVersion>,
+    aliasing_strategy: Box<AliasingStrategy>,
+    /// Sizes of the viewports, by name (see `set_viewport`).
+    viewports: HashMap<String, (u32, u32)>,
+    /// Prefixes of the sub-graphs being instantiated (see `instantiate`).
+    name_prefixes: Vec<String>,
+    /// Predicates of conditional passes (see `RenderPassBuilder::enable_if`).
+    pass_conditions: HashMap<NodeIndex, subgraph::PassCondition<'node>>,
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Errors detected when compiling a frame graph.
+#[derive(Clone, Debug)]
 pub enum Error {
-    ConcurrentWriteHazard, // TODO more info
+    /// A version of a resource is modified by a pass while other passes read it:
+    /// since there is no ordering between the readers and the writer, readers may
+    /// see the modified contents.
+    ConcurrentWriteHazard {
+        resource: String,
+        version: i32,
+        readers: Vec<String>,
+        writers: Vec<String>,
+    },
+    /// A pass reads a version of a resource that is not produced by any pass
+    /// (and that was not imported).
+    UnproducedResourceRead {
+        resource: String,
+        version: i32,
+        pass: String,
+    },
+    /// A pass writes to a version of a resource that was already modified by another pass.
+    /// The pass should write to the newer version instead.
+    StaleResourceWrite {
+        resource: String,
+        version: i32,
+        pass: String,
+        superseded_by: String,
+    },
+    /// A pass uses a resource with a usage that does not match the kind of resource
+    /// (e.g. a texture used as an uniform buffer), or with conflicting usages.
+    IncompatibleResourceUsage {
+        resource: String,
+        version: i32,
+        pass: String,
+        usages: Vec<ResourceUsage>,
+    },
+    /// The size of a texture is relative to a viewport whose size was not set.
+    UnknownViewport { resource: String, viewport: String },
+    /// Two history textures have the same name.
+    DuplicateHistory { resource: String },
+    /// Several errors were detected in the same graph.
+    Multiple(Vec<Error>),
 }
 
 impl ::std::error::Error for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::ConcurrentWriteHazard => "concurrent write hazard detected",
+            Error::ConcurrentWriteHazard { .. } => "concurrent write hazard detected",
+            Error::UnproducedResourceRead { .. } => "read of a resource that is never produced",
+            Error::StaleResourceWrite { .. } => "write to a stale version of a resource",
+            Error::IncompatibleResourceUsage { .. } => "incompatible resource usage",
+            Error::UnknownViewport { .. } => "texture size relative to an unknown viewport",
+            Error::DuplicateHistory { .. } => "duplicate history texture name",
+            Error::Multiple(_) => "multiple frame graph errors",
         }
     }
 }
@@ -268,11 +540,82 @@ impl ::std::error::Error for Error {
 impl ::std::fmt::Display for Error {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         match *self {
-            Error::ConcurrentWriteHazard => {
-                write!(f, "concurrent write hazard detected");
+            Error::ConcurrentWriteHazard {
+                ref resource,
+                version,
+                ref readers,
+                ref writers,
+            } => write!(
+                f,
+                "concurrent write hazard detected on resource `{}` (version {}): readers {:?}, writers {:?}",
+                resource, version, readers, writers
+            ),
+            Error::UnproducedResourceRead {
+                ref resource,
+                version,
+                ref pass,
+            } => write!(
+                f,
+                "pass `{}` reads version {} of resource `{}`, which is not produced by any pass",
+                pass, version, resource
+            ),
+            Error::StaleResourceWrite {
+                ref resource,
+                version,
+                ref pass,
+                ref superseded_by,
+            } => write!(
+                f,
+                "pass `{}` writes to version {} of resource `{}`, which was already modified by pass `{}`",
+                pass, version, resource, superseded_by
+            ),
+            Error::IncompatibleResourceUsage {
+                ref resource,
+                version,
+                ref pass,
+                ref usages,
+            } => write!(
+                f,
+                "pass `{}` uses version {} of resource `{}` with incompatible usages {:?}",
+                pass, version, resource, usages
+            ),
+            Error::UnknownViewport {
+                ref resource,
+                ref viewport,
+            } => write!(
+                f,
+                "the size of resource `{}` is relative to viewport `{}`, whose size was not set",
+                resource, viewport
+            ),
+            Error::DuplicateHistory { ref resource } => write!(
+                f,
+                "history texture name `{}` is used by more than one history texture",
+                resource
+            ),
+            Error::Multiple(ref errors) => {
+                write!(f, "{} errors:", errors.len())?;
+                for err in errors.iter() {
+                    write!(f, "\n{}", err)?;
+                }
+                Ok(())
             }
         }
-        Ok(())
+    }
+}
+
+impl ResourceUsage {
+    /// Returns whether a resource of the given kind can be used with this usage.
+    fn is_compatible_with(&self, info: &ResourceInfo) -> bool {
+        match (*self, *info) {
+            (ResourceUsage::Default, _) => true,
+            (ResourceUsage::RWImage, ResourceInfo::Texture { .. })
+            | (ResourceUsage::SampledImage, ResourceInfo::Texture { .. })
+            | (ResourceUsage::RenderTarget, ResourceInfo::Texture { .. }) => true,
+            (ResourceUsage::UniformBuffer, ResourceInfo::Buffer { .. })
+            | (ResourceUsage::ShaderStorageBuffer, ResourceInfo::Buffer { .. })
+            | (ResourceUsage::TransformFeedbackOutput, ResourceInfo::Buffer { .. }) => true,
+            _ => false,
+        }
     }
 }
 
@@ -281,7 +624,12 @@ impl<'node> FrameGraph<'node> {
     pub fn new() -> FrameGraph<'node> {
         FrameGraph {
             resources: Vec::new(),
-            graph: Graph::new(),
+            graph: StableGraph::new(),
+            outputs: Vec::new(),
+            aliasing_strategy: Box::new(FirstFit),
+            viewports: HashMap::new(),
+            name_prefixes: Vec::new(),
+            pass_conditions: HashMap::new(),
         }
     }
 
@@ -291,18 +639,19 @@ impl<'node> FrameGraph<'node> {
         name: String,
         callbacks: Box<RenderPassCallbacks + 'node>,
     ) -> RenderPass {
+        let name = self.prefixed_name(name);
         RenderPass(self.graph.add_node(Node::RenderPass {
-            name: name.into(),
+            name,
             callbacks,
         }))
     }
 
     /// Create a renderpassbuilder.
-    fn create_render_pass<'fg: 'node, S: Into<String>, C: RenderPassCallbacks + 'fg>(
+    pub fn create_render_pass<'fg, S: Into<String>, C: RenderPassCallbacks + 'node>(
         &'fg mut self,
         name: S,
         callbacks: C,
-    ) -> RenderPassBuilder<'fg> {
+    ) -> RenderPassBuilder<'fg, 'node> {
         let pass = self.create_render_pass_node(name.into(), Box::new(callbacks));
         RenderPassBuilder {
             framegraph: self,
@@ -315,12 +664,52 @@ impl<'node> FrameGraph<'node> {
         &mut self,
         name: S,
         create_info: ResourceInfo,
+    ) -> ResourceVersion {
+        self.add_resource(name.into(), create_info, None)
+    }
+
+    /// Imports an existing texture (e.g. a persistent render target) into the frame graph.
+    /// The returned resource version has no producer: passes can read from it and write to it
+    /// like any other resource. The frame graph never aliases nor frees imported resources.
+    pub fn import_texture<S: Into<String>>(
+        &mut self,
+        name: S,
+        tex: &gfx::TextureAny,
+    ) -> ResourceVersion {
+        self.add_resource(
+            name.into(),
+            ResourceInfo::Texture { desc: *tex.desc() },
+            Some(AliasedResource::Texture { tex: tex.clone() }),
+        )
+    }
+
+    /// Imports an existing buffer into the frame graph.
+    /// See `import_texture`.
+    pub fn import_buffer<S: Into<String>>(&mut self, name: S, buf: &gfx::BufferAny) -> ResourceVersion {
+        self.add_resource(
+            name.into(),
+            ResourceInfo::Buffer {
+                byte_size: buf.byte_size(),
+            },
+            Some(AliasedResource::Buffer { buf: buf.clone() }),
+        )
+    }
+
+    fn add_resource(
+        &mut self,
+        name: String,
+        info: ResourceInfo,
+        imported: Option<AliasedResource>,
     ) -> ResourceVersion {
         // Create a new resource
+        let name = self.prefixed_name(name);
         self.resources.push(UnversionedResource {
-            name: name.into(),
+            name,
             lifetime: None,
-            info: create_info,
+            info,
+            imported,
+            relative_size: None,
+            history: None,
             aliased_index: Cell::new(None),
         });
         let rindex = UnversionedResourceIndex::new(self.resources.len() - 1);
@@ -332,6 +721,7 @@ impl<'node> FrameGraph<'node> {
     }
 
     /// Gets the `ResourceInfo` for the specified resource node.
+    /// The size of textures created with a `RelativeSize` is only known once the graph is finalized.
     pub fn resource_info(&self, res: ResourceVersion) -> &ResourceInfo {
         self.graph
             .node_weight(res.0)
@@ -345,6 +735,22 @@ impl<'node> FrameGraph<'node> {
             .unwrap()
     }
 
+    /// Sets the strategy used to decide which resources share the same allocation.
+    /// The default is `FirstFit`.
+    /// Note that the strategy is not part of the structural hash of the graph.
+    pub fn set_aliasing_strategy<S: AliasingStrategy + 'static>(&mut self, strategy: S) {
+        self.aliasing_strategy = Box::new(strategy);
+    }
+
+    /// Marks a resource version as an output of the frame graph.
+    ///
+    /// As soon as one output is marked, `finalize` culls all passes that do not contribute
+    /// (directly or indirectly) to a marked output, along with the resources that they use.
+    /// If no output is marked, all passes are kept.
+    pub fn mark_output(&mut self, res: ResourceVersion) {
+        self.outputs.push(res);
+    }
+
     /// Clones a resource node and increase its version index.
     pub fn clone_resource(&mut self, res: ResourceVersion) -> ResourceVersion {
         let (index, version) = {
@@ -361,32 +767,193 @@ impl<'node> FrameGraph<'node> {
         }))
     }
 
+    /// Returns the resource and version of a resource node.
+    fn resource_version(&self, n: NodeIndex) -> (UnversionedResourceIndex, i32) {
+        match *self.graph.node_weight(n).unwrap() {
+            Node::Resource { index, version } => (index, version),
+            _ => panic!("not a resource node"),
+        }
+    }
+
     /// Adds an input to a pass node.
-    fn link_input(&mut self, pass: RenderPass, input: ResourceVersion, usage: ResourceUsage) {
-        self.graph.add_edge(input.0, pass.0, Edge { usage });
+    fn link_read(
+        &mut self,
+        pass: RenderPass,
+        input: ResourceVersion,
+        usage: ResourceUsage,
+        range: SubresourceRange,
+    ) {
+        self.graph.add_edge(
+            input.0,
+            pass.0,
+            Edge {
+                usage,
+                range,
+                access: ResourceAccess::Read,
+            },
+        );
+    }
+
+    /// Links a pass to the resource version it modifies, and to the new version that it produces.
+    /// Returns the new version.
+    fn link_write(
+        &mut self,
+        pass: RenderPass,
+        input: ResourceVersion,
+        usage: ResourceUsage,
+        range: SubresourceRange,
+    ) -> ResourceVersion {
+        let output = self.clone_resource(input);
+        let edge = Edge {
+            usage,
+            range,
+            access: ResourceAccess::Write,
+        };
+        self.graph.add_edge(input.0, pass.0, edge);
+        self.graph.add_edge(pass.0, output.0, edge);
+        output
     }
 
     /// Adds an output to a pass node.
     fn link_output(&mut self, pass: RenderPass, output: ResourceVersion, usage: ResourceUsage) {
-        self.graph.add_edge(pass.0, output.0, Edge { usage });
+        self.graph.add_edge(
+            pass.0,
+            output.0,
+            Edge {
+                usage,
+                range: SubresourceRange::all(),
+                access: ResourceAccess::Write,
+            },
+        );
     }
 
-    /// Returns true if the pass modifies a version of the specfied resource
-    fn is_resource_modified_by_pass(
-        &self,
-        pass: RenderPass,
-        rindex: UnversionedResourceIndex,
-    ) -> bool {
-        // For all outgoing neighbors
-        self.graph.neighbors_directed(pass.0, Direction::Outgoing)
-            // Return true if any...
-            .any(|n| match self.graph.node_weight(n).unwrap() {
-                // ... outgoing neighbor is a node pointing to the same resource
-                // (i.e. the pass writes to the node)
-                &Node::Resource { index, .. } if index == rindex => true,
-                &Node::RenderPass { .. } => panic!("malformed frame graph"),
-                _ => false
+    /// Removes the passes that do not contribute to the outputs of the graph,
+    /// and the resource versions that are not used by the remaining passes.
+    /// Does nothing if no outputs were marked.
+    fn cull_unused_nodes(&mut self) {
+        if self.outputs.is_empty() {
+            return;
+        }
+
+        // walk the graph backwards, starting from the outputs, and from history resources
+        // (which are read by the next frames)
+        let mut live = HashSet::new();
+        let mut stack: Vec<NodeIndex> = self.outputs.iter().map(|res| res.0).collect();
+        stack.extend(self.graph.node_indices().filter(|&n| {
+            match *self.graph.node_weight(n).unwrap() {
+                Node::Resource { index, .. } => self.is_current_history(index),
+                _ => false,
+            }
+        }));
+        while let Some(n) = stack.pop() {
+            if live.insert(n) {
+                stack.extend(self.graph.neighbors_directed(n, Direction::Incoming));
+            }
+        }
+
+        // A live pass still writes to all of its outputs, even those that nobody reads:
+        // keep them so that they are allocated.
+        let extra_outputs: Vec<NodeIndex> = live
+            .iter()
+            .filter(|&&n| self.graph.node_weight(n).unwrap().is_pass())
+            .flat_map(|&n| self.graph.neighbors_directed(n, Direction::Outgoing))
+            .collect();
+        live.extend(extra_outputs);
+
+        // Versions bypassed by disabled passes have no links anymore, but the handles to them
+        // are still used to access the resource: keep them.
+        let bypassed: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|&n| {
+                !self.graph.node_weight(n).unwrap().is_pass()
+                    && self.graph.neighbors_undirected(n).next().is_none()
             })
+            .collect();
+        live.extend(bypassed);
+
+        let dead: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|n| !live.contains(n))
+            .collect();
+        for n in dead {
+            match self.graph.remove_node(n) {
+                Some(Node::RenderPass { name, .. }) | Some(Node::ComputePass { name, .. }) => {
+                    debug!("culled pass {}", name)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Computes a hash of the structure of the graph: passes, resources (with their descriptions),
+    /// imported objects, and the links between them.
+    /// Two frame graphs with the same structural hash produce the same compiled frame graph,
+    /// so that a `CompiledFrameGraph` can be reused instead of finalizing the new graph.
+    /// Pass callbacks are not taken into account, but the current value of the predicates
+    /// of conditional passes is.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for n in self.graph.node_indices() {
+            n.index().hash(&mut hasher);
+            match self.graph.node_weight(n).unwrap() {
+                &Node::RenderPass { ref name, .. } => {
+                    0u8.hash(&mut hasher);
+                    name.hash(&mut hasher);
+                    self.is_pass_enabled(n).hash(&mut hasher);
+                }
+                &Node::Resource { index, version } => {
+                    1u8.hash(&mut hasher);
+                    index.hash(&mut hasher);
+                    version.hash(&mut hasher);
+                }
+                &Node::ComputePass {
+                    ref name,
+                    ref pipeline,
+                    ref dispatch,
+                    ref bindings,
+                    ..
+                } => {
+                    2u8.hash(&mut hasher);
+                    name.hash(&mut hasher);
+                    // like imported resources, the pipeline object is kept by the compiled graph
+                    (&***pipeline as *const _ as usize).hash(&mut hasher);
+                    dispatch.hash(&mut hasher);
+                    bindings.hash(&mut hasher);
+                    self.is_pass_enabled(n).hash(&mut hasher);
+                }
+            }
+        }
+        for e in self.graph.edge_indices() {
+            let (src, dst) = self.graph.edge_endpoints(e).unwrap();
+            src.index().hash(&mut hasher);
+            dst.index().hash(&mut hasher);
+            let edge = self.graph.edge_weight(e).unwrap();
+            edge.usage.hash(&mut hasher);
+            edge.range.hash(&mut hasher);
+            edge.access.hash(&mut hasher);
+        }
+        for (i, r) in self.resources.iter().enumerate() {
+            r.name.hash(&mut hasher);
+            // resizing a viewport changes the description of the resources that depend on it
+            match self.resolve_resource_info(UnversionedResourceIndex::new(i)) {
+                Ok(info) => info.hash(&mut hasher),
+                Err(_) => r.relative_size.hash(&mut hasher),
+            }
+            r.history.hash(&mut hasher);
+            // the compiled graph keeps a reference to imported objects:
+            // importing a different object must invalidate it
+            match r.imported {
+                Some(AliasedResource::Texture { ref tex }) => tex.gl_object().hash(&mut hasher),
+                Some(AliasedResource::Buffer { ref buf }) => buf.gl_object().hash(&mut hasher),
+                None => {}
+            }
+        }
+        for out in self.outputs.iter() {
+            out.0.index().hash(&mut hasher);
+        }
+        hasher.finish()
     }
 
     ///
@@ -396,32 +963,149 @@ impl<'node> FrameGraph<'node> {
         toposort(&self.graph, None).expect("Frame graph contains cycles (how is that possible?)")
     }
 
-    fn detect_concurrent_write_hazards(&self, toposort: &Vec<NodeIndex>) -> bool {
-        let mut has_write_hazards = false;
+    /// Returns the name of a pass node.
+    fn pass_name(&self, pass: NodeIndex) -> &str {
+        match self.graph.node_weight(pass).unwrap() {
+            &Node::RenderPass { ref name, .. } | &Node::ComputePass { ref name, .. } => name,
+            _ => panic!("not a pass node"),
+        }
+    }
+
+    /// Checks that resources are accessed correctly by passes.
+    /// Returns all errors found.
+    fn check_resource_accesses(&self, toposort: &Vec<NodeIndex>) -> Vec<Error> {
+        let mut errors = Vec::new();
+
         for &n in toposort.iter() {
-            if let &Node::Resource { index, .. } = self.graph.node_weight(n).unwrap() {
-                //let mut write_count = 0;
-                let mut writers = Vec::new();
-                //let mut read_count = 0;
-                let mut readers = Vec::new();
-                for pass in self.graph.neighbors_directed(n, Direction::Outgoing) {
-                    if self.is_resource_modified_by_pass(RenderPass(pass), index) {
-                        writers.push(pass);
-                    } else {
-                        readers.push(pass);
+            let (index, version) = match self.graph.node_weight(n).unwrap() {
+                &Node::Resource { index, version } => (index, version),
+                _ => continue,
+            };
+            let resource = &self.resources[index.index()];
+
+            // group the links to this resource version by pass
+            let mut producers = Vec::new();
+            let mut consumers: Vec<(NodeIndex, Vec<Edge>)> = Vec::new();
+            for e in self.graph.edges_directed(n, Direction::Incoming) {
+                producers.push((e.source(), e.weight().usage));
+            }
+            for e in self.graph.edges_directed(n, Direction::Outgoing) {
+                let pass = e.target();
+                match consumers.iter().position(|&(p, _)| p == pass) {
+                    Some(i) => consumers[i].1.push(*e.weight()),
+                    None => consumers.push((pass, vec![*e.weight()])),
+                }
+            }
+
+            // usages must match the kind of resource, and a pass cannot use the same
+            // subresources in two different ways
+            for &(pass, usage) in producers.iter() {
+                if !usage.is_compatible_with(&resource.info) {
+                    errors.push(Error::IncompatibleResourceUsage {
+                        resource: resource.name.clone(),
+                        version,
+                        pass: self.pass_name(pass).to_owned(),
+                        usages: vec![usage],
+                    });
+                }
+            }
+            for &(pass, ref edges) in consumers.iter() {
+                let conflicting_usages = edges.iter().enumerate().any(|(i, a)| {
+                    edges[i + 1..]
+                        .iter()
+                        .any(|b| a.usage != b.usage && a.range.overlaps(&b.range))
+                });
+                if edges.iter().any(|e| !e.usage.is_compatible_with(&resource.info))
+                    || conflicting_usages
+                {
+                    errors.push(Error::IncompatibleResourceUsage {
+                        resource: resource.name.clone(),
+                        version,
+                        pass: self.pass_name(pass).to_owned(),
+                        usages: edges.iter().map(|e| e.usage).collect(),
+                    });
+                }
+            }
+
+            // only imported resources and previous contents of history resources
+            // can be used without a producer
+            if producers.is_empty() && !(resource.has_initial_contents() && version == 0) {
+                for &(pass, _) in consumers.iter() {
+                    errors.push(Error::UnproducedResourceRead {
+                        resource: resource.name.clone(),
+                        version,
+                        pass: self.pass_name(pass).to_owned(),
+                    });
+                }
+            }
+
+            // split the links into reads and writes
+            let mut writes: Vec<(NodeIndex, SubresourceRange)> = Vec::new();
+            let mut reads: Vec<(NodeIndex, SubresourceRange)> = Vec::new();
+            for &(pass, ref edges) in consumers.iter() {
+                for e in edges.iter() {
+                    match e.access {
+                        ResourceAccess::Write => writes.push((pass, e.range)),
+                        ResourceAccess::Read => reads.push((pass, e.range)),
                     }
                 }
-                if (writers.len() > 1) || (readers.len() > 0 && writers.len() > 0) {
-                    has_write_hazards = true;
-                    error!("Concurrent read/write hazard detected in frame graph");
-                    error!(
-                        " -> resource {:?} readers {:?} writers {:?}",
-                        index, readers, writers
-                    );
+            }
+
+            // The first pass (in declaration order) that writes to the version is the one that
+            // produces the next version. Other writers are modifying a stale version, even if
+            // they write to other subresources: otherwise, no version would hold the results
+            // of all writers.
+            writes.sort_by_key(|&(pass, _)| pass.index());
+            let mut stale_writers = Vec::new();
+            for (i, &(stale_writer, _)) in writes.iter().enumerate() {
+                if stale_writers.contains(&stale_writer) {
+                    continue;
+                }
+                let superseded_by = writes[..i].iter().find(|&&(w, _)| w != stale_writer);
+                if let Some(&(writer, _)) = superseded_by {
+                    stale_writers.push(stale_writer);
+                    errors.push(Error::StaleResourceWrite {
+                        resource: resource.name.clone(),
+                        version,
+                        pass: self.pass_name(stale_writer).to_owned(),
+                        superseded_by: self.pass_name(writer).to_owned(),
+                    });
                 }
             }
+
+            // Reads of subresources that are modified (by the same pass or another one)
+            let mut readers = Vec::new();
+            let mut writers = Vec::new();
+            for &(writer, write_range) in writes.iter() {
+                for &(reader, read_range) in reads.iter() {
+                    if write_range.overlaps(&read_range) {
+                        if !readers.contains(&reader) {
+                            readers.push(reader);
+                        }
+                        if !writers.contains(&writer) {
+                            writers.push(writer);
+                        }
+                    }
+                }
+            }
+
+            if !writers.is_empty() {
+                errors.push(Error::ConcurrentWriteHazard {
+                    resource: resource.name.clone(),
+                    version,
+                    readers: readers
+                        .iter()
+                        .map(|&pass| self.pass_name(pass).to_owned())
+                        .collect(),
+                    writers: writers
+                        .iter()
+                        .map(|&pass| self.pass_name(pass).to_owned())
+                        .collect(),
+                });
+            }
         }
-        has_write_hazards
+
+        errors
     }
 
     /// Determine the lifetime of the resources referenced in the graph.
@@ -429,7 +1113,9 @@ impl<'node> FrameGraph<'node> {
         for (topo_index, &n) in toposort.iter().enumerate() {
             match self.graph.node_weight(n).unwrap() {
                 &Node::Resource { .. } => (),
-                &Node::RenderPass { .. } => for dep in self.graph.neighbors(n) {
+                // a pass may reference several versions of the same resource: look at both
+                // inputs and outputs
+                _ => for dep in self.graph.neighbors_undirected(n) {
                     if let &Node::Resource { index, .. } = self.graph.node_weight(dep).unwrap() {
                         let resource = &mut self.resources[index.index()];
                         resource.lifetime = match resource.lifetime {
@@ -438,7 +1124,7 @@ impl<'node> FrameGraph<'node> {
                                 end: topo_index as i32,
                             }),
                             Some(Lifetime { begin, end }) => {
-                                assert!(end < topo_index as i32);
+                                assert!(end <= topo_index as i32);
                                 Some(Lifetime {
                                     begin,
                                     end: topo_index as i32,
@@ -453,117 +1139,151 @@ impl<'node> FrameGraph<'node> {
         }
     }
 
-    /// Allocate and assign actual resources in the graph.
-    /// Concretely, this function sets the 'alloc_index' fields in UnversionedResources
-    fn assign_aliased_resources(
-        &mut self,
-        gctx: &gfx::Context,
-        allocator: &mut FrameGraphAllocator,
-    ) {
+    /// Assign allocations to the resources in the graph, using the aliasing strategy of the graph.
+    /// Concretely, this function sets the 'aliased_index' fields in UnversionedResources.
+    ///
+    /// `allocations` contains the descriptions of the allocations that already exist;
+    /// new allocations are appended to it. No GPU resources are created here.
+    ///
+    /// Buffers are allocated in pools of power-of-two sizes.
+    fn assign_aliased_resources(&mut self, allocations: &mut Vec<ResourceInfo>) -> MemoryReport {
+        let mut resource_indices = Vec::new();
+        let mut requests = Vec::new();
         for (index, resource) in self.resources.iter().enumerate() {
-            if resource.aliased_index.get().is_none() {
-                // No allocation for the resource, create one
-                match resource.info {
-                    ResourceInfo::Texture { desc: ref texdesc } => {
-                        // iter over texture entries, find matching desc
-                        let arindex = allocator
-                            .allocations
-                            .iter()
-                            .enumerate()
-                            .find(|&(arindex, ar)| {
-                                if let &AliasedResource::Texture { ref tex } = ar {
-                                    // check if desc matches, and that...
-                                    *tex.desc() == *texdesc && {
-                                        // ... the lifetime does not conflict with other users of the alloc
-                                        self.resources
-                                            .iter()
-                                            .enumerate()
-                                            .find(|&(other_index, ref other)| {
-                                                (other_index != index)  // not the same resource...
-                                        && other.aliased_index.get().map_or(false, |other_arindex| other_arindex == AliasedResourceIndex::new(arindex))   // same allocation...
-                                        && resource.lifetime.unwrap().overlaps(&other.lifetime.unwrap()) // ...and overlapping lifetimes.
-                                                                                                         // if all of these conditions are true, then there's a conflict
-                                            })
-                                            .is_none() // true if no conflicts
-                                    }
-                                } else {
-                                    false // not a texture alloc
-                                }
-                            })
-                            .map(|(arindex, _)| AliasedResourceIndex::new(arindex)); // keep only index, drop borrow of allocations
-
-                        match arindex {
-                            Some(index) => {
-                                /*debug!(
-                                    "alloc {}({}-{}) reusing texture {}",
-                                    resource.name,
-                                    resource.lifetime.unwrap().begin,
-                                    resource.lifetime.unwrap().end,
-                                    index
-                                );*/
-                                resource.aliased_index.set(Some(index));
-                            }
-                            None => {
-                                // alloc a new texture
-                                debug!(
-                                    "alloc {}({}-{}) new texture {:?}",
-                                    resource.name,
-                                    resource.lifetime.unwrap().begin,
-                                    resource.lifetime.unwrap().end,
-                                    texdesc
-                                );
-                                allocator.allocations.push(AliasedResource::Texture {
-                                    tex: gfx::TextureAny::new(gctx, texdesc),
-                                });
-                                resource.aliased_index.set(Some(AliasedResourceIndex::new(
-                                    allocator.allocations.len() - 1,
-                                )));
-                            }
-                        }
-                    }
-                    ResourceInfo::Buffer { byte_size } => {
-                        // allocating a buffer
-                        let buffer = gfx::BufferAny::new(gctx, byte_size, gfx::BufferUsage::UPLOAD);
-                        allocator.allocations.push(AliasedResource::Buffer {
-                            // TODO allocate in transient pool?
-                            // TODO reuse buffers?
-                            buf: buffer,
-                        });
-                    }
-                }
+            if resource.lifetime.is_none() || resource.imported.is_some() || resource.history.is_some() {
+                // all versions of the resource were culled, or the resource is owned by someone else
+                continue;
             }
+            // description of the allocation that we need
+            let alloc_info = match resource.info {
+                ResourceInfo::Texture { .. } => resource.info,
+                ResourceInfo::Buffer { byte_size } => ResourceInfo::Buffer {
+                    byte_size: buffer_bucket_size(byte_size),
+                },
+            };
+            resource_indices.push(index);
+            requests.push(AllocationRequest {
+                info: alloc_info,
+                lifetime: resource.lifetime.unwrap(),
+            });
         }
+
+        let assigned = self.aliasing_strategy.assign(&requests, allocations);
+        assert_eq!(assigned.len(), requests.len());
+
+        let mut report_entries = Vec::new();
+        for (&index, &arindex) in resource_indices.iter().zip(assigned.iter()) {
+            let resource = &self.resources[index];
+            debug!(
+                "alloc {}({}-{}) -> #{} {:?}",
+                resource.name,
+                resource.lifetime.unwrap().begin,
+                resource.lifetime.unwrap().end,
+                arindex.index(),
+                allocations[arindex.index()]
+            );
+            resource.aliased_index.set(Some(arindex));
+            report_entries.push((resource.info, resource.lifetime.unwrap(), arindex));
+        }
+
+        MemoryReport::new(&report_entries, allocations)
     }
 
-    /// Consumes self, return a 'compiled frame graph' that is ready to execute.
-    /// Borrows FrameGraphAlloc mutably, borrow is dropped when the compiled graph is executed.
-    /// The compiled graph's lifetime is bound to the frame queue.
-    pub fn finalize(
-        mut self,
-        gctx: &gfx::Context,
-        allocator: &'node mut FrameGraphAllocator,
-    ) -> Result<ExecutionContext<'node>, Error> {
+    /// Compilation steps that do not need a GPU context.
+    /// Returns the execution order of the remaining nodes.
+    fn schedule(&mut self) -> Result<Vec<NodeIndex>, Error> {
+        //--------------------------------------
+        // STEP 0: Resolve the size of textures relative to viewports
+        self.resolve_relative_sizes()?;
+
+        //--------------------------------------
+        // STEP 0b: Remove disabled passes
+        self.remove_disabled_passes();
+
         //--------------------------------------
-        // STEP 1: Toposort nodes
+        // STEP 1: Cull passes and resources that do not contribute to the outputs
+        self.cull_unused_nodes();
+
+        //--------------------------------------
+        // STEP 2: Toposort nodes
         let toposort = self.toposort_nodes();
 
         //--------------------------------------
-        // STEP 2: Concurrent resource write detection
-        if self.detect_concurrent_write_hazards(&toposort) {
-            return Err(Error::ConcurrentWriteHazard);
+        // STEP 3: Resource access validation (write hazards, usages, etc.)
+        let mut errors = self.check_history_names();
+        errors.extend(self.check_resource_accesses(&toposort));
+        for err in errors.iter() {
+            error!("{}", err);
+        }
+        match errors.len() {
+            0 => {}
+            1 => return Err(errors.pop().unwrap()),
+            _ => return Err(Error::Multiple(errors)),
         }
 
         //--------------------------------------
-        // STEP 3: Lifetime calculation
+        // STEP 4: Lifetime calculation
         self.determine_resource_lifetimes(&toposort);
 
+        Ok(toposort)
+    }
+
+    /// Compiles the frame graph without allocating any GPU resource, as if it was finalized
+    /// with an empty allocator.
+    /// The result can be inspected and exported (see `CompiledFrameGraph::write_dot`
+    /// and `CompiledFrameGraph::write_json`), but not executed.
+    pub fn plan(mut self) -> Result<CompiledFrameGraph<'node>, Error> {
+        let structural_hash = self.structural_hash();
+        let toposort = self.schedule()?;
+        let mut allocations = Vec::new();
+        let memory_report = self.assign_aliased_resources(&mut allocations);
+        let barriers = self.compute_barriers(&toposort);
+        Ok(CompiledFrameGraph::new(
+            self,
+            toposort,
+            structural_hash,
+            memory_report,
+            barriers,
+            None,
+        ))
+    }
+
+    /// Consumes self, return a 'compiled frame graph' that is ready to execute.
+    /// The allocations are placed in the given allocator: the same allocator must be passed
+    /// to `CompiledFrameGraph::execute`.
+    pub fn finalize(
+        mut self,
+        gctx: &gfx::Context,
+        allocator: &mut FrameGraphAllocator,
+    ) -> Result<CompiledFrameGraph<'node>, Error> {
+        let structural_hash = self.structural_hash();
+        let toposort = self.schedule()?;
+
         //--------------------------------------
-        // STEP 4: Resource allocation
+        // STEP 5: Resource allocation
         // Assign 'allocations' (concrete buffers or textures) to resources
-        self.assign_aliased_resources(gctx, allocator);
+        let mut allocations = allocator
+            .allocations
+            .iter()
+            .map(|ar| ar.info())
+            .collect::<Vec<_>>();
+        let memory_report = self.assign_aliased_resources(&mut allocations);
+        allocator.create_allocations(gctx, &allocations);
+        self.create_history_textures(gctx, allocator);
+
+        //--------------------------------------
+        // STEP 6: Memory barriers
+        let barriers = self.compute_barriers(&toposort);
 
         // now everything should be allocated, build the CompiledGraph object
-        Ok(ExecutionContext::new(self, allocator, toposort))
+        Ok(CompiledFrameGraph::new(
+            self,
+            toposort,
+            structural_hash,
+            memory_report,
+            barriers,
+            Some(allocator.id),
+        ))
     }
 }
 
@@ -585,6 +1305,1256 @@ mod tests {
         //framegraph.create_render_pass("test", unimplemented!()).build();
     }
 
+    fn test_texture_desc() -> gfx::TextureDesc {
+        gfx::TextureDesc {
+            width: 640,
+            height: 480,
+            ..Default::default()
+        }
+    }
+
+    fn noop(_frame: &gfx::Frame, _ectx: &ExecutionContext) {}
+
+    fn pass_names(fg: &FrameGraph, toposort: &[NodeIndex]) -> Vec<String> {
+        toposort
+            .iter()
+            .filter_map(|&n| match fg.graph.node_weight(n).unwrap() {
+                &Node::Resource { .. } => None,
+                _ => Some(fg.pass_name(n).to_owned()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cull_unused_passes() {
+        let mut fg = FrameGraph::new();
+        let (color, debug) = {
+            let mut pass = fg.create_render_pass("gbuffers", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            let debug = pass.create_texture("debug", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            (color, debug)
+        };
+        let color = {
+            let mut pass = fg.create_render_pass("lighting", noop);
+            let color = pass.write(color, ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        {
+            let mut pass = fg.create_render_pass("debug_view", noop);
+            pass.read(debug, ResourceUsage::SampledImage);
+            pass.create_texture("debug_view", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+        }
+        fg.mark_output(color);
+
+        let toposort = fg.schedule().unwrap();
+        assert_eq!(pass_names(&fg, &toposort), vec!["gbuffers", "lighting"]);
+        // the output of a live pass is still allocated, even if nobody reads it
+        assert!(fg.resources[1].lifetime.is_some());
+        // resources only used by culled passes are not
+        assert!(fg.resources[2].lifetime.is_none());
+    }
+
+    #[test]
+    fn test_no_outputs_keeps_all_passes() {
+        let mut fg = FrameGraph::new();
+        fg.create_render_pass("a", noop).build();
+        fg.create_render_pass("b", noop).build();
+        let toposort = fg.schedule().unwrap();
+        assert_eq!(pass_names(&fg, &toposort).len(), 2);
+    }
+
+    fn build_simple_graph(fg: &mut FrameGraph, width: u32) {
+        let desc = gfx::TextureDesc {
+            width,
+            ..test_texture_desc()
+        };
+        let color = {
+            let mut pass = fg.create_render_pass("scene", noop);
+            let color = pass.create_texture("color", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        let mut pass = fg.create_render_pass("post", noop);
+        pass.read(color, ResourceUsage::SampledImage);
+        pass.create_texture("final", &desc, ResourceUsage::RenderTarget);
+        pass.build();
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let mut a = FrameGraph::new();
+        let mut b = FrameGraph::new();
+        let mut c = FrameGraph::new();
+        build_simple_graph(&mut a, 640);
+        build_simple_graph(&mut b, 640);
+        build_simple_graph(&mut c, 1280);
+        assert_eq!(a.structural_hash(), b.structural_hash());
+        assert_ne!(a.structural_hash(), c.structural_hash());
+    }
+
+    #[test]
+    fn test_export() {
+        let mut fg = FrameGraph::new();
+        build_simple_graph(&mut fg, 640);
+        let compiled = fg.plan().unwrap();
+
+        let mut dot = Vec::new();
+        compiled.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph framegraph {"));
+        assert!(dot.contains("label=\"post\\n#"));
+        assert!(dot.contains("label=\"SampledImage\""));
+
+        let mut json = Vec::new();
+        compiled.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("{\"resource\": \"color\", \"version\": 0, \"usage\": \"SampledImage\"}"));
+        assert!(json.contains("\"aliased_index\": 0"));
+    }
+
+    #[test]
+    fn test_concurrent_write_hazard() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.read(color, ResourceUsage::SampledImage);
+            pass.build();
+        }
+        {
+            let mut pass = fg.create_render_pass("c", noop);
+            pass.write(color, ResourceUsage::RenderTarget);
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::ConcurrentWriteHazard {
+                resource,
+                version,
+                readers,
+                writers,
+            }) => {
+                assert_eq!(resource, "color");
+                assert_eq!(version, 0);
+                assert_eq!(readers, vec!["b"]);
+                assert_eq!(writers, vec!["c"]);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_stale_write() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.write(color, ResourceUsage::RenderTarget);
+            pass.build();
+        }
+        {
+            let mut pass = fg.create_render_pass("c", noop);
+            pass.write(color, ResourceUsage::RenderTarget);
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::StaleResourceWrite {
+                resource,
+                pass,
+                superseded_by,
+                ..
+            }) => {
+                assert_eq!(resource, "color");
+                assert_eq!(pass, "c");
+                assert_eq!(superseded_by, "b");
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_multiple_errors() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.read(color, ResourceUsage::UniformBuffer);
+            pass.build();
+        }
+        {
+            let mut pass = fg.create_render_pass("c", noop);
+            pass.read(color, ResourceUsage::UniformBuffer);
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::Multiple(errors)) => {
+                let mut passes = errors
+                    .into_iter()
+                    .map(|err| match err {
+                        Error::IncompatibleResourceUsage { pass, .. } => pass,
+                        other => panic!("unexpected error: {}", other),
+                    })
+                    .collect::<Vec<_>>();
+                passes.sort();
+                assert_eq!(passes, vec!["b", "c"]);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_unproduced_read() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        // new version that no pass writes to
+        let color_v1 = fg.clone_resource(color);
+        {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.read(color_v1, ResourceUsage::SampledImage);
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::UnproducedResourceRead {
+                resource,
+                version,
+                pass,
+            }) => {
+                assert_eq!(resource, "color");
+                assert_eq!(version, 1);
+                assert_eq!(pass, "b");
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_incompatible_usage() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.read(color, ResourceUsage::UniformBuffer);
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::IncompatibleResourceUsage { pass, usages, .. }) => {
+                assert_eq!(pass, "b");
+                assert_eq!(usages, vec![ResourceUsage::UniformBuffer]);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_buffer_aliasing() {
+        let mut fg = FrameGraph::new();
+        let a = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let a = pass.create_buffer("a", 1000, ResourceUsage::ShaderStorageBuffer);
+            pass.build();
+            a
+        };
+        let b = {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.read(a, ResourceUsage::ShaderStorageBuffer);
+            let b = pass.create_buffer("b", 600, ResourceUsage::ShaderStorageBuffer);
+            pass.build();
+            b
+        };
+        let c = {
+            let mut pass = fg.create_render_pass("c", noop);
+            pass.read(b, ResourceUsage::ShaderStorageBuffer);
+            let c = pass.create_buffer("c", 900, ResourceUsage::ShaderStorageBuffer);
+            pass.build();
+            c
+        };
+        fg.mark_output(c);
+        let compiled = fg.plan().unwrap();
+        let fg = &compiled.fg;
+        let alloc = |i: usize| fg.resources[i].aliased_index.get().unwrap();
+        // a and b are alive at the same time
+        assert_ne!(alloc(0), alloc(1));
+        // a and c are in the same bucket, and do not overlap
+        assert_eq!(alloc(0), alloc(2));
+    }
+
+    #[test]
+    fn test_aliasing_strategies() {
+        let lifetime = |begin, end| Lifetime { begin, end };
+        let buffer = |byte_size| ResourceInfo::Buffer { byte_size };
+        let requests = [
+            AllocationRequest {
+                info: buffer(1024),
+                lifetime: lifetime(0, 2),
+            },
+            AllocationRequest {
+                info: buffer(256),
+                lifetime: lifetime(4, 6),
+            },
+            AllocationRequest {
+                info: buffer(512),
+                lifetime: lifetime(0, 6),
+            },
+        ];
+
+        // pre-existing allocations
+        let existing = vec![buffer(1024), buffer(256)];
+
+        let mut allocations = existing.clone();
+        let first_fit = FirstFit.assign(&requests, &mut allocations);
+        assert_eq!(
+            first_fit,
+            vec![
+                AliasedResourceIndex::new(0),
+                AliasedResourceIndex::new(0),
+                AliasedResourceIndex::new(2),
+            ]
+        );
+
+        let mut allocations = existing.clone();
+        let best_fit = BestFit.assign(&requests, &mut allocations);
+        assert_eq!(
+            best_fit,
+            vec![
+                AliasedResourceIndex::new(0),
+                AliasedResourceIndex::new(1),
+                AliasedResourceIndex::new(2),
+            ]
+        );
+        assert_eq!(allocations.len(), 3);
+    }
+
+    #[test]
+    fn test_interval_coloring() {
+        let lifetime = |begin, end| Lifetime { begin, end };
+        let buffer = |byte_size| ResourceInfo::Buffer { byte_size };
+        let request = |begin, end| AllocationRequest {
+            info: buffer(256),
+            lifetime: lifetime(begin, end),
+        };
+
+        // overlapping lifetimes need one allocation each
+        let mut allocations = Vec::new();
+        let assigned = IntervalColoring.assign(
+            &[request(0, 4), request(2, 6), request(3, 3)],
+            &mut allocations,
+        );
+        assert_eq!(
+            assigned,
+            vec![
+                AliasedResourceIndex::new(0),
+                AliasedResourceIndex::new(1),
+                AliasedResourceIndex::new(2),
+            ]
+        );
+        assert_eq!(allocations.len(), 3);
+
+        // disjoint lifetimes share allocations; requests are visited by order of their first
+        // use, and reuse the allocation released most recently
+        let mut allocations = Vec::new();
+        let assigned = IntervalColoring.assign(
+            &[request(5, 6), request(0, 1), request(0, 3), request(2, 2)],
+            &mut allocations,
+        );
+        assert_eq!(
+            assigned,
+            vec![
+                AliasedResourceIndex::new(1),
+                AliasedResourceIndex::new(0),
+                AliasedResourceIndex::new(1),
+                AliasedResourceIndex::new(0),
+            ]
+        );
+        assert_eq!(allocations.len(), 2);
+    }
+
+    #[test]
+    fn test_texture_byte_sizes() {
+        let texture = |dimensions, width, height, depth, mips| ResourceInfo::Texture {
+            desc: gfx::TextureDesc {
+                dimensions,
+                format: gfx::Format::R8G8B8A8_UNORM,
+                width,
+                height,
+                depth,
+                mip_map_count: gfx::MipMaps::Count(mips),
+                ..Default::default()
+            },
+        };
+
+        // 2D: 16x8 + 8x4 + 4x2 texels
+        assert_eq!(
+            resource_byte_size(&texture(gfx::TextureDimensions::Tex2D, 16, 8, 1, 3)),
+            (128 + 32 + 8) * 4
+        );
+        // cube maps have six faces
+        assert_eq!(
+            resource_byte_size(&texture(gfx::TextureDimensions::TexCube, 16, 16, 1, 2)),
+            6 * (256 + 64) * 4
+        );
+        // the layers of 2D arrays are not reduced in the mip chain
+        assert_eq!(
+            resource_byte_size(&texture(gfx::TextureDimensions::Tex2DArray, 16, 16, 5, 2)),
+            5 * (256 + 64) * 4
+        );
+        // 1D arrays store their layers in the height
+        assert_eq!(
+            resource_byte_size(&texture(gfx::TextureDimensions::Tex1DArray, 16, 3, 1, 2)),
+            3 * (16 + 8) * 4
+        );
+        // the depth of 3D textures is reduced in the mip chain
+        assert_eq!(
+            resource_byte_size(&texture(gfx::TextureDimensions::Tex3D, 8, 8, 4, 4)),
+            (8 * 8 * 4 + 4 * 4 * 2 + 2 * 2 * 1 + 1) * 4
+        );
+    }
+
+    #[test]
+    fn test_memory_report() {
+        let mut fg = FrameGraph::new();
+        let desc = test_texture_desc();
+        let a = {
+            let mut pass = fg.create_render_pass("a", noop);
+            let a = pass.create_texture("a", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            a
+        };
+        let b = {
+            let mut pass = fg.create_render_pass("b", noop);
+            pass.read(a, ResourceUsage::SampledImage);
+            let b = pass.create_texture("b", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            b
+        };
+        let c = {
+            let mut pass = fg.create_render_pass("c", noop);
+            pass.read(b, ResourceUsage::SampledImage);
+            let c = pass.create_texture("c", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            c
+        };
+        fg.mark_output(c);
+        let compiled = fg.plan().unwrap();
+        let report = compiled.memory_report();
+        let tex_size = 640 * 480 * 4;
+        assert_eq!(report.resource_count, 3);
+        assert_eq!(report.allocation_count, 2);
+        assert_eq!(report.no_aliasing_bytes, 3 * tex_size);
+        assert_eq!(report.allocated_bytes, 2 * tex_size);
+        assert_eq!(report.peak_live_bytes, 2 * tex_size);
+    }
+
+    #[test]
+    fn test_barriers() {
+        let mut fg = FrameGraph::new();
+        let particles = {
+            let mut pass = fg.create_render_pass("simulate", noop);
+            let particles = pass.create_buffer("particles", 4096, ResourceUsage::ShaderStorageBuffer);
+            pass.build();
+            particles
+        };
+        let mut outputs = Vec::new();
+        for &name in ["draw_a", "draw_b"].iter() {
+            let mut pass = fg.create_render_pass(name, noop);
+            pass.read(particles, ResourceUsage::UniformBuffer);
+            outputs.push(pass.create_texture(name, &test_texture_desc(), ResourceUsage::RenderTarget));
+            pass.build();
+        }
+        {
+            let mut pass = fg.create_render_pass("compose", noop);
+            pass.read(outputs[0], ResourceUsage::SampledImage);
+            pass.read(outputs[1], ResourceUsage::SampledImage);
+            pass.build();
+        }
+        let compiled = fg.plan().unwrap();
+        let barriers = compiled.barriers();
+        // a single uniform barrier, before the first pass that reads the particles,
+        // and no barrier for render targets
+        assert_eq!(barriers.len(), 1);
+        assert!(barriers[0].0 == "draw_a" || barriers[0].0 == "draw_b");
+        assert_eq!(barriers[0].1, gfx::MB_UNIFORM);
+    }
+
+    fn build_relative_graph(fg: &mut FrameGraph) -> (ResourceVersion, ResourceVersion) {
+        let mut pass = fg.create_render_pass("half_res", noop);
+        let half = pass.create_texture_relative(
+            "half",
+            &test_texture_desc(),
+            RelativeSize::viewport("main").scaled(1, 2),
+            ResourceUsage::RenderTarget,
+        );
+        let quarter = pass.create_texture_relative(
+            "quarter",
+            &test_texture_desc(),
+            RelativeSize::same_as(half).scaled(1, 2),
+            ResourceUsage::RenderTarget,
+        );
+        pass.build();
+        (half, quarter)
+    }
+
+    #[test]
+    #[should_panic(expected = "relative size scaled by 1/0")]
+    fn test_relative_size_zero_divisor() {
+        RelativeSize::viewport("main").scaled(1, 0);
+    }
+
+    #[test]
+    fn test_relative_size() {
+        let mut fg = FrameGraph::new();
+        fg.set_viewport("main", 1280, 720);
+        let (half, quarter) = build_relative_graph(&mut fg);
+        let hash_720p = fg.structural_hash();
+
+        let compiled = fg.plan().unwrap();
+        match *compiled.fg.resource_info(half) {
+            ResourceInfo::Texture { ref desc } => assert_eq!((desc.width, desc.height), (640, 360)),
+            _ => panic!("expected a texture"),
+        }
+        match *compiled.fg.resource_info(quarter) {
+            ResourceInfo::Texture { ref desc } => assert_eq!((desc.width, desc.height), (320, 180)),
+            _ => panic!("expected a texture"),
+        }
+
+        // resizing the viewport changes the structure of the graph
+        let mut fg = FrameGraph::new();
+        fg.set_viewport("main", 1920, 1080);
+        build_relative_graph(&mut fg);
+        assert!(fg.structural_hash() != hash_720p);
+
+        // missing viewport
+        let mut fg = FrameGraph::new();
+        build_relative_graph(&mut fg);
+        match fg.plan() {
+            Err(Error::UnknownViewport { ref viewport, .. }) => assert_eq!(viewport, "main"),
+            _ => panic!("expected an unknown viewport error"),
+        }
+    }
+
+    #[test]
+    fn test_subresource_ranges() {
+        let mut fg = FrameGraph::new();
+        let desc = gfx::TextureDesc {
+            mip_map_count: gfx::MipMaps::Count(4),
+            ..test_texture_desc()
+        };
+        let mut bloom = {
+            let mut pass = fg.create_render_pass("bright", noop);
+            let bloom = pass.create_texture("bloom", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            bloom
+        };
+        // downsample chain: each pass reads mip N and writes mip N+1 with image stores
+        for mip in 1..4 {
+            let mut pass = fg.create_render_pass(format!("downsample{}", mip), noop);
+            pass.read_range(bloom, ResourceUsage::RWImage, SubresourceRange::mip(mip - 1));
+            bloom = pass.write_range(bloom, ResourceUsage::RWImage, SubresourceRange::mip(mip));
+            pass.build();
+        }
+        {
+            let mut pass = fg.create_render_pass("compose", noop);
+            pass.read_range(bloom, ResourceUsage::SampledImage, SubresourceRange::mips(1, 3));
+            pass.build();
+        }
+        let compiled = fg.plan().unwrap();
+        let barriers = compiled.barriers();
+        // mip 0 is written by a render target: no barrier before the first downsample
+        assert_eq!(
+            barriers,
+            vec![
+                ("downsample2", gfx::MB_SHADER_IMAGE_ACCESS),
+                ("downsample3", gfx::MB_SHADER_IMAGE_ACCESS),
+                ("compose", gfx::MB_TEXTURE_FETCH),
+            ]
+        );
+
+        // reading and writing the same mip level in a pass is still a hazard
+        let mut fg = FrameGraph::new();
+        let tex = {
+            let mut pass = fg.create_render_pass("init", noop);
+            let tex = pass.create_texture("tex", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            tex
+        };
+        {
+            let mut pass = fg.create_render_pass("feedback", noop);
+            pass.read_range(tex, ResourceUsage::RWImage, SubresourceRange::mips(0, 2));
+            pass.write_range(tex, ResourceUsage::RWImage, SubresourceRange::mip(1));
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::ConcurrentWriteHazard { readers, writers, .. }) => {
+                assert_eq!(readers, vec!["feedback"]);
+                assert_eq!(writers, vec!["feedback"]);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        // passes writing different faces of a cube map in sequence: the last version
+        // holds all faces
+        let mut fg = FrameGraph::new();
+        let mut cube = {
+            let mut pass = fg.create_render_pass("init", noop);
+            let cube = pass.create_texture("cube", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            cube
+        };
+        for face in 0..6 {
+            let mut pass = fg.create_render_pass(format!("face{}", face), noop);
+            cube = pass.write_range(cube, ResourceUsage::RenderTarget, SubresourceRange::layer(face));
+            pass.build();
+        }
+        let filtered = {
+            let mut pass = fg.create_render_pass("filter", noop);
+            pass.read(cube, ResourceUsage::SampledImage);
+            let filtered = pass.create_texture("filtered", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            filtered
+        };
+        fg.mark_output(filtered);
+        let toposort = fg.schedule().unwrap();
+        assert_eq!(
+            pass_names(&fg, &toposort),
+            vec!["init", "face0", "face1", "face2", "face3", "face4", "face5", "filter"]
+        );
+
+        // writing different faces of the same version is still a stale write
+        let mut fg = FrameGraph::new();
+        let cube = {
+            let mut pass = fg.create_render_pass("init", noop);
+            let cube = pass.create_texture("cube", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            cube
+        };
+        for face in 0..2 {
+            let mut pass = fg.create_render_pass(format!("face{}", face), noop);
+            pass.write_range(cube, ResourceUsage::RenderTarget, SubresourceRange::layer(face));
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::StaleResourceWrite {
+                pass,
+                superseded_by,
+                ..
+            }) => {
+                assert_eq!(pass, "face1");
+                assert_eq!(superseded_by, "face0");
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    struct Blur;
+
+    impl<'node> SubGraph<'node> for Blur {
+        type Inputs = ResourceVersion;
+        type Outputs = ResourceVersion;
+
+        fn build(&self, fg: &mut FrameGraph<'node>, input: ResourceVersion) -> ResourceVersion {
+            let tmp = {
+                let mut pass = fg.create_render_pass("blur_h", noop);
+                pass.read(input, ResourceUsage::SampledImage);
+                let tmp = pass.create_texture("tmp", &test_texture_desc(), ResourceUsage::RenderTarget);
+                pass.build();
+                tmp
+            };
+            let mut pass = fg.create_render_pass("blur_v", noop);
+            pass.read(tmp, ResourceUsage::SampledImage);
+            let output = pass.create_texture("output", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            output
+        }
+    }
+
+    #[test]
+    fn test_subgraph() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("scene", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        let main = fg.instantiate("main", &Blur, color);
+        let reflection = fg.instantiate("reflection", &Blur, color);
+        fg.mark_output(main);
+        fg.mark_output(reflection);
+        let toposort = fg.schedule().unwrap();
+        let mut names = pass_names(&fg, &toposort);
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "main/blur_h",
+                "main/blur_v",
+                "reflection/blur_h",
+                "reflection/blur_v",
+                "scene",
+            ]
+        );
+        assert_eq!(fg.resources[1].name, "main/tmp");
+    }
+
+    fn build_conditional_graph<'a>(fg: &mut FrameGraph<'a>, enabled: &'a Cell<bool>) {
+        let color = {
+            let mut pass = fg.create_render_pass("scene", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        let color = {
+            let mut pass = fg.create_render_pass("fog", noop);
+            let color = pass.write(color, ResourceUsage::RenderTarget);
+            pass.enable_if(move || enabled.get());
+            pass.build();
+            color
+        };
+        let mut pass = fg.create_render_pass("tonemap", noop);
+        pass.read(color, ResourceUsage::SampledImage);
+        let output = pass.create_texture("output", &test_texture_desc(), ResourceUsage::RenderTarget);
+        pass.build();
+        fg.mark_output(output);
+    }
+
+    #[test]
+    fn test_conditional_pass() {
+        let enabled = Cell::new(true);
+        let mut fg = FrameGraph::new();
+        build_conditional_graph(&mut fg, &enabled);
+        let hash_enabled = fg.structural_hash();
+        let toposort = fg.schedule().unwrap();
+        assert_eq!(pass_names(&fg, &toposort), vec!["scene", "fog", "tonemap"]);
+
+        enabled.set(false);
+        let mut fg = FrameGraph::new();
+        build_conditional_graph(&mut fg, &enabled);
+        assert_ne!(fg.structural_hash(), hash_enabled);
+        // tonemap reads the output of the scene pass directly
+        let toposort = fg.schedule().unwrap();
+        assert_eq!(pass_names(&fg, &toposort), vec!["scene", "tonemap"]);
+    }
+
+    #[test]
+    fn test_conditional_pass_evaluated_once() {
+        let evaluations = Cell::new(0);
+        let mut fg = FrameGraph::new();
+        {
+            let mut pass = fg.create_render_pass("a", noop);
+            pass.enable_if(|| {
+                evaluations.set(evaluations.get() + 1);
+                true
+            });
+            pass.build();
+        }
+        let hash = fg.structural_hash();
+        assert_eq!(fg.structural_hash(), hash);
+        let compiled = fg.plan().unwrap();
+        assert_eq!(compiled.structural_hash(), hash);
+        assert_eq!(evaluations.get(), 1);
+    }
+
+    #[test]
+    fn test_description() {
+        let desc = GraphDescription::from_str(
+            r#"
+            outputs = ["color"]
+
+            [[passes]]
+            name = "gbuffer"
+            kind = "draw"
+            create = [
+                { name = "normals", format = "R16G16B16A16_SFLOAT", viewport = "main", usage = "RenderTarget" },
+                { name = "depth", format = "D32_SFLOAT", viewport = "main", scale = [1, 2], usage = "RenderTarget" },
+            ]
+
+            [[passes]]
+            name = "lighting"
+            kind = "draw"
+            read = [{ resource = "normals", usage = "SampledImage" }]
+            create = [{ name = "color", format = "R8G8B8A8_UNORM", width = 256, height = 256, usage = "RenderTarget" }]
+
+            [[passes]]
+            name = "unused"
+            kind = "draw"
+            write = [{ resource = "normals", usage = "RenderTarget" }]
+            "#,
+        ).unwrap();
+
+        let mut registry = PassRegistry::new();
+        registry.register(
+            "draw",
+            |desc: &PassDescription, resources: &PassResources| -> Box<RenderPassCallbacks> {
+                // factories see all the resources accessed by the pass
+                let names = desc.read
+                    .iter()
+                    .chain(desc.write.iter())
+                    .map(|access| &access.resource)
+                    .chain(desc.create.iter().map(|res| &res.name));
+                for name in names {
+                    assert!(resources.contains_key(name));
+                }
+                Box::new(noop)
+            },
+        );
+
+        let mut fg = FrameGraph::new();
+        fg.set_viewport("main", 1280, 720);
+        let versions = fg.load_description(&desc, &registry).unwrap();
+        let toposort = fg.schedule().unwrap();
+        assert_eq!(pass_names(&fg, &toposort), vec!["gbuffer", "lighting"]);
+        match *fg.resource_info(versions["depth"]) {
+            ResourceInfo::Texture { ref desc } => assert_eq!((desc.width, desc.height), (640, 360)),
+            _ => panic!("not a texture"),
+        }
+
+        let desc = GraphDescription::from_str(
+            r#"
+            [[passes]]
+            name = "blit"
+            kind = "blit"
+            "#,
+        ).unwrap();
+        match FrameGraph::new().load_description(&desc, &registry) {
+            Err(LoadError::UnknownPassKind { ref kind, .. }) => assert_eq!(kind, "blit"),
+            _ => panic!("expected an unknown pass kind error"),
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut fg = FrameGraph::new();
+        let (color, depth) = {
+            let mut pass = fg.create_render_pass("scene", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            let depth = pass.create_texture("depth", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            (color, depth)
+        };
+        let shadows = {
+            let mut pass = fg.create_render_pass("shadows", noop);
+            let shadows = pass.create_texture("shadows", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            shadows
+        };
+        let post = {
+            let mut pass = fg.create_render_pass("post", noop);
+            pass.read(color, ResourceUsage::SampledImage);
+            pass.read(depth, ResourceUsage::SampledImage);
+            pass.build()
+        };
+        let toposort = fg.schedule().unwrap();
+        let allocator = FrameGraphAllocator::new();
+        let position = toposort.iter().position(|&n| n == post.0).unwrap();
+        let index = |res: ResourceVersion| fg.resource_version(res.0).0;
+
+        // `post` looks up the color buffer and the shadow map, but not the depth buffer
+        let mut validator = validation::Validator::new();
+        validator.begin_pass(post.0, position);
+        assert!(validator.record_access(&fg, index(color)).is_ok());
+        assert!(validator.record_access(&fg, index(shadows)).is_err());
+        assert!(validator.record_access(&fg, index(shadows)).is_err());
+        validator.end_pass(&fg, &allocator, &[]);
+        let report = validator.finish();
+
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.undeclared_accesses,
+            vec![UndeclaredAccess {
+                pass: "post".to_owned(),
+                resource: "shadows".to_owned(),
+            }]
+        );
+        assert_eq!(
+            report.unused_inputs,
+            vec![UnusedInput {
+                pass: "post".to_owned(),
+                resource: "depth".to_owned(),
+            }]
+        );
+    }
+
+    fn null_context() -> (gfx::Context, gfx::Queue) {
+        let context = gfx::Context::new_null(&gfx::ContextConfig {
+            max_frames_in_flight: 2,
+        });
+        let queue = gfx::Queue::new(&context);
+        (context, queue)
+    }
+
+    #[test]
+    fn test_validated_execution() {
+        let (context, mut queue) = null_context();
+        let external = gfx::TextureAny::new(&context, &test_texture_desc());
+        let mut fg = FrameGraph::new();
+        fg.import_texture("external", &external);
+        let color = {
+            let mut pass = fg.create_render_pass("scene", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        {
+            // binds the imported texture without declaring it
+            let external = external.clone();
+            let mut pass = fg.create_render_pass("post", move |frame: &gfx::Frame, ectx: &ExecutionContext| {
+                ectx.texture_resource(color);
+                frame.ref_texture(external.clone());
+            });
+            pass.read(color, ResourceUsage::SampledImage);
+            pass.build();
+        }
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+
+        let frame = gfx::Frame::new(&mut queue);
+        let report = compiled.execute_validated::<()>(&frame, &allocator, &());
+        frame.submit();
+        assert_eq!(
+            report.undeclared_accesses,
+            vec![UndeclaredAccess {
+                pass: "post".to_owned(),
+                resource: "external".to_owned(),
+            }]
+        );
+        assert!(report.unused_inputs.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "pass `post` accesses undeclared resource `shadows`")]
+    fn test_validated_execution_undeclared_lookup() {
+        let (context, mut queue) = null_context();
+        let mut fg = FrameGraph::new();
+        let shadows = {
+            let mut pass = fg.create_render_pass("shadows", noop);
+            let shadows = pass.create_texture("shadows", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            shadows
+        };
+        fg.create_render_pass("post", move |_frame: &gfx::Frame, ectx: &ExecutionContext| {
+            ectx.texture_resource(shadows);
+        }).build();
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+
+        let frame = gfx::Frame::new(&mut queue);
+        compiled.execute_validated::<()>(&frame, &allocator, &());
+        frame.submit();
+    }
+
+    #[test]
+    fn test_validated_execution_undeclared_history() {
+        let (context, mut queue) = null_context();
+        // the current history texture, fetched from the allocator once the graph is finalized
+        let history: RefCell<Option<gfx::TextureAny>> = RefCell::new(None);
+        let mut fg = FrameGraph::new();
+        {
+            let mut pass = fg.create_render_pass("taa", noop);
+            let taa = pass.create_history_texture("taa", &test_texture_desc(), 1, ResourceUsage::RenderTarget);
+            pass.read_previous(taa, 1);
+            pass.build();
+        }
+        let output = {
+            // binds the history texture without declaring it
+            let mut pass = fg.create_render_pass("post", |frame: &gfx::Frame, _ectx: &ExecutionContext| {
+                frame.ref_texture(history.borrow().clone().unwrap());
+            });
+            let output = pass.create_texture("output", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            output
+        };
+        fg.mark_output(output);
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+        *history.borrow_mut() = match *allocator.history["taa"].texture(0) {
+            AliasedResource::Texture { ref tex } => Some(tex.clone()),
+            _ => panic!("expected a history texture"),
+        };
+
+        let frame = gfx::Frame::new(&mut queue);
+        let report = compiled.execute_validated::<()>(&frame, &allocator, &());
+        frame.submit();
+        assert_eq!(
+            report.undeclared_accesses,
+            vec![UndeclaredAccess {
+                pass: "post".to_owned(),
+                resource: "taa".to_owned(),
+            }]
+        );
+    }
+
+    /// Parameters that borrow data from the caller.
+    struct TestParams<'a> {
+        value: &'a Cell<u32>,
+    }
+
+    impl<'p> FrameParams<'p> for TestParams<'static> {
+        type Params = TestParams<'p>;
+    }
+
+    #[test]
+    fn test_execute_params() {
+        let (context, mut queue) = null_context();
+        let mut fg = FrameGraph::new();
+        fg.create_render_pass("scene", |_frame: &gfx::Frame, ectx: &ExecutionContext| {
+            ectx.params::<TestParams<'static>>().value.set(42);
+        }).build();
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+
+        let value = Cell::new(0);
+        let frame = gfx::Frame::new(&mut queue);
+        compiled.execute::<TestParams<'static>>(&frame, &allocator, &TestParams { value: &value });
+        frame.submit();
+        assert_eq!(value.get(), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected type for the frame graph parameters")]
+    fn test_execute_params_wrong_type() {
+        let (context, mut queue) = null_context();
+        let mut fg = FrameGraph::new();
+        fg.create_render_pass("scene", |_frame: &gfx::Frame, ectx: &ExecutionContext| {
+            ectx.params::<TestParams<'static>>();
+        }).build();
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+
+        let frame = gfx::Frame::new(&mut queue);
+        compiled.execute::<()>(&frame, &allocator, &());
+        frame.submit();
+    }
+
+    #[test]
+    #[should_panic(expected = "the frame graph was compiled with another allocator")]
+    fn test_execute_other_allocator() {
+        let (context, mut queue) = null_context();
+        let mut fg = FrameGraph::new();
+        fg.create_render_pass("scene", noop).build();
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+
+        let other_allocator = FrameGraphAllocator::new();
+        let frame = gfx::Frame::new(&mut queue);
+        compiled.execute::<()>(&frame, &other_allocator, &());
+        frame.submit();
+    }
+
+    #[test]
+    fn test_history() {
+        let mut fg = FrameGraph::new();
+        let color = {
+            let mut pass = fg.create_render_pass("scene", noop);
+            let color = pass.create_texture("color", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            color
+        };
+        let taa_prev = {
+            let mut pass = fg.create_render_pass("taa", noop);
+            pass.read(color, ResourceUsage::SampledImage);
+            let taa = pass.create_history_texture("taa", &test_texture_desc(), 1, ResourceUsage::RenderTarget);
+            let taa_prev = pass.read_previous(taa, 1);
+            pass.build();
+            taa_prev
+        };
+        let output = {
+            let mut pass = fg.create_render_pass("tonemap", noop);
+            pass.read(color, ResourceUsage::SampledImage);
+            let output = pass.create_texture("output", &test_texture_desc(), ResourceUsage::RenderTarget);
+            pass.build();
+            output
+        };
+        // the output does not depend on the history texture, but the next frame does:
+        // the TAA pass is not culled
+        fg.mark_output(output);
+        let compiled = fg.plan().unwrap();
+        let (index, _) = compiled.fg.resource_version(taa_prev.0);
+        assert_eq!(compiled.fg.resources[index.index()].name, "taa[-1]");
+        let mut names = pass_names(&compiled.fg, &compiled.toposort);
+        names.sort();
+        assert_eq!(names, vec!["scene", "taa", "tonemap"]);
+        // history textures are owned by the allocator, not aliased with transient resources
+        assert_eq!(compiled.memory_report().resource_count, 2);
+    }
+
+    #[test]
+    fn test_history_rotation() {
+        let (context, mut queue) = null_context();
+        // (current, previous, previous is valid), recorded at each frame
+        let frames = RefCell::new(Vec::new());
+        let handles: Cell<Option<(ResourceVersion, ResourceVersion)>> = Cell::new(None);
+        let mut fg = FrameGraph::new();
+        let taa = {
+            let mut pass = fg.create_render_pass("taa", |_frame: &gfx::Frame, ectx: &ExecutionContext| {
+                let (taa, taa_prev) = handles.get().unwrap();
+                frames.borrow_mut().push((
+                    ectx.texture_resource(taa).gl_object(),
+                    ectx.texture_resource(taa_prev).gl_object(),
+                    ectx.is_history_valid(taa_prev),
+                ));
+            });
+            let taa = pass.create_history_texture("taa", &test_texture_desc(), 1, ResourceUsage::RenderTarget);
+            let taa_prev = pass.read_previous(taa, 1);
+            handles.set(Some((taa, taa_prev)));
+            pass.build();
+            taa
+        };
+        fg.mark_output(taa);
+        let mut allocator = FrameGraphAllocator::new();
+        let compiled = fg.finalize(&context, &mut allocator).unwrap();
+
+        for _ in 0..3 {
+            let frame = gfx::Frame::new(&mut queue);
+            compiled.execute::<()>(&frame, &allocator, &());
+            frame.submit();
+        }
+
+        let frames = frames.borrow();
+        // there is no previous frame yet
+        assert!(!frames[0].2);
+        assert!(frames[1].2 && frames[2].2);
+        for n in 1..3 {
+            // frame N reads the texture written by frame N-1
+            assert_eq!(frames[n].1, frames[n - 1].0);
+            assert_ne!(frames[n].0, frames[n].1);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_history() {
+        let mut fg = FrameGraph::new();
+        for pass_name in ["a", "b"].iter() {
+            let mut pass = fg.create_render_pass(*pass_name, noop);
+            pass.create_history_texture("taa", &test_texture_desc(), 1, ResourceUsage::RenderTarget);
+            pass.build();
+        }
+        match fg.schedule() {
+            Err(Error::DuplicateHistory { resource }) => assert_eq!(resource, "taa"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_undeclared_history_eviction() {
+        let (context, _queue) = null_context();
+        let mut allocator = FrameGraphAllocator::new();
+        let history_graph = |name: &'static str| {
+            let mut fg = FrameGraph::new();
+            let res = {
+                let mut pass = fg.create_render_pass("pass", noop);
+                let res = pass.create_history_texture(name, &test_texture_desc(), 1, ResourceUsage::RenderTarget);
+                pass.build();
+                res
+            };
+            fg.mark_output(res);
+            fg
+        };
+        history_graph("a").finalize(&context, &mut allocator).unwrap();
+        assert!(allocator.history.contains_key("a"));
+        // the next graph does not declare `a` anymore
+        history_graph("b").finalize(&context, &mut allocator).unwrap();
+        assert!(!allocator.history.contains_key("a"));
+        assert!(allocator.history.contains_key("b"));
+    }
+
+    #[test]
+    fn test_dispatch_size() {
+        let mut fg = FrameGraph::new();
+        let (tex, buf) = {
+            let mut pass = fg.create_render_pass("init", noop);
+            let tex = pass.create_texture("tex", &test_texture_desc(), ResourceUsage::RenderTarget);
+            let buf = pass.create_buffer("buf", 1000 * 16, ResourceUsage::TransformFeedbackOutput);
+            pass.build();
+            (tex, buf)
+        };
+        let array = {
+            let mut pass = fg.create_render_pass("init_array", noop);
+            let desc = gfx::TextureDesc {
+                dimensions: gfx::TextureDimensions::Tex2DArray,
+                depth: 6,
+                ..test_texture_desc()
+            };
+            let array = pass.create_texture("array", &desc, ResourceUsage::RenderTarget);
+            pass.build();
+            array
+        };
+        assert_eq!(
+            fg.dispatch_groups(&DispatchSize::Groups(3, 2, 1), (8, 8, 1)),
+            (3, 2, 1)
+        );
+        assert_eq!(
+            fg.dispatch_groups(&DispatchSize::Invocations(17, 8, 1), (8, 8, 1)),
+            (3, 1, 1)
+        );
+        assert_eq!(
+            fg.dispatch_groups(
+                &DispatchSize::TextureSize {
+                    resource: tex,
+                    mip_level: 0,
+                },
+                (16, 16, 1)
+            ),
+            (40, 30, 1)
+        );
+        assert_eq!(
+            fg.dispatch_groups(
+                &DispatchSize::TextureSize {
+                    resource: tex,
+                    mip_level: 3,
+                },
+                (16, 16, 1)
+            ),
+            (5, 4, 1)
+        );
+        // the layers of an array texture are not reduced in the mip chain
+        assert_eq!(
+            fg.dispatch_groups(
+                &DispatchSize::TextureSize {
+                    resource: array,
+                    mip_level: 3,
+                },
+                (16, 16, 1)
+            ),
+            (5, 4, 6)
+        );
+        assert_eq!(
+            fg.dispatch_groups(
+                &DispatchSize::BufferElements {
+                    resource: buf,
+                    element_size: 16,
+                },
+                (64, 1, 1)
+            ),
+            (16, 1, 1)
+        );
+    }
+
     #[test]
     fn test_borrows() {
 
@@ -597,4 +2567,78 @@ mod tests {
         let mut out = File::create(path).unwrap();
         write!(out, "{:#?}", Dot::new(&fg.graph));*/
     }
+
+    fn fake_profiled_frame(profiler: &mut FrameProfiler, passes: &[&str]) {
+        profiler.begin_frame();
+        for &name in passes.iter() {
+            profiler.begin_pass(name);
+            profiler.end_pass();
+        }
+        profiler.end_frame();
+    }
+
+    #[test]
+    fn test_rolling_average() {
+        let mut profiler = FrameProfiler::cpu_only(4);
+        for _ in 0..10 {
+            fake_profiled_frame(&mut profiler, &["gbuffer", "lighting"]);
+        }
+        fake_profiled_frame(&mut profiler, &["gbuffer"]);
+
+        assert_eq!(profiler.reports().len(), 4);
+        assert_eq!(profiler.latest_report().unwrap().frame, 10);
+        assert_eq!(profiler.average("gbuffer").unwrap().samples, 4);
+        assert_eq!(profiler.average("lighting").unwrap().samples, 3);
+        assert!(profiler.average("lighting").unwrap().gpu_time.is_none());
+        assert!(profiler.average("shadows").is_none());
+        let names: Vec<String> = profiler.averages().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, vec!["gbuffer".to_owned(), "lighting".to_owned()]);
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let report = FrameReport {
+            frame: 3,
+            passes: vec![PassTiming {
+                name: "gbuffer".to_owned(),
+                cpu_start: ::std::time::Duration::new(0, 1000),
+                cpu_time: ::std::time::Duration::new(0, 250_000),
+                gpu_start: Some(::std::time::Duration::new(0, 2000)),
+                gpu_time: Some(::std::time::Duration::new(0, 1_500_000)),
+            }],
+        };
+        let mut out = Vec::new();
+        report.write_chrome_trace(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\"name\": \"gbuffer\", \"cat\": \"cpu\", \"ph\": \"X\", \"pid\": 0, \"tid\": 0, \"ts\": 1.000, \"dur\": 250.000"));
+        assert!(out.contains("\"cat\": \"gpu\", \"ph\": \"X\", \"pid\": 0, \"tid\": 1, \"ts\": 2.000, \"dur\": 1500.000"));
+    }
+
+    #[test]
+    fn test_profiler_chrome_trace_frames() {
+        let mut profiler = FrameProfiler::cpu_only(4);
+        for _ in 0..3 {
+            fake_profiled_frame(&mut profiler, &["gbuffer"]);
+        }
+        let mut out = Vec::new();
+        profiler.write_chrome_trace(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        // each event is tagged with the frame it was measured in
+        for frame in 0..3 {
+            assert_eq!(
+                out.matches(&format!("\"args\": {{\"frame\": {}}}", frame)).count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn test_profiler_null_context() {
+        let (context, _queue) = null_context();
+        let mut profiler = FrameProfiler::new(&context, 4);
+        fake_profiled_frame(&mut profiler, &["gbuffer"]);
+        // no timer queries on a null context: the report is complete immediately
+        assert_eq!(profiler.reports().len(), 1);
+        assert!(profiler.latest_report().unwrap().gpu_time().is_none());
+    }
 }
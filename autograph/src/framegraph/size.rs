@@ -0,0 +1,163 @@
+//! Texture resources with a size relative to a viewport or to another resource.
+//!
+use super::*;
+
+/// Size of a texture resource, relative to a named viewport (see `FrameGraph::set_viewport`)
+/// or to another texture resource.
+///
+/// The base size is multiplied by `num / den` (rounded down, and at least one texel).
+#[derive(Clone, Debug)]
+pub enum RelativeSize {
+    Viewport { name: String, num: u32, den: u32 },
+    Resource {
+        resource: ResourceVersion,
+        num: u32,
+        den: u32,
+    },
+}
+
+impl RelativeSize {
+    /// Same size as the specified viewport.
+    pub fn viewport<S: Into<String>>(name: S) -> RelativeSize {
+        RelativeSize::Viewport {
+            name: name.into(),
+            num: 1,
+            den: 1,
+        }
+    }
+
+    /// Same size as another texture resource.
+    pub fn same_as(resource: ResourceVersion) -> RelativeSize {
+        RelativeSize::Resource {
+            resource,
+            num: 1,
+            den: 1,
+        }
+    }
+
+    /// Scales the size by `num / den` (e.g. `scaled(1, 2)` for half-resolution).
+    ///
+    /// Panics if `den` is zero.
+    pub fn scaled(self, num: u32, den: u32) -> RelativeSize {
+        assert!(den != 0, "relative size scaled by {}/0", num);
+        match self {
+            RelativeSize::Viewport {
+                name,
+                num: n,
+                den: d,
+            } => RelativeSize::Viewport {
+                name,
+                num: n * num,
+                den: d * den,
+            },
+            RelativeSize::Resource {
+                resource,
+                num: n,
+                den: d,
+            } => RelativeSize::Resource {
+                resource,
+                num: n * num,
+                den: d * den,
+            },
+        }
+    }
+}
+
+/// A size relative to a viewport, once references to other resources have been followed.
+#[derive(Clone, Debug, Hash)]
+pub(super) struct ViewportRelativeSize {
+    viewport: String,
+    num: u32,
+    den: u32,
+}
+
+fn scale(size: u32, num: u32, den: u32) -> u32 {
+    cmp::max((size as u64 * num as u64 / den as u64) as u32, 1)
+}
+
+impl<'node> FrameGraph<'node> {
+    /// Sets the size of a viewport.
+    /// Textures created with a size relative to this viewport are resized accordingly
+    /// when the graph is finalized.
+    pub fn set_viewport<S: Into<String>>(&mut self, name: S, width: u32, height: u32) {
+        self.viewports.insert(name.into(), (width, height));
+    }
+
+    /// Creates a texture resource with a size relative to a viewport or another resource.
+    /// The width and height of `desc` are ignored.
+    pub(super) fn create_relative_texture(
+        &mut self,
+        name: String,
+        desc: &gfx::TextureDesc,
+        size: RelativeSize,
+    ) -> ResourceVersion {
+        let mut desc = *desc;
+        let relative_size = match size {
+            RelativeSize::Viewport { name, num, den } => Some(ViewportRelativeSize {
+                viewport: name,
+                num,
+                den,
+            }),
+            RelativeSize::Resource { resource, num, den } => {
+                let index = match *self.graph.node_weight(resource.0).unwrap() {
+                    Node::Resource { index, .. } => index,
+                    _ => panic!("not a resource node"),
+                };
+                let base = &self.resources[index.index()];
+                match base.relative_size {
+                    // the base is itself relative to a viewport: follow it
+                    Some(ref base_size) => Some(ViewportRelativeSize {
+                        viewport: base_size.viewport.clone(),
+                        num: base_size.num * num,
+                        den: base_size.den * den,
+                    }),
+                    // the base has a fixed size: compute it now
+                    None => match base.info {
+                        ResourceInfo::Texture { desc: ref base_desc } => {
+                            desc.width = scale(base_desc.width, num, den);
+                            desc.height = scale(base_desc.height, num, den);
+                            None
+                        }
+                        _ => panic!("not a texture resource"),
+                    },
+                }
+            }
+        };
+        let res = self.create_resource(name, ResourceInfo::Texture { desc });
+        let index = self.resources.len() - 1;
+        self.resources[index].relative_size = relative_size;
+        res
+    }
+
+    /// Returns the description of a resource, with its size resolved if it is relative to a viewport.
+    pub(super) fn resolve_resource_info(
+        &self,
+        index: UnversionedResourceIndex,
+    ) -> Result<ResourceInfo, Error> {
+        let resource = &self.resources[index.index()];
+        match (resource.info, &resource.relative_size) {
+            (ResourceInfo::Texture { mut desc }, &Some(ref size)) => {
+                let &(width, height) =
+                    self.viewports
+                        .get(&size.viewport)
+                        .ok_or_else(|| Error::UnknownViewport {
+                            resource: resource.name.clone(),
+                            viewport: size.viewport.clone(),
+                        })?;
+                desc.width = scale(width, size.num, size.den);
+                desc.height = scale(height, size.num, size.den);
+                Ok(ResourceInfo::Texture { desc })
+            }
+            (info, _) => Ok(info),
+        }
+    }
+
+    /// Replaces relative sizes by the actual size of the textures.
+    pub(super) fn resolve_relative_sizes(&mut self) -> Result<(), Error> {
+        for i in 0..self.resources.len() {
+            let info = self.resolve_resource_info(UnversionedResourceIndex::new(i))?;
+            self.resources[i].info = info;
+        }
+        Ok(())
+    }
+}
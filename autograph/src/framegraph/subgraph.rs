@@ -0,0 +1,141 @@
+//! Reusable sub-graphs and conditional passes.
+//!
+use super::*;
+
+/// A reusable part of a frame graph, instantiated with `FrameGraph::instantiate`.
+///
+/// `Inputs` and `Outputs` are typically structs of `ResourceVersion`s: for instance,
+/// a lighting sub-graph takes a G-buffer and returns the lit image.
+pub trait SubGraph<'node> {
+    type Inputs;
+    type Outputs;
+
+    /// Adds the passes and resources of the sub-graph to `fg`.
+    fn build(&self, fg: &mut FrameGraph<'node>, inputs: Self::Inputs) -> Self::Outputs;
+}
+
+/// A predicate deciding whether a pass runs (see `RenderPassBuilder::enable_if`).
+/// It is evaluated at most once: the structural hash and the compilation of the graph
+/// see the same value.
+pub(super) struct PassCondition<'node> {
+    predicate: Box<Fn() -> bool + 'node>,
+    value: Cell<Option<bool>>,
+}
+
+impl<'node> PassCondition<'node> {
+    fn evaluate(&self) -> bool {
+        if let Some(value) = self.value.get() {
+            return value;
+        }
+        let value = (self.predicate)();
+        self.value.set(Some(value));
+        value
+    }
+}
+
+impl<'node> FrameGraph<'node> {
+    /// Adds an instance of a sub-graph to the frame graph.
+    ///
+    /// The names of all passes and resources created by the sub-graph are prefixed by `prefix`
+    /// (`prefix/name`), so that the same sub-graph can be instantiated several times
+    /// (e.g. once per view). Instantiations can be nested.
+    pub fn instantiate<S: SubGraph<'node>, P: Into<String>>(
+        &mut self,
+        prefix: P,
+        subgraph: &S,
+        inputs: S::Inputs,
+    ) -> S::Outputs {
+        self.name_prefixes.push(prefix.into());
+        let outputs = subgraph.build(self, inputs);
+        self.name_prefixes.pop();
+        outputs
+    }
+
+    /// Prepends the prefixes of the sub-graphs being instantiated to a pass or resource name.
+    pub(super) fn prefixed_name(&self, name: String) -> String {
+        if self.name_prefixes.is_empty() {
+            return name;
+        }
+        let mut prefixed = self.name_prefixes.join("/");
+        prefixed.push('/');
+        prefixed.push_str(&name);
+        prefixed
+    }
+
+    /// Sets the predicate that decides whether a pass runs.
+    pub(super) fn set_pass_condition(
+        &mut self,
+        pass: RenderPass,
+        predicate: Box<Fn() -> bool + 'node>,
+    ) {
+        self.pass_conditions.insert(
+            pass.0,
+            PassCondition {
+                predicate,
+                value: Cell::new(None),
+            },
+        );
+    }
+
+    /// Returns whether a pass is enabled.
+    /// Its predicate is evaluated on the first call only.
+    pub(super) fn is_pass_enabled(&self, pass: NodeIndex) -> bool {
+        self.pass_conditions
+            .get(&pass)
+            .map_or(true, |condition| condition.evaluate())
+    }
+
+    /// Removes the disabled passes from the graph.
+    ///
+    /// Passes that read a version modified by a disabled pass read the unmodified version instead.
+    /// Resources created by a disabled pass are not produced: passes that read them must be
+    /// disabled as well.
+    pub(super) fn remove_disabled_passes(&mut self) {
+        let disabled: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|&n| self.graph.node_weight(n).unwrap().is_pass() && !self.is_pass_enabled(n))
+            .collect();
+
+        for pass in disabled {
+            let outputs: Vec<NodeIndex> = self
+                .graph
+                .neighbors_directed(pass, Direction::Outgoing)
+                .collect();
+            for output in outputs {
+                let (index, _) = self.resource_version(output);
+                // the version modified by the pass, if it did not create the resource
+                let input = self
+                    .graph
+                    .edges_directed(pass, Direction::Incoming)
+                    .filter(|e| {
+                        e.weight().access == ResourceAccess::Write
+                            && self.resource_version(e.source()).0 == index
+                    })
+                    .max_by_key(|e| self.resource_version(e.source()).1)
+                    .map(|e| e.source());
+                let input = match input {
+                    Some(input) => input,
+                    None => continue,
+                };
+                // bypass the pass
+                let consumers: Vec<(EdgeIndex, NodeIndex, Edge)> = self
+                    .graph
+                    .edges_directed(output, Direction::Outgoing)
+                    .map(|e| (e.id(), e.target(), *e.weight()))
+                    .collect();
+                for (e, consumer, edge) in consumers {
+                    self.graph.remove_edge(e);
+                    self.graph.add_edge(input, consumer, edge);
+                }
+                for out in self.outputs.iter_mut() {
+                    if out.0 == output {
+                        out.0 = input;
+                    }
+                }
+            }
+            debug!("disabled pass {}", self.pass_name(pass));
+            self.graph.remove_node(pass);
+        }
+    }
+}
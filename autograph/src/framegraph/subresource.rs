@@ -0,0 +1,107 @@
+//! Subresources: mip levels and array layers of textures.
+//!
+use std::u32;
+
+/// A range of mip levels and array layers (or cube map faces) of a texture.
+///
+/// Passes that access disjoint ranges of the same resource version do not conflict:
+/// for instance, a pass can read mip level N of a texture and write mip level N+1.
+/// Buffers are always accessed as a whole, with `SubresourceRange::all()`.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct SubresourceRange {
+    pub base_mip: u32,
+    /// Number of mip levels, or `u32::MAX` for all remaining levels.
+    pub mip_count: u32,
+    pub base_layer: u32,
+    /// Number of layers, or `u32::MAX` for all remaining layers.
+    pub layer_count: u32,
+}
+
+impl Default for SubresourceRange {
+    fn default() -> SubresourceRange {
+        SubresourceRange::all()
+    }
+}
+
+/// Returns whether the intervals [a, a+a_count) and [b, b+b_count) intersect.
+fn intervals_overlap(a: u32, a_count: u32, b: u32, b_count: u32) -> bool {
+    let a_end = a.saturating_add(a_count);
+    let b_end = b.saturating_add(b_count);
+    a < b_end && b < a_end
+}
+
+impl SubresourceRange {
+    /// All mip levels and layers.
+    pub fn all() -> SubresourceRange {
+        SubresourceRange {
+            base_mip: 0,
+            mip_count: u32::MAX,
+            base_layer: 0,
+            layer_count: u32::MAX,
+        }
+    }
+
+    /// A single mip level, all layers.
+    pub fn mip(level: u32) -> SubresourceRange {
+        SubresourceRange::mips(level, 1)
+    }
+
+    /// A range of mip levels, all layers.
+    pub fn mips(base_mip: u32, mip_count: u32) -> SubresourceRange {
+        SubresourceRange {
+            base_mip,
+            mip_count,
+            ..SubresourceRange::all()
+        }
+    }
+
+    /// A single layer (or cube map face), all mip levels.
+    pub fn layer(layer: u32) -> SubresourceRange {
+        SubresourceRange {
+            base_layer: layer,
+            layer_count: 1,
+            ..SubresourceRange::all()
+        }
+    }
+
+    /// Restricts the range to a single layer.
+    pub fn with_layer(self, layer: u32) -> SubresourceRange {
+        SubresourceRange {
+            base_layer: layer,
+            layer_count: 1,
+            ..self
+        }
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == SubresourceRange::all()
+    }
+
+    /// Returns whether the two ranges have at least one subresource in common.
+    pub fn overlaps(&self, other: &SubresourceRange) -> bool {
+        intervals_overlap(self.base_mip, self.mip_count, other.base_mip, other.mip_count)
+            && intervals_overlap(
+                self.base_layer,
+                self.layer_count,
+                other.base_layer,
+                other.layer_count,
+            )
+    }
+}
+
+impl ::std::fmt::Display for SubresourceRange {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let fmt_interval = |base: u32, count: u32| match count {
+            u32::MAX if base == 0 => "*".to_owned(),
+            u32::MAX => format!("{}..", base),
+            1 => format!("{}", base),
+            count => format!("{}..{}", base, base.saturating_add(count)),
+        };
+        write!(
+            f,
+            "mip {} layer {}",
+            fmt_interval(self.base_mip, self.mip_count),
+            fmt_interval(self.base_layer, self.layer_count)
+        )
+    }
+}
@@ -0,0 +1,394 @@
+//! Per-pass CPU and GPU timing.
+//!
+//! CPU times are measured around the callbacks of each pass. GPU times are measured with
+//! timestamp queries: their results are only available a few frames later, so
+//! the report of a frame is complete only once the GPU has finished executing it.
+//!
+use gfx;
+use gl;
+use gl::types::*;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Timing of a pass during one frame.
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub name: String,
+    /// Start of the pass on the CPU, relative to the beginning of the execution of the graph.
+    pub cpu_start: Duration,
+    /// Time spent in the pass callbacks on the CPU.
+    pub cpu_time: Duration,
+    /// Start of the pass on the GPU, relative to the beginning of the execution of the graph.
+    /// `None` if timer queries are not available.
+    pub gpu_start: Option<Duration>,
+    /// Time spent by the GPU executing the commands of the pass.
+    pub gpu_time: Option<Duration>,
+}
+
+/// Timings of all passes executed during one frame, in execution order.
+#[derive(Clone, Debug, Default)]
+pub struct FrameReport {
+    /// Index of the frame, counted by the profiler.
+    pub frame: u64,
+    pub passes: Vec<PassTiming>,
+}
+
+/// Average timings of a pass over the last frames.
+#[derive(Copy, Clone, Debug)]
+pub struct PassAverage {
+    pub cpu_time: Duration,
+    pub gpu_time: Option<Duration>,
+    /// Number of frames in which the pass was executed.
+    pub samples: usize,
+}
+
+fn duration_to_us(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e6 + d.subsec_nanos() as f64 / 1e3
+}
+
+fn duration_from_ns(ns: u64) -> Duration {
+    Duration::new(ns / 1_000_000_000, (ns % 1_000_000_000) as u32)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl FrameReport {
+    /// Returns the timing of the pass with the specified name.
+    pub fn pass(&self, name: &str) -> Option<&PassTiming> {
+        self.passes.iter().find(|p| p.name == name)
+    }
+
+    /// Total CPU time spent in passes.
+    pub fn cpu_time(&self) -> Duration {
+        self.passes.iter().map(|p| p.cpu_time).sum()
+    }
+
+    /// Total GPU time spent in passes, if known.
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.passes
+            .iter()
+            .map(|p| p.gpu_time)
+            .collect::<Option<Vec<_>>>()
+            .map(|times| times.into_iter().sum())
+    }
+
+    /// Writes the report in the Chrome trace event format (load it in `chrome://tracing`).
+    /// CPU and GPU timings appear on two different threads.
+    pub fn write_chrome_trace<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_trace_header(w)?;
+        self.write_trace_events(w, Duration::new(0, 0))?;
+        write_trace_footer(w)
+    }
+
+    /// Writes the events of the passes, shifted by `offset`.
+    /// Returns the end of the frame (including `offset`).
+    fn write_trace_events<W: Write>(&self, w: &mut W, offset: Duration) -> io::Result<Duration> {
+        let mut frame_end = offset;
+        for p in self.passes.iter() {
+            let cpu_start = p.cpu_start + offset;
+            frame_end = ::std::cmp::max(frame_end, cpu_start + p.cpu_time);
+            write!(
+                w,
+                ",\n  {{\"name\": \"{}\", \"cat\": \"cpu\", \"ph\": \"X\", \"pid\": 0, \"tid\": 0, \"ts\": {:.3}, \"dur\": {:.3}, \"args\": {{\"frame\": {}}}}}",
+                escape(&p.name),
+                duration_to_us(cpu_start),
+                duration_to_us(p.cpu_time),
+                self.frame
+            )?;
+            if let (Some(start), Some(time)) = (p.gpu_start, p.gpu_time) {
+                let gpu_start = start + offset;
+                frame_end = ::std::cmp::max(frame_end, gpu_start + time);
+                write!(
+                    w,
+                    ",\n  {{\"name\": \"{}\", \"cat\": \"gpu\", \"ph\": \"X\", \"pid\": 0, \"tid\": 1, \"ts\": {:.3}, \"dur\": {:.3}, \"args\": {{\"frame\": {}}}}}",
+                    escape(&p.name),
+                    duration_to_us(gpu_start),
+                    duration_to_us(time),
+                    self.frame
+                )?;
+            }
+        }
+        Ok(frame_end)
+    }
+}
+
+/// Writes the beginning of a Chrome trace, with the names of the CPU and GPU threads.
+fn write_trace_header<W: Write>(w: &mut W) -> io::Result<()> {
+    writeln!(w, "{{\"traceEvents\": [")?;
+    writeln!(
+        w,
+        "  {{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 0, \"tid\": 0, \"args\": {{\"name\": \"CPU\"}}}},"
+    )?;
+    write!(
+        w,
+        "  {{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 0, \"tid\": 1, \"args\": {{\"name\": \"GPU\"}}}}"
+    )
+}
+
+fn write_trace_footer<W: Write>(w: &mut W) -> io::Result<()> {
+    writeln!(w)?;
+    writeln!(w, "]}}")
+}
+
+/// Timestamp queries issued around the passes of a frame.
+struct PendingFrame {
+    report: FrameReport,
+    /// Query issued at the beginning of the frame.
+    start_query: GLuint,
+    /// Queries issued before and after each pass, in the same order as `report.passes`.
+    pass_queries: Vec<(GLuint, GLuint)>,
+}
+
+/// Collects the timings of the passes of a frame graph over several frames.
+/// Pass it to `CompiledFrameGraph::execute_profiled`.
+pub struct FrameProfiler {
+    /// Number of frames kept for averages.
+    window: usize,
+    gpu_timing: bool,
+    frame_count: u64,
+    /// The frame being executed.
+    current: Option<(Instant, PendingFrame)>,
+    /// The pass being executed, with its start time.
+    current_pass: Option<(String, Instant, Option<GLuint>)>,
+    /// Frames waiting for the results of their GPU queries.
+    pending: VecDeque<PendingFrame>,
+    /// Complete reports of the last frames, most recent last.
+    history: VecDeque<FrameReport>,
+    free_queries: Vec<GLuint>,
+}
+
+impl FrameProfiler {
+    /// Creates a profiler measuring CPU and GPU times (if the driver supports timer queries).
+    /// Averages are computed over the last `window` frames.
+    /// Null contexts have no GPU: only CPU times are measured.
+    pub fn new(gctx: &gfx::Context, window: usize) -> FrameProfiler {
+        let mut profiler = FrameProfiler::cpu_only(window);
+        profiler.gpu_timing = !gctx.is_null()
+            && gl::QueryCounter::is_loaded()
+            && gl::GetQueryObjectui64v::is_loaded();
+        if !profiler.gpu_timing {
+            warn!("timer queries are not available: only CPU times will be measured");
+        }
+        profiler
+    }
+
+    /// Creates a profiler that only measures CPU times. Does not need a GPU context.
+    pub fn cpu_only(window: usize) -> FrameProfiler {
+        FrameProfiler {
+            window: ::std::cmp::max(window, 1),
+            gpu_timing: false,
+            frame_count: 0,
+            current: None,
+            current_pass: None,
+            pending: VecDeque::new(),
+            history: VecDeque::new(),
+            free_queries: Vec::new(),
+        }
+    }
+
+    fn timestamp(&mut self) -> Option<GLuint> {
+        if !self.gpu_timing {
+            return None;
+        }
+        let query = self.free_queries.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            unsafe {
+                gl::GenQueries(1, &mut query);
+            }
+            query
+        });
+        unsafe {
+            gl::QueryCounter(query, gl::TIMESTAMP);
+        }
+        Some(query)
+    }
+
+    pub(super) fn begin_frame(&mut self) {
+        self.collect();
+        let frame = self.frame_count;
+        self.frame_count += 1;
+        let start_query = self.timestamp().unwrap_or(0);
+        self.current = Some((
+            Instant::now(),
+            PendingFrame {
+                report: FrameReport {
+                    frame,
+                    passes: Vec::new(),
+                },
+                start_query,
+                pass_queries: Vec::new(),
+            },
+        ));
+    }
+
+    pub(super) fn begin_pass(&mut self, name: &str) {
+        let query = self.timestamp();
+        self.current_pass = Some((name.to_owned(), Instant::now(), query));
+    }
+
+    pub(super) fn end_pass(&mut self) {
+        let end = Instant::now();
+        let end_query = self.timestamp();
+        let (name, start, start_query) = self.current_pass.take().expect("no pass started");
+        let &mut (frame_start, ref mut pending) =
+            self.current.as_mut().expect("no frame started");
+        pending.report.passes.push(PassTiming {
+            name,
+            cpu_start: start.duration_since(frame_start),
+            cpu_time: end.duration_since(start),
+            gpu_start: None,
+            gpu_time: None,
+        });
+        if let (Some(b), Some(e)) = (start_query, end_query) {
+            pending.pass_queries.push((b, e));
+        }
+    }
+
+    pub(super) fn end_frame(&mut self) {
+        let (_, pending) = self.current.take().expect("no frame started");
+        if self.gpu_timing {
+            self.pending.push_back(pending);
+        } else {
+            self.push_report(pending.report);
+        }
+        self.collect();
+    }
+
+    fn push_report(&mut self, report: FrameReport) {
+        self.history.push_back(report);
+        while self.history.len() > self.window {
+            self.history.pop_front();
+        }
+    }
+
+    /// Reads the results of the GPU queries of the frames that have finished executing.
+    fn collect(&mut self) {
+        while let Some(mut pending) = self.pending.pop_front() {
+            let last_query = pending
+                .pass_queries
+                .last()
+                .map_or(pending.start_query, |&(_, e)| e);
+            let mut available = 0;
+            unsafe {
+                gl::GetQueryObjectuiv(last_query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            }
+            if available == 0 {
+                // later frames are not finished either
+                self.pending.push_front(pending);
+                break;
+            }
+
+            let read = |query: GLuint| {
+                let mut t: GLuint64 = 0;
+                unsafe {
+                    gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut t);
+                }
+                t
+            };
+            let frame_start = read(pending.start_query);
+            for (pass, &(b, e)) in pending
+                .report
+                .passes
+                .iter_mut()
+                .zip(pending.pass_queries.iter())
+            {
+                let (tb, te) = (read(b), read(e));
+                pass.gpu_start = Some(duration_from_ns(tb.saturating_sub(frame_start)));
+                pass.gpu_time = Some(duration_from_ns(te.saturating_sub(tb)));
+            }
+
+            self.free_queries.push(pending.start_query);
+            for &(b, e) in pending.pass_queries.iter() {
+                self.free_queries.push(b);
+                self.free_queries.push(e);
+            }
+            self.push_report(pending.report);
+        }
+    }
+
+    /// Returns the most recent complete report.
+    /// With GPU timing, this is usually the report of a frame executed a few frames ago.
+    pub fn latest_report(&self) -> Option<&FrameReport> {
+        self.history.back()
+    }
+
+    /// Returns the complete reports of the last frames, oldest first.
+    pub fn reports(&self) -> &VecDeque<FrameReport> {
+        &self.history
+    }
+
+    /// Returns the average timings of the specified pass over the last frames.
+    /// The GPU time is `None` if it was not measured in all frames.
+    pub fn average(&self, pass: &str) -> Option<PassAverage> {
+        let timings: Vec<&PassTiming> = self
+            .history
+            .iter()
+            .filter_map(|report| report.pass(pass))
+            .collect();
+        if timings.is_empty() {
+            return None;
+        }
+        let n = timings.len() as u32;
+        let cpu_time = timings.iter().map(|t| t.cpu_time).sum::<Duration>() / n;
+        let gpu_time = timings
+            .iter()
+            .map(|t| t.gpu_time)
+            .collect::<Option<Vec<_>>>()
+            .map(|times| times.into_iter().sum::<Duration>() / n);
+        Some(PassAverage {
+            cpu_time,
+            gpu_time,
+            samples: timings.len(),
+        })
+    }
+
+    /// Returns the average timings of all passes that appear in the last frames, by name,
+    /// in the execution order of the most recent frame.
+    pub fn averages(&self) -> Vec<(String, PassAverage)> {
+        let mut names: Vec<&str> = Vec::new();
+        for report in self.history.iter().rev() {
+            for p in report.passes.iter() {
+                if !names.contains(&p.name.as_str()) {
+                    names.push(&p.name);
+                }
+            }
+        }
+        names
+            .into_iter()
+            .map(|name| (name.to_owned(), self.average(name).unwrap()))
+            .collect()
+    }
+
+    /// Writes the complete reports of the last frames in the Chrome trace event format.
+    /// The frames are laid out one after the other, and each event keeps the index of its frame.
+    pub fn write_chrome_trace<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_trace_header(w)?;
+        let mut offset = Duration::new(0, 0);
+        for report in self.history.iter() {
+            offset = report.write_trace_events(w, offset)?;
+        }
+        write_trace_footer(w)
+    }
+}
+
+impl Drop for FrameProfiler {
+    fn drop(&mut self) {
+        if !self.gpu_timing {
+            return;
+        }
+        let mut queries = self.free_queries.clone();
+        for pending in self.pending.iter() {
+            queries.push(pending.start_query);
+            for &(b, e) in pending.pass_queries.iter() {
+                queries.push(b);
+                queries.push(e);
+            }
+        }
+        unsafe {
+            gl::DeleteQueries(queries.len() as i32, queries.as_ptr());
+        }
+    }
+}
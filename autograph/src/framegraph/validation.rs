@@ -0,0 +1,218 @@
+//! Validation of the resources accessed by pass callbacks.
+//!
+//! When a graph is executed with `CompiledFrameGraph::execute_validated`, every resource
+//! lookup through `ExecutionContext` and every texture or buffer bound by the commands of
+//! a pass is checked against the resources declared by the pass.
+//! Lookups of undeclared resources panic; undeclared objects bound by commands are
+//! only detected at the end of the pass, and are reported.
+//!
+use super::*;
+
+/// A pass used a resource that it did not declare.
+/// Since the frame graph does not know about the access, the resource may be aliased
+/// with another one, or not yet produced.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UndeclaredAccess {
+    pub pass: String,
+    pub resource: String,
+}
+
+/// A pass declared that it reads a resource, but never used it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnusedInput {
+    pub pass: String,
+    pub resource: String,
+}
+
+/// Result of a validated execution of a frame graph.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub undeclared_accesses: Vec<UndeclaredAccess>,
+    pub unused_inputs: Vec<UnusedInput>,
+}
+
+impl ::std::fmt::Display for UndeclaredAccess {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "pass `{}` accesses undeclared resource `{}`",
+            self.pass, self.resource
+        )
+    }
+}
+
+impl ValidationReport {
+    /// Returns true if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.undeclared_accesses.is_empty() && self.unused_inputs.is_empty()
+    }
+}
+
+impl ::std::fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        for access in self.undeclared_accesses.iter() {
+            writeln!(f, "{}", access)?;
+        }
+        for input in self.unused_inputs.iter() {
+            writeln!(
+                f,
+                "pass `{}` does not use its input `{}`",
+                input.pass, input.resource
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the accesses of the pass being executed.
+pub(super) struct Validator {
+    pass: Option<NodeIndex>,
+    /// Position of the pass in the execution order.
+    position: i32,
+    accessed: HashSet<UnversionedResourceIndex>,
+    report: ValidationReport,
+}
+
+impl Validator {
+    pub(super) fn new() -> Validator {
+        Validator {
+            pass: None,
+            position: 0,
+            accessed: HashSet::new(),
+            report: ValidationReport::default(),
+        }
+    }
+
+    pub(super) fn begin_pass(&mut self, pass: NodeIndex, position: usize) {
+        self.pass = Some(pass);
+        self.position = position as i32;
+        self.accessed.clear();
+    }
+
+    /// Records an access to a resource by the current pass.
+    /// Returns an error if the pass did not declare the resource.
+    pub(super) fn record_access(
+        &mut self,
+        fg: &FrameGraph,
+        index: UnversionedResourceIndex,
+    ) -> Result<(), UndeclaredAccess> {
+        match self.pass {
+            Some(pass) => self.record_pass_access(fg, pass, index),
+            None => Ok(()),
+        }
+    }
+
+    fn record_pass_access(
+        &mut self,
+        fg: &FrameGraph,
+        pass: NodeIndex,
+        index: UnversionedResourceIndex,
+    ) -> Result<(), UndeclaredAccess> {
+        self.accessed.insert(index);
+        if fg.pass_declares(pass, index) {
+            return Ok(());
+        }
+        let access = UndeclaredAccess {
+            pass: fg.pass_name(pass).to_owned(),
+            resource: fg.resources[index.index()].name.clone(),
+        };
+        if !self.report.undeclared_accesses.contains(&access) {
+            error!("{}", access);
+            self.report.undeclared_accesses.push(access.clone());
+        }
+        Err(access)
+    }
+
+    /// Checks the objects used by the commands of the current pass, and the inputs
+    /// that it did not use.
+    pub(super) fn end_pass(
+        &mut self,
+        fg: &FrameGraph,
+        allocator: &FrameGraphAllocator,
+        used_objects: &[gfx::UsedObject],
+    ) {
+        let pass = match self.pass.take() {
+            Some(pass) => pass,
+            None => return,
+        };
+
+        for &object in used_objects.iter() {
+            // resources backed by this object (several resources can share an allocation)
+            let candidates: Vec<UnversionedResourceIndex> = (0..fg.resources.len())
+                .map(UnversionedResourceIndex::new)
+                .filter(|&index| resource_object(fg, allocator, index) == Some(object))
+                .collect();
+            let index = candidates
+                .iter()
+                .find(|&&index| fg.pass_declares(pass, index))
+                .or_else(|| {
+                    // not declared: blame the resource that is alive during the pass
+                    candidates.iter().find(|&&index| {
+                        fg.resources[index.index()]
+                            .lifetime
+                            .map_or(false, |l| l.begin <= self.position && self.position <= l.end)
+                    })
+                })
+                .or_else(|| candidates.first())
+                .cloned();
+            if let Some(index) = index {
+                // the commands were already sent: the access can only be reported
+                let _ = self.record_pass_access(fg, pass, index);
+            }
+        }
+
+        for input in fg.graph.edges_directed(pass, Direction::Incoming) {
+            if input.weight().access != ResourceAccess::Read {
+                continue;
+            }
+            let (index, _) = fg.resource_version(input.source());
+            let input = UnusedInput {
+                pass: fg.pass_name(pass).to_owned(),
+                resource: fg.resources[index.index()].name.clone(),
+            };
+            if !self.accessed.contains(&index) && !self.report.unused_inputs.contains(&input) {
+                warn!(
+                    "pass `{}` does not use its input `{}`",
+                    input.pass, input.resource
+                );
+                self.report.unused_inputs.push(input);
+            }
+        }
+    }
+
+    pub(super) fn finish(self) -> ValidationReport {
+        self.report
+    }
+}
+
+/// Returns the GL object backing a resource, if it was allocated.
+fn resource_object(
+    fg: &FrameGraph,
+    allocator: &FrameGraphAllocator,
+    index: UnversionedResourceIndex,
+) -> Option<gfx::UsedObject> {
+    let resource = &fg.resources[index.index()];
+    let aliased = if let Some(ref imported) = resource.imported {
+        imported
+    } else if let Some(ref slot) = resource.history {
+        allocator.history[&slot.key].texture(slot.frames_ago)
+    } else {
+        match resource.aliased_index.get() {
+            Some(aliased_index) => &allocator.allocations[aliased_index.index()],
+            None => return None,
+        }
+    };
+    Some(match *aliased {
+        AliasedResource::Texture { ref tex } => gfx::UsedObject::Texture(tex.gl_object()),
+        AliasedResource::Buffer { ref buf } => gfx::UsedObject::Buffer(buf.gl_object()),
+    })
+}
+
+impl<'node> FrameGraph<'node> {
+    /// Returns whether a pass reads, writes or creates any version of a resource.
+    fn pass_declares(&self, pass: NodeIndex, index: UnversionedResourceIndex) -> bool {
+        self.graph
+            .neighbors_undirected(pass)
+            .any(|n| self.resource_version(n).0 == index)
+    }
+}
@@ -1,6 +1,8 @@
+use gfx::null::Command;
 use gfx::{
-    BufferData, BufferSliceAny, Framebuffer, FramebufferObject, GraphicsPipeline,
-    GraphicsShaderPipeline, Sampler, TextureAny,
+    BlendState, BufferData, BufferSliceAny, ComputePipeline, ComputeShaderPipeline, Context,
+    DepthStencilState, Framebuffer, FramebufferObject, GlFormatInfo, GraphicsPipeline,
+    GraphicsShaderPipeline, RasterizerState, Sampler, TextureAny, MAX_BLEND_STATES,
 };
 use gl;
 use gl::types::*;
@@ -55,11 +57,20 @@ bitflags! {
         const SG_VERTEX_BUFFERS = (1 << 12); // DONE
         const SG_INDEX_BUFFER = (1 << 13); // DONE
         const SG_IMAGE = (1 << 14); // DONE
+        const SG_ALL_GRAPHICS_PIPELINE = SG_BLEND_STATE.bits | SG_RASTERIZER_STATE.bits | SG_DEPTH_STENCIL_STATE.bits | SG_VERTEX_ARRAY.bits | SG_PROGRAM.bits;
         const SG_ALL_COMPUTE = SG_IMAGE.bits | SG_TEXTURES.bits | SG_SAMPLERS.bits | SG_PROGRAM.bits | SG_UNIFORM_BUFFERS.bits | SG_SHADER_STORAGE_BUFFERS.bits;
         const SG_ALL = 0xFFFFFFF;
     }
 }
 
+unsafe fn set_enabled(cap: GLenum, enabled: bool) {
+    if enabled {
+        gl::Enable(cap);
+    } else {
+        gl::Disable(cap);
+    }
+}
+
 pub(super) unsafe fn bind_graphics_pipeline(pipe: &GraphicsPipeline, mask: StateGroupMask) {
     if mask.contains(SG_BLEND_STATE) {
         gl::Enable(gl::BLEND); // XXX is this necessary
@@ -95,16 +106,57 @@ pub(super) unsafe fn bind_graphics_pipeline(pipe: &GraphicsPipeline, mask: State
 
         gl::DepthFunc(pipe.depth_stencil_state.depth_test_func);
 
-        if pipe.depth_stencil_state.stencil_enable {
-            unimplemented!("Stencil buffers")
+        let dss = &pipe.depth_stencil_state;
+        if dss.stencil_enable {
+            gl::Enable(gl::STENCIL_TEST);
+            let faces = [(gl::FRONT, dss.stencil_front), (gl::BACK, dss.stencil_back)];
+            for &(face, ref ops) in faces.iter() {
+                gl::StencilFuncSeparate(
+                    face,
+                    ops.compare_func,
+                    dss.stencil_ref,
+                    dss.stencil_read_mask,
+                );
+                gl::StencilOpSeparate(face, ops.stencil_fail_op, ops.depth_fail_op, ops.pass_op);
+            }
+            gl::StencilMask(dss.stencil_write_mask);
         } else {
             gl::Disable(gl::STENCIL_TEST);
         }
     }
 
     if mask.contains(SG_RASTERIZER_STATE) {
-        gl::PolygonMode(gl::FRONT_AND_BACK, pipe.rasterizer_state.fill_mode);
-        gl::Disable(gl::CULL_FACE);
+        let rs = &pipe.rasterizer_state;
+        gl::PolygonMode(gl::FRONT_AND_BACK, rs.fill_mode);
+        if rs.cull_mode == gl::NONE {
+            gl::Disable(gl::CULL_FACE);
+        } else {
+            gl::Enable(gl::CULL_FACE);
+            gl::CullFace(rs.cull_mode);
+        }
+        gl::FrontFace(rs.front_face);
+
+        let depth_bias_enable = rs.depth_bias != 0.0 || rs.slope_scaled_depth_bias != 0.0;
+        set_enabled(gl::POLYGON_OFFSET_FILL, depth_bias_enable);
+        set_enabled(gl::POLYGON_OFFSET_LINE, depth_bias_enable);
+        set_enabled(gl::POLYGON_OFFSET_POINT, depth_bias_enable);
+        if depth_bias_enable {
+            // glPolygonOffsetClamp is core since 4.6 only: ignore the clamp if not available
+            if rs.depth_bias_clamp != 0.0 && gl::PolygonOffsetClamp::is_loaded() {
+                gl::PolygonOffsetClamp(
+                    rs.slope_scaled_depth_bias,
+                    rs.depth_bias,
+                    rs.depth_bias_clamp,
+                );
+            } else {
+                gl::PolygonOffset(rs.slope_scaled_depth_bias, rs.depth_bias);
+            }
+        }
+
+        set_enabled(gl::DEPTH_CLAMP, rs.depth_clamp_enable);
+        gl::LineWidth(rs.line_width);
+        set_enabled(gl::MULTISAMPLE, rs.multisample_enable);
+        set_enabled(gl::SAMPLE_ALPHA_TO_COVERAGE, rs.alpha_to_coverage_enable);
     }
 
     if mask.contains(SG_VERTEX_ARRAY) {
@@ -174,42 +226,52 @@ pub(super) unsafe fn bind_vertex_input(vertex_input: &VertexInput) {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Scissors {
     All(Option<(i32, i32, i32, i32)>),
 }
 
-pub(super) unsafe fn bind_scissors(scissors: &Scissors) {
-    match scissors {
-        &Scissors::All(None) => gl::Disable(gl::SCISSOR_TEST),
-        &Scissors::All(Some((x, y, w, h))) => {
-            gl::Enable(gl::SCISSOR_TEST);
-            gl::Scissor(x, y, w, h);
-        }
-    }
-}
-
 pub(super) unsafe fn bind_target(framebuffer: &Framebuffer, viewport: &[(f32, f32, f32, f32)]) {
     gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer.obj);
     gl::ViewportArrayv(0, 8, viewport.as_ptr() as *const GLfloat);
 }
 
+/// Tracks the state bound on the context, to avoid redundant state changes.
 pub struct StateCache {
+    gctx: Context,
     /// All uniforms
     //uniforms: Option<Uniforms>,
     //vertex_input: Option<VertexInput>,
     framebuffer: Option<*const FramebufferObject>,
     pipeline: Option<*const super::pipeline::inner::GraphicsPipeline>,
+    compute_pipeline: Option<*const super::pipeline::inner::ComputePipeline>,
+    // state groups of graphics pipelines, compared individually when the pipeline changes
+    blend_states: Option<[BlendState; MAX_BLEND_STATES]>,
+    rasterizer_state: Option<RasterizerState>,
+    depth_stencil_state: Option<DepthStencilState>,
+    vao: Option<GLuint>,
+    program: Option<GLuint>,
     scissors: Option<Scissors>,
+    /// Whether `GL_SCISSOR_TEST` is enabled.
+    scissor_test: Option<bool>,
 }
 
 impl StateCache {
-    pub(super) fn new() -> StateCache {
+    pub(super) fn new(gctx: &Context) -> StateCache {
         StateCache {
+            gctx: gctx.clone(),
             //uniforms: None,
             //vertex_input: None,
             pipeline: None,
+            compute_pipeline: None,
             framebuffer: None,
+            blend_states: None,
+            rasterizer_state: None,
+            depth_stencil_state: None,
+            vao: None,
+            program: None,
             scissors: None,
+            scissor_test: None,
         }
     }
 
@@ -217,17 +279,81 @@ impl StateCache {
         // same pipeline as before?
         if self
             .pipeline
+            .map_or(false, |prev_pipe| prev_pipe == pipe.as_ref() as *const _)
+        {
+            return;
+        }
+
+        // nope, bind the state groups that differ from the current state
+        let program = pipe.shader_pipeline.get_program().unwrap();
+        let mut changed = StateGroupMask::empty();
+        if self.blend_states != Some(pipe.blend_states) {
+            changed |= SG_BLEND_STATE;
+        }
+        if self.rasterizer_state != Some(pipe.rasterizer_state) {
+            changed |= SG_RASTERIZER_STATE;
+        }
+        if self.depth_stencil_state != Some(pipe.depth_stencil_state) {
+            changed |= SG_DEPTH_STENCIL_STATE;
+        }
+        if self.vao != Some(pipe.vao) {
+            changed |= SG_VERTEX_ARRAY;
+        }
+        if self.program != Some(program) {
+            changed |= SG_PROGRAM;
+        }
+
+        if !changed.is_empty() {
+            match self.gctx.null_device() {
+                Some(null) => null.record(Command::BindGraphicsPipeline {
+                    vao: pipe.vao,
+                    program,
+                    changed,
+                }),
+                None => bind_graphics_pipeline(pipe, changed),
+            }
+        }
+
+        self.blend_states = Some(pipe.blend_states);
+        self.rasterizer_state = Some(pipe.rasterizer_state);
+        self.depth_stencil_state = Some(pipe.depth_stencil_state);
+        self.vao = Some(pipe.vao);
+        self.program = Some(program);
+        self.pipeline = Some(pipe.as_ref() as *const _);
+        self.compute_pipeline = None;
+        self.update_scissor_test();
+    }
+
+    pub unsafe fn set_compute_pipeline(&mut self, pipe: &ComputePipeline) {
+        if self
+            .compute_pipeline
             .map_or(true, |prev_pipe| prev_pipe != pipe.as_ref() as *const _)
         {
-            // nope, bind it
-            // TODO fine-grained state changes
-            bind_graphics_pipeline(pipe, SG_ALL);
-            self.pipeline = Some(pipe.as_ref() as *const _);
+            let program = pipe.shader_pipeline.get_program().unwrap();
+            if self.program != Some(program) {
+                match self.gctx.null_device() {
+                    Some(null) => null.record(Command::BindComputePipeline { program }),
+                    None => gl::UseProgram(program),
+                }
+            }
+            self.compute_pipeline = Some(pipe.as_ref() as *const _);
+            self.program = Some(program);
+            // the program of the graphics pipeline is not bound anymore
+            self.pipeline = None;
         }
     }
 
     pub unsafe fn set_uniform_buffer(&mut self, slot: u32, buffer: &BufferSliceAny) {
         // TODO batch and cache
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindUniformBuffer {
+                slot,
+                obj: buffer.owner.gl_object(),
+                offset: buffer.offset,
+                size: buffer.byte_size,
+            });
+            return;
+        }
         gl::BindBufferRange(
             gl::UNIFORM_BUFFER,
             slot,
@@ -239,6 +365,15 @@ impl StateCache {
 
     pub unsafe fn set_shader_storage_buffer(&mut self, slot: u32, buffer: &BufferSliceAny) {
         // TODO batch and cache
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindStorageBuffer {
+                slot,
+                obj: buffer.owner.gl_object(),
+                offset: buffer.offset,
+                size: buffer.byte_size,
+            });
+            return;
+        }
         gl::BindBufferRange(
             gl::SHADER_STORAGE_BUFFER,
             slot,
@@ -254,6 +389,15 @@ impl StateCache {
 
     pub unsafe fn set_vertex_buffer(&self, slot: u32, buffer: &BufferSliceAny, stride: usize) {
         // No caching
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindVertexBuffer {
+                slot,
+                obj: buffer.owner.gl_object(),
+                offset: buffer.offset,
+                stride,
+            });
+            return;
+        }
         gl::BindVertexBuffer(
             slot,
             buffer.owner.gl_object(),
@@ -264,15 +408,73 @@ impl StateCache {
 
     pub unsafe fn set_index_buffer(&self, buffer: &BufferSliceAny) {
         // TODO cache
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindIndexBuffer {
+                obj: buffer.owner.gl_object(),
+            });
+            return;
+        }
         gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, buffer.owner.gl_object());
     }
 
+    pub unsafe fn set_draw_indirect_buffer(&self, buffer: &BufferSliceAny) {
+        // No caching
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindDrawIndirectBuffer {
+                obj: buffer.owner.gl_object(),
+            });
+            return;
+        }
+        gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, buffer.owner.gl_object());
+    }
+
     pub unsafe fn set_texture(&self, slot: u32, texture: &TextureAny, sampler: &Sampler) {
         // TODO cache and batch
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindTexture {
+                slot,
+                obj: texture.gl_object(),
+                sampler: sampler.obj,
+            });
+            return;
+        }
         gl::BindTextureUnit(slot, texture.gl_object());
         gl::BindSampler(slot, sampler.obj);
     }
 
+    /// Binds a mip level of a texture to an image unit.
+    /// All layers are bound if `layer` is `None`.
+    pub unsafe fn set_image(
+        &self,
+        slot: u32,
+        texture: &TextureAny,
+        mip_level: u32,
+        layer: Option<u32>,
+        access: GLenum,
+    ) {
+        // TODO cache and batch
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::BindImage {
+                slot,
+                obj: texture.gl_object(),
+                mip_level,
+                layer,
+                access,
+            });
+            return;
+        }
+        let glfmt = GlFormatInfo::from_format(texture.desc().format);
+        gl::BindImageTexture(
+            slot,
+            texture.gl_object(),
+            mip_level as i32,
+            layer.is_none() as GLboolean,
+            layer.unwrap_or(0) as i32,
+            access,
+            glfmt.internal_fmt,
+        );
+    }
+
     pub unsafe fn set_target(
         &mut self,
         framebuffer: &Framebuffer,
@@ -283,14 +485,43 @@ impl StateCache {
             prev_framebuffer != framebuffer.as_ref() as *const _
         }) {
             // nope, bind it
-            bind_target(framebuffer, viewport);
+            match self.gctx.null_device() {
+                Some(null) => null.record(Command::BindTarget {
+                    framebuffer: framebuffer.obj,
+                    viewports: viewport.to_vec(),
+                }),
+                None => bind_target(framebuffer, viewport),
+            }
             self.framebuffer = Some(framebuffer.as_ref() as *const _);
         }
     }
 
     pub unsafe fn set_scissors(&mut self, scissors: &Scissors) {
-        // TODO cache
-        bind_scissors(scissors);
+        if self.scissors == Some(*scissors) {
+            return;
+        }
+        let &Scissors::All(rect) = scissors;
+        match self.gctx.null_device() {
+            Some(null) => null.record(Command::SetScissors(rect)),
+            None => if let Some((x, y, w, h)) = rect {
+                gl::Scissor(x, y, w, h);
+            },
+        }
+        self.scissors = Some(*scissors);
+        self.update_scissor_test();
+    }
+
+    /// Enables the scissor test if the current pipeline allows it and there is a scissor rectangle.
+    unsafe fn update_scissor_test(&mut self) {
+        let enabled = self.rasterizer_state.map_or(false, |rs| rs.scissor_enable)
+            && self.scissors.map_or(false, |Scissors::All(rect)| rect.is_some());
+        if self.scissor_test != Some(enabled) {
+            match self.gctx.null_device() {
+                Some(null) => null.record(Command::SetScissorTest(enabled)),
+                None => set_enabled(gl::SCISSOR_TEST, enabled),
+            }
+            self.scissor_test = Some(enabled);
+        }
     }
 
     /// Commit all uniforms
@@ -299,18 +530,50 @@ impl StateCache {
     }
 
     pub unsafe fn set_uniform_f32(&self, program: u32, location: u32, v: f32) {
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::SetUniform {
+                program,
+                location,
+                value: vec![v],
+            });
+            return;
+        }
         gl::ProgramUniform1f(program, location as i32, v);
     }
 
     pub unsafe fn set_uniform_vec2(&self, program: u32, location: u32, v: [f32; 2]) {
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::SetUniform {
+                program,
+                location,
+                value: v.to_vec(),
+            });
+            return;
+        }
         gl::ProgramUniform2f(program, location as i32, v[0], v[1]);
     }
 
     pub unsafe fn set_uniform_vec3(&self, program: u32, location: u32, v: [f32; 3]) {
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::SetUniform {
+                program,
+                location,
+                value: v.to_vec(),
+            });
+            return;
+        }
         gl::ProgramUniform3f(program, location as i32, v[0], v[1], v[2]);
     }
 
     pub unsafe fn set_uniform_vec4(&self, program: u32, location: u32, v: [f32; 4]) {
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::SetUniform {
+                program,
+                location,
+                value: v.to_vec(),
+            });
+            return;
+        }
         gl::ProgramUniform4f(program, location as i32, v[0], v[1], v[2], v[3]);
     }
 
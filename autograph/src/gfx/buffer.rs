@@ -8,6 +8,7 @@ use std::marker::PhantomData;
 use std::mem;
 use std::ops::Deref;
 use std::os::raw::c_void;
+use std::slice;
 use std::sync::Arc;
 
 macro_rules! deref_to {
@@ -44,10 +45,17 @@ pub struct RawBufferObject {
 }
 
 unsafe fn create_buffer<T: BufferData + ?Sized>(
+    gctx: &Context,
     byte_size: usize,
     usage: BufferUsage,
     initial_data: Option<&T>,
 ) -> GLuint {
+    if let Some(null) = gctx.null_device() {
+        let initial_data =
+            initial_data.map(|data| slice::from_raw_parts(data as *const T as *const u8, byte_size));
+        return null.create_buffer(byte_size, initial_data);
+    }
+
     let mut obj: GLuint = 0;
     let flags = match usage {
         BufferUsage::READBACK => gl::MAP_READ_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT,
@@ -129,7 +137,7 @@ impl RawBufferObject {
     pub fn new(gctx: &Context, byte_size: usize, usage: BufferUsage) -> RawBufferObject {
         RawBufferObject {
             gctx: gctx.clone(),
-            obj: unsafe { create_buffer::<u8>(byte_size, usage, None) },
+            obj: unsafe { create_buffer::<u8>(gctx, byte_size, usage, None) },
             byte_size,
             usage,
         }
@@ -143,7 +151,7 @@ impl RawBufferObject {
         let byte_size = mem::size_of_val(data);
         RawBufferObject {
             gctx: gctx.clone(),
-            obj: unsafe { create_buffer(mem::size_of_val(data), usage, Some(data)) },
+            obj: unsafe { create_buffer(gctx, mem::size_of_val(data), usage, Some(data)) },
             byte_size,
             usage,
         }
@@ -169,6 +177,10 @@ impl RawBufferObject {
             }
         };
 
+        if let Some(null) = self.gctx.null_device() {
+            return null.map_buffer(self.obj);
+        }
+
         gl::MapNamedBufferRange(self.obj, 0, self.byte_size() as isize, flags)
     }
 
@@ -313,6 +325,10 @@ impl<T: BufferData + ?Sized> AsSlice<T> for Arc<Buffer<T>> {
 
 impl Drop for RawBufferObject {
     fn drop(&mut self) {
+        if let Some(null) = self.gctx.null_device() {
+            null.delete_buffer(self.obj);
+            return;
+        }
         unsafe {
             gl::DeleteBuffers(1, &self.obj);
         }
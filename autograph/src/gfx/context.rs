@@ -1,3 +1,5 @@
+use super::headless::HeadlessContext;
+use super::null::{Command, NullDevice};
 use super::sampler::{Sampler, SamplerDesc};
 use cache::Cache;
 use gl;
@@ -35,42 +37,105 @@ pub struct ContextObject {
     sampler_cache: Mutex<HashMap<SamplerDesc, Arc<Sampler>>>,
     /// cache for objects used internally by gfx (pipelines, etc.)
     cache: Cache,
+    /// Command log, for contexts created with `Context::new_null`
+    null: Option<NullDevice>,
+    /// OpenGL context owned by this context, for contexts created with `Context::new_headless`.
+    /// Declared last, so that it is destroyed after the objects in the caches.
+    headless: Option<HeadlessContext>,
 }
 
-impl ContextObject {
-    pub fn new(cfg: &ContextConfig) -> Arc<ContextObject> {
-        unsafe {
-            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
-            gl::DebugMessageCallback(debug_callback as GLDEBUGPROC, 0 as *const c_void);
-            gl::DebugMessageControl(
-                gl::DONT_CARE,
-                gl::DONT_CARE,
-                gl::DONT_CARE,
-                0,
-                0 as *const u32,
-                1,
-            );
-        }
+unsafe fn enable_debug_output() {
+    gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    gl::DebugMessageCallback(debug_callback as GLDEBUGPROC, 0 as *const c_void);
+    gl::DebugMessageControl(
+        gl::DONT_CARE,
+        gl::DONT_CARE,
+        gl::DONT_CARE,
+        0,
+        0 as *const u32,
+        1,
+    );
+}
 
+impl ContextObject {
+    fn with_backend(
+        cfg: &ContextConfig,
+        null: Option<NullDevice>,
+        headless: Option<HeadlessContext>,
+    ) -> Arc<ContextObject> {
         Arc::new(ContextObject {
             cfg: *cfg,
             sampler_cache: Mutex::new(HashMap::new()),
             cache: Cache::new(),
+            null,
+            headless,
         })
     }
 
+    pub fn new(cfg: &ContextConfig) -> Arc<ContextObject> {
+        unsafe {
+            enable_debug_output();
+        }
+        ContextObject::with_backend(cfg, None, None)
+    }
+
+    /// Creates a context that owns the specified headless OpenGL context.
+    pub fn new_headless(cfg: &ContextConfig, headless: HeadlessContext) -> Arc<ContextObject> {
+        unsafe {
+            enable_debug_output();
+        }
+        ContextObject::with_backend(cfg, None, Some(headless))
+    }
+
+    /// Creates a context that records commands instead of calling OpenGL (see `gfx::null`).
+    /// No OpenGL context needs to be current.
+    pub fn new_null(cfg: &ContextConfig) -> Arc<ContextObject> {
+        ContextObject::with_backend(cfg, Some(NullDevice::new()), None)
+    }
+
     pub fn get_sampler(&self, desc: &SamplerDesc) -> Arc<Sampler> {
         self.sampler_cache
             .lock()
             .unwrap()
             .entry(*desc)
-            .or_insert_with(|| Arc::new(Sampler::new(desc)))
+            .or_insert_with(|| {
+                Arc::new(match self.null {
+                    Some(ref null) => Sampler {
+                        desc: *desc,
+                        obj: null.gen_object(),
+                    },
+                    None => Sampler::new(desc),
+                })
+            })
             .clone()
     }
 
     pub fn cache(&self) -> &Cache {
         &self.cache
     }
+
+    /// Returns true if the context owns a headless OpenGL context.
+    pub fn is_headless(&self) -> bool {
+        self.headless.is_some()
+    }
+
+    /// Returns true if the context was created with `Context::new_null`.
+    pub fn is_null(&self) -> bool {
+        self.null.is_some()
+    }
+
+    /// Returns the commands recorded since the last call, and clears the log.
+    /// Panics if the context was not created with `Context::new_null`.
+    pub fn take_commands(&self) -> Vec<Command> {
+        self.null
+            .as_ref()
+            .expect("not a null context")
+            .take_commands()
+    }
+
+    pub(super) fn null_device(&self) -> Option<&NullDevice> {
+        self.null.as_ref()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -80,6 +145,16 @@ impl Context {
     pub fn new(cfg: &ContextConfig) -> Context {
         Context(ContextObject::new(cfg))
     }
+
+    /// Creates a context that records commands instead of calling OpenGL (see `gfx::null`).
+    pub fn new_null(cfg: &ContextConfig) -> Context {
+        Context(ContextObject::new_null(cfg))
+    }
+
+    /// Creates a context that owns a headless OpenGL context (see `gfx::headless`).
+    pub fn new_headless(cfg: &ContextConfig, headless: HeadlessContext) -> Context {
+        Context(ContextObject::new_headless(cfg, headless))
+    }
 }
 
 impl Deref for Context {
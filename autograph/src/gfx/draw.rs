@@ -1,10 +1,8 @@
 use gfx::bind::StateCache;
-use gfx::bind::{
-    bind_graphics_pipeline, bind_scissors, bind_target, bind_uniforms, bind_vertex_input, SG_ALL,
-};
 use gfx::bind::{Scissors, Uniforms, VertexInput};
 use gfx::buffer_data::BufferData;
-use gfx::pipeline::{GraphicsPipeline, TypedGraphicsPipeline};
+use gfx::null::Command;
+use gfx::pipeline::{ComputePipeline, GraphicsPipeline, TypedGraphicsPipeline};
 use gfx::shader_interface::ShaderInterface;
 use gfx::Frame;
 use gfx::Framebuffer;
@@ -18,6 +16,30 @@ use std::cell::RefMut;
 use std::marker::PhantomData;
 use std::mem;
 
+bitflags! {
+    /// Memory barriers, as passed to `glMemoryBarrier`.
+    /// Required before reading data written by incoherent memory accesses
+    /// (image stores and shader storage buffer writes).
+    #[derive(Default)]
+    pub struct MemoryBarriers: u32 {
+        const MB_VERTEX_ATTRIB_ARRAY = gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT;
+        const MB_ELEMENT_ARRAY = gl::ELEMENT_ARRAY_BARRIER_BIT;
+        const MB_UNIFORM = gl::UNIFORM_BARRIER_BIT;
+        const MB_TEXTURE_FETCH = gl::TEXTURE_FETCH_BARRIER_BIT;
+        const MB_SHADER_IMAGE_ACCESS = gl::SHADER_IMAGE_ACCESS_BARRIER_BIT;
+        const MB_COMMAND = gl::COMMAND_BARRIER_BIT;
+        const MB_PIXEL_BUFFER = gl::PIXEL_BUFFER_BARRIER_BIT;
+        const MB_TEXTURE_UPDATE = gl::TEXTURE_UPDATE_BARRIER_BIT;
+        const MB_BUFFER_UPDATE = gl::BUFFER_UPDATE_BARRIER_BIT;
+        const MB_FRAMEBUFFER = gl::FRAMEBUFFER_BARRIER_BIT;
+        const MB_TRANSFORM_FEEDBACK = gl::TRANSFORM_FEEDBACK_BARRIER_BIT;
+        const MB_ATOMIC_COUNTER = gl::ATOMIC_COUNTER_BARRIER_BIT;
+        const MB_SHADER_STORAGE = gl::SHADER_STORAGE_BARRIER_BIT;
+        const MB_CLIENT_MAPPED_BUFFER = gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT;
+        const MB_QUERY_BUFFER = gl::QUERY_BUFFER_BARRIER_BIT;
+    }
+}
+
 pub enum DrawCmd {
     DrawArrays {
         first: usize,
@@ -28,6 +50,69 @@ pub enum DrawCmd {
         count: usize,
         base_vertex: usize,
     },
+    DrawArraysInstanced {
+        first: usize,
+        count: usize,
+        instance_count: usize,
+        base_instance: usize,
+    },
+    DrawIndexedInstanced {
+        first: usize,
+        count: usize,
+        base_vertex: usize,
+        instance_count: usize,
+        base_instance: usize,
+    },
+    /// Draws `draw_count` `DrawArraysIndirectCommand`s read from the indirect buffer,
+    /// starting at the `first` command.
+    DrawArraysIndirect {
+        first: usize,
+        draw_count: usize,
+    },
+    /// Draws `draw_count` `DrawIndexedIndirectCommand`s read from the indirect buffer,
+    /// starting at the `first` command.
+    DrawIndexedIndirect {
+        first: usize,
+        draw_count: usize,
+    },
+}
+
+/// Parameters of a non-indexed draw sourced from an indirect buffer.
+/// Layout matches `DrawArraysIndirectCommand` in the GL spec.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DrawArraysIndirectCommand {
+    pub count: u32,
+    pub instance_count: u32,
+    pub first: u32,
+    pub base_instance: u32,
+}
+
+/// Parameters of an indexed draw sourced from an indirect buffer.
+/// Layout matches `DrawElementsIndirectCommand` in the GL spec.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DrawIndexedIndirectCommand {
+    pub count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub base_instance: u32,
+}
+
+/// Element types of indirect buffers.
+/// Unsafe because GL reads the element layout directly.
+pub unsafe trait IndirectCommand: Copy + 'static {
+    /// Whether this is a command for indexed draws.
+    const INDEXED: bool;
+}
+
+unsafe impl IndirectCommand for DrawArraysIndirectCommand {
+    const INDEXED: bool = false;
+}
+
+unsafe impl IndirectCommand for DrawIndexedIndirectCommand {
+    const INDEXED: bool = true;
 }
 
 pub trait DrawExt<'queue> {
@@ -61,6 +146,19 @@ pub trait DrawExt<'queue> {
 
     fn clear_framebuffer_depth(&self, framebuffer: &Framebuffer, clear_depth: f32) -> &Self;
 
+    fn clear_framebuffer_stencil(&self, framebuffer: &Framebuffer, clear_stencil: i32) -> &Self;
+
+    fn clear_framebuffer_depth_stencil(
+        &self,
+        framebuffer: &Framebuffer,
+        clear_depth: f32,
+        clear_stencil: i32,
+    ) -> &Self;
+
+    /// Orders incoherent memory writes issued before this command with the accesses
+    /// issued after it (see `glMemoryBarrier`).
+    fn memory_barrier(&self, barriers: MemoryBarriers) -> &Self;
+
     /// Begins building a draw command.
     /// This function does not perform any type checking.
     ///fn begin_draw<'frame>(&'frame self, target: &Framebuffer, pipeline: &GraphicsPipeline) -> DrawCommandBuilder<'frame,'queue> where 'queue:'frame;
@@ -83,6 +181,30 @@ pub trait DrawExt<'queue> {
     ) -> DrawCmdBuilder<'frame, 'queue, 'pipeline>
     where
         'queue: 'frame;
+
+    /// Begins building a compute dispatch of the specified number of work groups.
+    fn dispatch<'frame>(
+        &'frame self,
+        pipeline: &ComputePipeline,
+        num_groups: (u32, u32, u32),
+    ) -> DispatchCmdBuilder<'frame, 'queue>
+    where
+        'queue: 'frame;
+}
+
+/// Calls `f` with depth and stencil writes enabled.
+/// Framebuffer clears are masked by the depth and stencil write masks,
+/// which are left as set by the last graphics pipeline.
+unsafe fn with_depth_stencil_writes<F: FnOnce()>(f: F) {
+    let mut depth_write_mask = 0;
+    let mut stencil_write_mask = 0;
+    gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_write_mask);
+    gl::GetIntegerv(gl::STENCIL_WRITEMASK, &mut stencil_write_mask);
+    gl::DepthMask(gl::TRUE);
+    gl::StencilMask(!0);
+    f();
+    gl::DepthMask(depth_write_mask);
+    gl::StencilMask(stencil_write_mask as u32);
 }
 
 impl<'queue> DrawExt<'queue> for Frame<'queue> {
@@ -93,6 +215,13 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
         mip_level: usize,
         clear_color: &[f32; 4],
     ) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearTexture {
+                obj: texture.obj,
+                mip_level,
+            });
+            return self;
+        }
         unsafe {
             gl::ClearTexImage(
                 texture.obj,
@@ -111,6 +240,13 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
         mip_level: usize,
         clear_color: &[i32; 4],
     ) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearTexture {
+                obj: texture.obj,
+                mip_level,
+            });
+            return self;
+        }
         unsafe {
             gl::ClearTexImage(
                 texture.obj,
@@ -129,6 +265,13 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
         mip_level: usize,
         clear_depth: f32,
     ) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearTexture {
+                obj: texture.obj,
+                mip_level,
+            });
+            return self;
+        }
         unsafe {
             gl::ClearTexImage(
                 texture.obj,
@@ -147,6 +290,14 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
         drawbuffer: usize,
         clear_color: &[f32; 4],
     ) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearFramebuffer {
+                obj: framebuffer.gl_object(),
+                buffer: gl::COLOR,
+                drawbuffer,
+            });
+            return self;
+        }
         unsafe {
             gl::ClearNamedFramebufferfv(
                 framebuffer.gl_object(),
@@ -159,13 +310,85 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
     }
 
     fn clear_framebuffer_depth(&self, framebuffer: &Framebuffer, clear_depth: f32) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearFramebuffer {
+                obj: framebuffer.gl_object(),
+                buffer: gl::DEPTH,
+                drawbuffer: 0,
+            });
+            return self;
+        }
         unsafe {
-            gl::ClearNamedFramebufferfv(
-                framebuffer.gl_object(),
-                gl::DEPTH,
-                0,
-                &clear_depth as *const f32,
-            );
+            with_depth_stencil_writes(|| {
+                gl::ClearNamedFramebufferfv(
+                    framebuffer.gl_object(),
+                    gl::DEPTH,
+                    0,
+                    &clear_depth as *const f32,
+                );
+            });
+        }
+        self
+    }
+
+    fn clear_framebuffer_stencil(&self, framebuffer: &Framebuffer, clear_stencil: i32) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearFramebuffer {
+                obj: framebuffer.gl_object(),
+                buffer: gl::STENCIL,
+                drawbuffer: 0,
+            });
+            return self;
+        }
+        unsafe {
+            with_depth_stencil_writes(|| {
+                gl::ClearNamedFramebufferiv(
+                    framebuffer.gl_object(),
+                    gl::STENCIL,
+                    0,
+                    &clear_stencil as *const i32,
+                );
+            });
+        }
+        self
+    }
+
+    fn clear_framebuffer_depth_stencil(
+        &self,
+        framebuffer: &Framebuffer,
+        clear_depth: f32,
+        clear_stencil: i32,
+    ) -> &Self {
+        if let Some(null) = self.queue().context().null_device() {
+            null.record(Command::ClearFramebuffer {
+                obj: framebuffer.gl_object(),
+                buffer: gl::DEPTH_STENCIL,
+                drawbuffer: 0,
+            });
+            return self;
+        }
+        unsafe {
+            with_depth_stencil_writes(|| {
+                gl::ClearNamedFramebufferfi(
+                    framebuffer.gl_object(),
+                    gl::DEPTH_STENCIL,
+                    0,
+                    clear_depth,
+                    clear_stencil,
+                );
+            });
+        }
+        self
+    }
+
+    fn memory_barrier(&self, barriers: MemoryBarriers) -> &Self {
+        if !barriers.is_empty() {
+            match self.queue().context().null_device() {
+                Some(null) => null.record(Command::MemoryBarrier(barriers)),
+                None => unsafe {
+                    gl::MemoryBarrier(barriers.bits());
+                },
+            }
         }
         self
     }
@@ -196,6 +419,8 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
             index_buffer_offset: None,
             index_stride: None,
             index_buffer_type: None,
+            indirect_buffer: None,
+            submitted: false,
         }
     }
 
@@ -233,10 +458,45 @@ impl<'queue> DrawExt<'queue> for Frame<'queue> {
             index_buffer_offset: None,
             index_stride: None,
             index_buffer_type: None,
+            indirect_buffer: None,
+            submitted: false,
+        }
+    }
+
+    fn dispatch<'frame>(
+        &'frame self,
+        pipeline: &ComputePipeline,
+        num_groups: (u32, u32, u32),
+    ) -> DispatchCmdBuilder<'frame, 'queue>
+    where
+        'queue: 'frame,
+    {
+        let mut state_cache = self.state_cache.borrow_mut();
+        unsafe {
+            state_cache.set_compute_pipeline(pipeline);
+        }
+
+        DispatchCmdBuilder {
+            frame: self,
+            state_cache,
+            num_groups,
+            submitted: false,
         }
     }
 }
 
+/// Errors detected when submitting a draw command.
+#[derive(Debug, Fail)]
+pub enum DrawError {
+    #[fail(
+        display = "indirect commands {}..{} are out of the bounds of the indirect buffer ({} commands)",
+        first,
+        end,
+        len
+    )]
+    IndirectCommandsOutOfBounds { first: usize, end: usize, len: usize },
+}
+
 /// Draw command builder.
 /// Statically locks the frame object: allocate your buffers before starting a command!
 #[must_use]
@@ -247,13 +507,27 @@ pub struct DrawCmdBuilder<'frame, 'queue: 'frame, 'binder> {
     index_buffer_type: Option<GLenum>,
     index_buffer_offset: Option<usize>,
     index_stride: Option<usize>,
+    indirect_buffer: Option<IndirectBuffer>,
     cmd: DrawCmd,
+    /// Set by `submit` to defuse the drop bomb.
+    submitted: bool,
+}
+
+/// Indirect buffer bound to a draw command.
+struct IndirectBuffer {
+    offset: usize,
+    stride: usize,
+    /// Number of commands in the buffer slice.
+    len: usize,
+    indexed: bool,
 }
 
 // Drop bomb
 impl<'frame, 'queue: 'frame, 'binder> Drop for DrawCmdBuilder<'frame, 'queue, 'binder> {
     fn drop(&mut self) {
-        panic!("unsubmitted draw command")
+        if !self.submitted {
+            panic!("unsubmitted draw command")
+        }
     }
 }
 
@@ -291,6 +565,63 @@ impl<'frame, 'queue: 'frame, 'binder> DrawCmdBuilder<'frame, 'queue, 'binder> {
         self
     }
 
+    /// Set a shader storage buffer to use for this command.
+    pub fn with_storage_buffer<S: ToBufferSlice>(self, slot: u32, buffer: &S) -> Self {
+        let buffer = unsafe { buffer.to_slice_any() };
+        self.with_storage_buffer_any(slot, &buffer)
+    }
+
+    /// Set a shader storage buffer (untyped slice) to use for this command.
+    pub fn with_storage_buffer_any(mut self, slot: u32, buffer: &BufferSliceAny) -> Self {
+        unsafe {
+            self.state_cache.set_shader_storage_buffer(slot, buffer);
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_buffer(buffer.owner.clone());
+        self
+    }
+
+    /// Binds the specified mip level of a texture to an image unit.
+    /// `access` is one of `gl::READ_ONLY`, `gl::WRITE_ONLY` or `gl::READ_WRITE`.
+    /// Writes are incoherent: insert a memory barrier before reading them in another command.
+    pub fn with_image(self, slot: u32, tex: &TextureAny, mip_level: u32, access: GLenum) -> Self {
+        self.with_image_impl(slot, tex, mip_level, None, access)
+    }
+
+    /// Binds a single layer of the specified mip level of an array, cube map or 3D texture
+    /// to an image unit.
+    pub fn with_image_layer(
+        self,
+        slot: u32,
+        tex: &TextureAny,
+        mip_level: u32,
+        layer: u32,
+        access: GLenum,
+    ) -> Self {
+        self.with_image_impl(slot, tex, mip_level, Some(layer), access)
+    }
+
+    fn with_image_impl(
+        mut self,
+        slot: u32,
+        tex: &TextureAny,
+        mip_level: u32,
+        layer: Option<u32>,
+        access: GLenum,
+    ) -> Self {
+        unsafe {
+            self.state_cache
+                .set_image(slot, tex, mip_level, layer, access);
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_texture(tex.clone());
+        self
+    }
+
     pub fn with_vertex_buffer<V: ToBufferSlice>(mut self, slot: u32, vertices: &V) -> Self {
         let vertices = unsafe { vertices.to_slice_any() };
         let stride = mem::size_of::<<<V as ToBufferSlice>::Target as BufferData>::Element>();
@@ -325,38 +656,344 @@ impl<'frame, 'queue: 'frame, 'binder> DrawCmdBuilder<'frame, 'queue, 'binder> {
         self
     }
 
+    /// Set the buffer containing the parameters of indirect draw commands.
+    pub fn with_indirect_buffer<B: ToBufferSlice>(mut self, commands: &B) -> Self
+    where
+        <<B as ToBufferSlice>::Target as BufferData>::Element: IndirectCommand,
+    {
+        let commands = unsafe { commands.to_slice_any() };
+        let stride = mem::size_of::<<<B as ToBufferSlice>::Target as BufferData>::Element>();
+        self.indirect_buffer = Some(IndirectBuffer {
+            offset: commands.offset,
+            stride,
+            len: commands.byte_size / stride,
+            indexed:
+                <<<B as ToBufferSlice>::Target as BufferData>::Element as IndirectCommand>::INDEXED,
+        });
+        unsafe {
+            self.state_cache.set_draw_indirect_buffer(&commands);
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_buffer(commands.owner);
+        self
+    }
+
+    /// Byte offset in the index buffer of the `first` index.
+    fn index_offset(&self, first: usize) -> usize {
+        self.index_buffer_offset
+            .expect("indexed draw command without an index buffer")
+            + first * self.index_stride.unwrap()
+    }
+
+    /// Byte offset and stride in the indirect buffer of the `first` indirect command.
+    fn indirect_offset(&self, first: usize, indexed: bool) -> (usize, usize) {
+        let indirect_buffer = self
+            .indirect_buffer
+            .as_ref()
+            .expect("indirect draw command without an indirect buffer");
+        assert_eq!(
+            indirect_buffer.indexed, indexed,
+            "indirect buffer element type does not match the draw command"
+        );
+        (
+            indirect_buffer.offset + first * indirect_buffer.stride,
+            indirect_buffer.stride,
+        )
+    }
+
+    /// Checks that the indirect commands drawn are within the bounds of the indirect buffer.
+    fn check_indirect_range(&self) -> Result<(), DrawError> {
+        let (first, draw_count) = match self.cmd {
+            DrawCmd::DrawArraysIndirect { first, draw_count }
+            | DrawCmd::DrawIndexedIndirect { first, draw_count } => (first, draw_count),
+            _ => return Ok(()),
+        };
+        let len = self
+            .indirect_buffer
+            .as_ref()
+            .expect("indirect draw command without an indirect buffer")
+            .len;
+        if first + draw_count > len {
+            return Err(DrawError::IndirectCommandsOutOfBounds {
+                first,
+                end: first + draw_count,
+                len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Submits the command. Panics if the command is invalid (see `try_submit`).
+    pub fn submit(self) {
+        self.try_submit().expect("invalid draw command")
+    }
+
+    /// Submits the command, or returns an error without drawing anything if the indirect
+    /// commands to draw are out of the bounds of the indirect buffer.
+    pub fn try_submit(mut self) -> Result<(), DrawError> {
+        // the state cache lock is released when self is dropped, even on error
+        self.submitted = true;
+        self.check_indirect_range()?;
+        unsafe {
+            self.state_cache.commit();
+        }
+        let topology = self.pipeline.primitive_topology;
+        if let Some(null) = self.frame.queue().context().null_device() {
+            null.record(match self.cmd {
+                DrawCmd::DrawArrays { first, count } => Command::Draw {
+                    topology,
+                    first,
+                    count,
+                },
+                DrawCmd::DrawIndexed {
+                    first,
+                    count,
+                    base_vertex,
+                } => Command::DrawIndexed {
+                    topology,
+                    first,
+                    count,
+                    base_vertex,
+                },
+                DrawCmd::DrawArraysInstanced {
+                    first,
+                    count,
+                    instance_count,
+                    base_instance,
+                } => Command::DrawInstanced {
+                    topology,
+                    first,
+                    count,
+                    instance_count,
+                    base_instance,
+                },
+                DrawCmd::DrawIndexedInstanced {
+                    first,
+                    count,
+                    base_vertex,
+                    instance_count,
+                    base_instance,
+                } => Command::DrawIndexedInstanced {
+                    topology,
+                    first,
+                    count,
+                    base_vertex,
+                    instance_count,
+                    base_instance,
+                },
+                DrawCmd::DrawArraysIndirect { first, draw_count } => {
+                    let (offset, stride) = self.indirect_offset(first, false);
+                    Command::DrawIndirect {
+                        topology,
+                        offset,
+                        draw_count,
+                        stride,
+                    }
+                }
+                DrawCmd::DrawIndexedIndirect { first, draw_count } => {
+                    let (offset, stride) = self.indirect_offset(first, true);
+                    Command::DrawIndexedIndirect {
+                        topology,
+                        offset,
+                        draw_count,
+                        stride,
+                    }
+                }
+            });
+        } else {
+            match self.cmd {
+                DrawCmd::DrawArrays { first, count } => unsafe {
+                    gl::DrawArrays(topology, first as i32, count as i32);
+                },
+                DrawCmd::DrawIndexed {
+                    first,
+                    count,
+                    base_vertex,
+                } => unsafe {
+                    gl::DrawElementsBaseVertex(
+                        topology,
+                        count as i32,
+                        self.index_buffer_type.unwrap(),
+                        self.index_offset(first) as *const GLvoid,
+                        base_vertex as i32,
+                    );
+                },
+                DrawCmd::DrawArraysInstanced {
+                    first,
+                    count,
+                    instance_count,
+                    base_instance,
+                } => unsafe {
+                    gl::DrawArraysInstancedBaseInstance(
+                        topology,
+                        first as i32,
+                        count as i32,
+                        instance_count as i32,
+                        base_instance as u32,
+                    );
+                },
+                DrawCmd::DrawIndexedInstanced {
+                    first,
+                    count,
+                    base_vertex,
+                    instance_count,
+                    base_instance,
+                } => unsafe {
+                    gl::DrawElementsInstancedBaseVertexBaseInstance(
+                        topology,
+                        count as i32,
+                        self.index_buffer_type.unwrap(),
+                        self.index_offset(first) as *const GLvoid,
+                        instance_count as i32,
+                        base_vertex as i32,
+                        base_instance as u32,
+                    );
+                },
+                DrawCmd::DrawArraysIndirect { first, draw_count } => unsafe {
+                    let (offset, stride) = self.indirect_offset(first, false);
+                    gl::MultiDrawArraysIndirect(
+                        topology,
+                        offset as *const GLvoid,
+                        draw_count as i32,
+                        stride as i32,
+                    );
+                },
+                DrawCmd::DrawIndexedIndirect { first, draw_count } => unsafe {
+                    let (offset, stride) = self.indirect_offset(first, true);
+                    gl::MultiDrawElementsIndirect(
+                        topology,
+                        self.index_buffer_type
+                            .expect("indexed draw command without an index buffer"),
+                        offset as *const GLvoid,
+                        draw_count as i32,
+                        stride as i32,
+                    );
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compute dispatch command builder.
+/// Like `DrawCmdBuilder`, it statically locks the frame object.
+#[must_use]
+pub struct DispatchCmdBuilder<'frame, 'queue: 'frame> {
+    frame: &'frame Frame<'queue>,
+    state_cache: RefMut<'frame, StateCache>,
+    num_groups: (u32, u32, u32),
+    /// Set by `submit` to defuse the drop bomb.
+    submitted: bool,
+}
+
+// Drop bomb
+impl<'frame, 'queue: 'frame> Drop for DispatchCmdBuilder<'frame, 'queue> {
+    fn drop(&mut self) {
+        if !self.submitted {
+            panic!("unsubmitted dispatch command")
+        }
+    }
+}
+
+impl<'frame, 'queue: 'frame> DispatchCmdBuilder<'frame, 'queue> {
+    /// Set a uniform buffer to use for this command.
+    pub fn with_uniform_buffer<U: ToBufferSlice>(self, slot: u32, buffer: &U) -> Self {
+        let buffer = unsafe { buffer.to_slice_any() };
+        self.with_uniform_buffer_any(slot, &buffer)
+    }
+
+    /// Set a uniform buffer (untyped slice) to use for this command.
+    pub fn with_uniform_buffer_any(mut self, slot: u32, buffer: &BufferSliceAny) -> Self {
+        unsafe {
+            self.state_cache.set_uniform_buffer(slot, buffer);
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_buffer(buffer.owner.clone());
+        self
+    }
+
+    /// Set a shader storage buffer to use for this command.
+    pub fn with_storage_buffer_any(mut self, slot: u32, buffer: &BufferSliceAny) -> Self {
+        unsafe {
+            self.state_cache.set_shader_storage_buffer(slot, buffer);
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_buffer(buffer.owner.clone());
+        self
+    }
+
+    pub fn with_texture(mut self, slot: u32, tex: &TextureAny, sampler: &SamplerDesc) -> Self {
+        let gctx = self.frame.queue().context();
+        unsafe {
+            self.state_cache
+                .set_texture(slot, tex, &gctx.get_sampler(sampler));
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_texture(tex.clone());
+        self
+    }
+
+    /// Binds the specified mip level of a texture to an image unit.
+    /// `access` is one of `gl::READ_ONLY`, `gl::WRITE_ONLY` or `gl::READ_WRITE`.
+    pub fn with_image(self, slot: u32, tex: &TextureAny, mip_level: u32, access: GLenum) -> Self {
+        self.with_image_impl(slot, tex, mip_level, None, access)
+    }
+
+    /// Binds a single layer of the specified mip level of an array, cube map or 3D texture
+    /// to an image unit.
+    pub fn with_image_layer(
+        self,
+        slot: u32,
+        tex: &TextureAny,
+        mip_level: u32,
+        layer: u32,
+        access: GLenum,
+    ) -> Self {
+        self.with_image_impl(slot, tex, mip_level, Some(layer), access)
+    }
+
+    fn with_image_impl(
+        mut self,
+        slot: u32,
+        tex: &TextureAny,
+        mip_level: u32,
+        layer: Option<u32>,
+        access: GLenum,
+    ) -> Self {
+        unsafe {
+            self.state_cache
+                .set_image(slot, tex, mip_level, layer, access);
+        }
+        self.frame
+            .resource_tracker
+            .borrow_mut()
+            .ref_texture(tex.clone());
+        self
+    }
+
     pub fn submit(mut self) {
         unsafe {
             self.state_cache.commit();
         }
-        match self.cmd {
-            DrawCmd::DrawArrays { first, count } => unsafe {
-                gl::DrawArrays(self.pipeline.primitive_topology, first as i32, count as i32);
-            },
-            DrawCmd::DrawIndexed {
-                first,
-                count,
-                base_vertex,
-            } => unsafe {
-                gl::DrawElementsBaseVertex(
-                    self.pipeline.primitive_topology,
-                    count as i32,
-                    self.index_buffer_type.unwrap(),
-                    (self.index_buffer_offset.unwrap() + first * self.index_stride.unwrap())
-                        as *const GLvoid,
-                    base_vertex as i32,
-                );
+        match self.frame.queue().context().null_device() {
+            Some(null) => null.record(Command::Dispatch {
+                num_groups: self.num_groups,
+            }),
+            None => unsafe {
+                gl::DispatchCompute(self.num_groups.0, self.num_groups.1, self.num_groups.2);
             },
         }
 
-        // extract everything that needs to be dropped, and drop it there
-        // then, forget (leak) the DrawCmdBuilder to prevent the destructor bomb from going off
-        let mut state_cache = unsafe { mem::uninitialized() };
-        let mut cmd = unsafe { mem::uninitialized() };
-        mem::swap(&mut self.state_cache, &mut state_cache);
-        mem::swap(&mut self.cmd, &mut cmd);
-        drop(state_cache);
-        drop(cmd);
-        mem::forget(self);
+        // the state cache lock is released when self is dropped
+        self.submitted = true;
     }
 }
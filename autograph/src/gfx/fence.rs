@@ -3,6 +3,9 @@ use gl;
 use gl::types::*;
 use std::collections::vec_deque::VecDeque;
 
+/// Timeout of each wait in `Fence::wait_until`.
+const WAIT_TIMEOUT_NS: u64 = 1_000_000_000;
+
 #[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq)]
 pub struct FenceValue(pub i64);
 
@@ -15,18 +18,39 @@ pub struct Fence {
     sync_points: VecDeque<SyncPoint>,
     current_value: FenceValue,
     next_value: FenceValue,
+    /// Fences of null contexts are signaled immediately.
+    null: bool,
+    /// Fences of null contexts are only signaled when waited on (for tests).
+    null_signal_on_wait: bool,
 }
 
 impl Fence {
-    pub fn new(_gctx: &Context, init_value: FenceValue) -> Fence {
+    pub fn new(gctx: &Context, init_value: FenceValue) -> Fence {
         Fence {
             sync_points: VecDeque::new(),
             current_value: init_value,
             next_value: FenceValue(init_value.0 + 1),
+            null: gctx.is_null(),
+            null_signal_on_wait: false,
         }
     }
 
+    /// Null fences will only be signaled by `wait_until`, as if the GPU was still busy
+    /// with all the submitted frames.
+    #[cfg(test)]
+    pub(super) fn signal_null_on_wait(&mut self) {
+        assert!(self.null, "not a null fence");
+        self.null_signal_on_wait = true;
+    }
+
     pub fn advance_async(&mut self) -> FenceValue {
+        if self.null {
+            if !self.null_signal_on_wait {
+                self.current_value = self.next_value;
+            }
+            self.next_value.0 += 1;
+            return self.next_value;
+        }
         let sync = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
         self.sync_points.push_back(SyncPoint {
             sync,
@@ -78,8 +102,20 @@ impl Fence {
         advanced
     }
 
-    fn wait_until(&mut self, _timeout: u64) {
-        unimplemented!()
+    /// Blocks until the fence reaches the specified value.
+    /// Panics if the value was not submitted with `advance_async`.
+    pub fn wait_until(&mut self, value: FenceValue) {
+        assert!(
+            value < self.next_value,
+            "waiting on a fence value that was not submitted"
+        );
+        if self.null {
+            self.current_value = ::std::cmp::max(self.current_value, value);
+            return;
+        }
+        while self.current_value < value {
+            self.wait_one(WAIT_TIMEOUT_NS);
+        }
     }
 }
 
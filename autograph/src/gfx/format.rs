@@ -4,7 +4,7 @@ use gl::types::*;
 
 /// Storage formats of GPU data (texture, vertices, etc).
 /// These are actually Vulkan formats.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
 #[allow(non_camel_case_types)]
 #[repr(u16)]
 pub enum Format {
@@ -238,11 +238,25 @@ impl FormatInfo {
         self.component_bits == [0, 0, 0, 0]
     }
 
+    pub fn has_depth(&self) -> bool {
+        match self.component_layout {
+            ComponentLayout::D | ComponentLayout::DS | ComponentLayout::XD => true,
+            _ => false,
+        }
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        match self.component_layout {
+            ComponentLayout::DS | ComponentLayout::S => true,
+            _ => false,
+        }
+    }
+
     pub fn byte_size(&self) -> usize {
-        (self.component_bits[0]
-            + self.component_bits[1]
-            + self.component_bits[2]
-            + self.component_bits[3]) as usize / 8
+        self.component_bits
+            .iter()
+            .map(|&bits| bits as usize)
+            .sum::<usize>() / 8
     }
 }
 
@@ -1445,8 +1459,41 @@ static GLF_D32_SFLOAT: GlFormatInfo = GlFormatInfo {
     upload_components: gl::DEPTH_COMPONENT,
     upload_ty: gl::FLOAT,
 };
+static GLF_D24_UNORM_S8_UINT: GlFormatInfo = GlFormatInfo {
+    internal_fmt: gl::DEPTH24_STENCIL8,
+    upload_components: gl::DEPTH_STENCIL,
+    upload_ty: gl::UNSIGNED_INT_24_8,
+};
+static GLF_D32_SFLOAT_S8_UINT: GlFormatInfo = GlFormatInfo {
+    internal_fmt: gl::DEPTH32F_STENCIL8,
+    upload_components: gl::DEPTH_STENCIL,
+    upload_ty: gl::FLOAT_32_UNSIGNED_INT_24_8_REV,
+};
 
 impl GlFormatInfo {
+    /// Returns the size in bytes of a texel transferred with `upload_components` and `upload_ty`,
+    /// and the size of each of its components.
+    /// The component size is `None` for packed types, whose texels can only be read whole.
+    /// This can differ from `FormatInfo::byte_size` (`D32_SFLOAT_S8_UINT` is packed in 8 bytes).
+    pub fn pixel_layout(&self) -> (usize, Option<usize>) {
+        let component_size = match self.upload_ty {
+            gl::UNSIGNED_BYTE | gl::BYTE => 1,
+            gl::UNSIGNED_SHORT | gl::SHORT | gl::HALF_FLOAT => 2,
+            gl::UNSIGNED_INT | gl::INT | gl::FLOAT => 4,
+            gl::UNSIGNED_INT_24_8 => return (4, None),
+            gl::FLOAT_32_UNSIGNED_INT_24_8_REV => return (8, None),
+            other => panic!("unsupported pixel type 0x{:x}", other),
+        };
+        let components = match self.upload_components {
+            gl::RED | gl::RED_INTEGER | gl::DEPTH_COMPONENT | gl::STENCIL_INDEX => 1,
+            gl::RG | gl::RG_INTEGER => 2,
+            gl::RGB | gl::RGB_INTEGER | gl::BGR => 3,
+            gl::RGBA | gl::RGBA_INTEGER | gl::BGRA => 4,
+            other => panic!("unsupported pixel format 0x{:x}", other),
+        };
+        (components * component_size, Some(component_size))
+    }
+
     pub fn from_format(fmt: Format) -> &'static GlFormatInfo {
         match fmt {
             Format::R8_UNORM => &GLF_R8_UNORM,
@@ -1464,6 +1511,8 @@ impl GlFormatInfo {
             Format::R8G8B8_SRGB => &GLF_R8G8B8_SRGB,
             Format::R8G8B8A8_SRGB => &GLF_R8G8B8A8_SRGB,
             Format::D32_SFLOAT => &GLF_D32_SFLOAT,
+            Format::D24_UNORM_S8_UINT => &GLF_D24_UNORM_S8_UINT,
+            Format::D32_SFLOAT_S8_UINT => &GLF_D32_SFLOAT_S8_UINT,
             _ => panic!("Unsupported format"),
         }
     }
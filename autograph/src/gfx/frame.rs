@@ -7,7 +7,7 @@ use super::pipeline::GraphicsPipeline;
 use super::queue::{FrameResources, Queue};
 use super::sampler::SamplerDesc;
 use super::texture::TextureAny;
-use super::upload_buffer::UploadBuffer;
+use super::upload_buffer::{UploadBuffer, UploadError};
 
 use std::cell::RefCell;
 use std::marker::PhantomData;
@@ -91,10 +91,19 @@ pub struct Frame<'q> {
     pub(super) state_cache: RefCell<StateCache>,
 }
 
+/// A texture or buffer used by a command, identified by its GL object.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum UsedObject {
+    Texture(GLuint),
+    Buffer(GLuint),
+}
+
 /// All resources needed for a frame
 pub struct ResourceTracker {
     ref_buffers: Vec<BufferAny>,
     ref_textures: Vec<TextureAny>,
+    /// Objects used since `Frame::record_used_objects`, if recording.
+    used_objects: Option<Vec<UsedObject>>,
 }
 
 impl ResourceTracker {
@@ -102,14 +111,21 @@ impl ResourceTracker {
         ResourceTracker {
             ref_textures: Vec::new(),
             ref_buffers: Vec::new(),
+            used_objects: None,
         }
     }
 
     pub fn ref_texture(&mut self, tex: TextureAny) {
+        if let Some(ref mut used_objects) = self.used_objects {
+            used_objects.push(UsedObject::Texture(tex.gl_object()));
+        }
         self.ref_textures.push(tex);
     }
 
     pub fn ref_buffer(&mut self, buf: BufferAny) {
+        if let Some(ref mut used_objects) = self.used_objects {
+            used_objects.push(UsedObject::Buffer(buf.gl_object()));
+        }
         self.ref_buffers.push(buf);
     }
 }
@@ -118,11 +134,12 @@ impl<'q> Frame<'q> {
     /// Creates a new frame, mut-borrows the queue
     /// Since we can't build multiple command streams in parallel in OpenGL
     pub fn new<'a>(queue: &'a mut Queue) -> Frame<'a> {
+        let state_cache = StateCache::new(queue.context());
         Frame {
             queue,
             resource_tracker: RefCell::new(ResourceTracker::new()),
             //upload_buffer: UploadBuffer::new(queue.context(), DEFAULT_UPLOAD_BUFFER_SIZE),
-            state_cache: RefCell::new(StateCache::new()),
+            state_cache: RefCell::new(state_cache),
         }
     }
 
@@ -131,23 +148,46 @@ impl<'q> Frame<'q> {
     /// TODO specify target usage
     /// The lifetime of the returned resource is bound to the lifetime of self:
     /// this allows to statically limit the usage of the buffer to the current frame only.
+    /// Fails if the data does not fit in the upload buffer and its overflow strategy
+    /// cannot make room for it.
+    pub fn try_upload_into<'a, T: BufferData + ?Sized>(
+        &'a self,
+        upload_buffer: &'a UploadBuffer,
+        data: &T,
+    ) -> Result<TransientBufferSlice<'a, T>, UploadError> {
+        let slice = unsafe {
+            // TODO infer alignment from usage
+            upload_buffer.upload(
+                data,
+                256,
+                self.queue,
+                &mut self.resource_tracker.borrow_mut(),
+            )?
+        };
+        Ok(TransientBufferSlice {
+            slice,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Same as `try_upload_into`, but panics if the upload fails.
+    /// Uploads never fail with the `Chain` and `OneOff` overflow strategies.
     pub fn upload_into<'a, T: BufferData + ?Sized>(
         &'a self,
         upload_buffer: &'a UploadBuffer,
         data: &T,
     ) -> TransientBufferSlice<'a, T> {
-        TransientBufferSlice {
-            slice: unsafe {
-                // TODO infer alignment from usage
-                upload_buffer.upload(
-                    data,
-                    256,
-                    self.queue.next_frame_fence_value(),
-                    self.queue.last_completed_frame(),
-                )
-            },
-            _phantom: PhantomData,
-        }
+        self.try_upload_into(upload_buffer, data)
+            .expect("failed to upload data")
+    }
+
+    /// Allocates and uploads data to the default upload buffer of the queue.
+    /// Effectively calls `try_upload_into` with `self.queue().default_upload_buffer()`
+    pub fn try_upload<'a, T: BufferData + ?Sized>(
+        &'a self,
+        data: &T,
+    ) -> Result<TransientBufferSlice<'a, T>, UploadError> {
+        self.try_upload_into(self.queue.default_upload_buffer(), data)
     }
 
     /// Allocates and uploads data to the default upload buffer of the queue.
@@ -169,6 +209,21 @@ impl<'q> Frame<'q> {
         self.resource_tracker.borrow_mut().ref_texture(tex);
     }
 
+    /// Starts recording the textures and buffers used by the commands of this frame.
+    /// Used by frame graph validation to check the resources bound by each pass.
+    pub fn record_used_objects(&self) {
+        self.resource_tracker.borrow_mut().used_objects = Some(Vec::new());
+    }
+
+    /// Stops recording and returns the objects used since `record_used_objects`.
+    pub fn take_used_objects(&self) -> Vec<UsedObject> {
+        self.resource_tracker
+            .borrow_mut()
+            .used_objects
+            .take()
+            .unwrap_or_default()
+    }
+
     /// Returns the current value of the fence of the queue.
     pub fn fence_value(&self) -> FenceValue {
         self.queue.fence.borrow().next_value()
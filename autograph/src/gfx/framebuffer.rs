@@ -1,5 +1,6 @@
 use super::context::Context;
 use super::format::Format;
+use super::null::Command;
 use super::texture::{Texture2D, TextureAny};
 use gl;
 use gl::types::*;
@@ -65,10 +66,49 @@ impl FramebufferObject {
     pub fn gl_object(&self) -> GLuint {
         self.obj
     }
+
+    /// Reads back the contents of a color attachment, as tightly packed RGBA8 pixels,
+    /// bottom row first (as returned by `glReadPixels`).
+    /// Waits for all rendering to the framebuffer to complete.
+    /// For the default framebuffer, `attachment` is ignored and the back buffer is read.
+    pub fn read_pixels(&self, attachment: u32) -> Vec<u8> {
+        let (width, height) = self.size;
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        // the null backend does not keep track of contents
+        if self.gctx.is_null() {
+            return pixels;
+        }
+        let read_buffer = if self.obj == 0 {
+            gl::BACK
+        } else {
+            gl::COLOR_ATTACHMENT0 + attachment
+        };
+        unsafe {
+            gl::NamedFramebufferReadBuffer(self.obj, read_buffer);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.obj);
+            gl::ReadnPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.len() as i32,
+                pixels.as_mut_ptr() as *mut GLvoid,
+            );
+        }
+        pixels
+    }
 }
 
 impl Drop for FramebufferObject {
     fn drop(&mut self) {
+        if let Some(null) = self.gctx.null_device() {
+            if self.obj != 0 {
+                null.record(Command::DeleteFramebuffer { obj: self.obj });
+            }
+            return;
+        }
         unsafe {
             gl::DeleteFramebuffers(1, &mut self.obj);
         }
@@ -96,6 +136,19 @@ pub enum FramebufferAttachment<'a> {
     Empty,
 }
 
+impl OwnedFramebufferAttachment {
+    fn format(&self) -> Option<Format> {
+        match *self {
+            OwnedFramebufferAttachment::Renderbuffer(ref renderbuffer) => Some(renderbuffer.format),
+            OwnedFramebufferAttachment::Texture(ref texture)
+            | OwnedFramebufferAttachment::TextureLayer(ref texture, _) => {
+                Some(texture.desc().format)
+            }
+            OwnedFramebufferAttachment::Default | OwnedFramebufferAttachment::Empty => None,
+        }
+    }
+}
+
 impl<'a> FramebufferAttachment<'a> {
     fn to_owned(self) -> OwnedFramebufferAttachment {
         match self {
@@ -152,6 +205,8 @@ pub enum FramebufferError {
     AttachmentSizeMismatch,
     #[fail(display = "framebuffer validation failed")]
     ValidationFailed,
+    #[fail(display = "{:?} is not a depth or stencil format", _0)]
+    NotDepthStencilFormat(Format),
 }
 
 impl FramebufferBuilder {
@@ -170,10 +225,10 @@ impl FramebufferBuilder {
     ) -> Result<(), FramebufferError> {
         let size = match *new {
             OwnedFramebufferAttachment::Renderbuffer(ref renderbuffer) => Some(renderbuffer.size),
-            OwnedFramebufferAttachment::Texture(ref texture) => {
+            OwnedFramebufferAttachment::Texture(ref texture)
+            | OwnedFramebufferAttachment::TextureLayer(ref texture, _) => {
                 Some((texture.width(), texture.height()))
             }
-            OwnedFramebufferAttachment::TextureLayer(ref texture, _) => unimplemented!(),
             OwnedFramebufferAttachment::Default => None,
             OwnedFramebufferAttachment::Empty => None,
         };
@@ -215,11 +270,18 @@ impl FramebufferBuilder {
         Ok(())
     }
 
+    /// Attaches a depth, stencil, or combined depth-stencil texture or renderbuffer
+    /// (e.g. `D24_UNORM_S8_UINT`). The attachment point is chosen according to the format.
     pub fn attach_depth<'a, A: ToFramebufferAttachment<'a>>(
         &mut self,
         attachment: A,
     ) -> Result<(), FramebufferError> {
         let new = attachment.to_framebuffer_attachement().to_owned();
+        if let Some(format) = new.format() {
+            if depth_stencil_attachment_point(format).is_none() {
+                return Err(FramebufferError::NotDepthStencilFormat(format));
+            }
+        }
         self.check_or_update_size(&new)?;
         self.depth_attachment = new;
         Ok(())
@@ -227,50 +289,10 @@ impl FramebufferBuilder {
 
     pub fn build(self) -> Framebuffer {
         assert!(self.attachments.len() < 8);
-        let mut obj = 0;
-        unsafe {
-            gl::CreateFramebuffers(1, &mut obj);
-        }
-
-        for (index, attachment) in self.attachments.iter().enumerate() {
-            match attachment {
-                &OwnedFramebufferAttachment::Texture(ref tex) => unsafe {
-                    gl::NamedFramebufferTexture(
-                        obj,
-                        gl::COLOR_ATTACHMENT0 + index as u32,
-                        tex.gl_object(),
-                        0,
-                    );
-                },
-                &OwnedFramebufferAttachment::Renderbuffer(ref renderbuffer) => unsafe {
-                    gl::NamedFramebufferRenderbuffer(
-                        obj,
-                        gl::COLOR_ATTACHMENT0 + index as u32,
-                        gl::RENDERBUFFER,
-                        renderbuffer.obj,
-                    );
-                },
-                &OwnedFramebufferAttachment::Empty => (),
-                _ => unimplemented!("Framebuffer attachment not implemented"),
-            }
-        }
-
-        unsafe {
-            gl::NamedFramebufferDrawBuffers(
-                obj,
-                8,
-                [
-                    gl::COLOR_ATTACHMENT0,
-                    gl::COLOR_ATTACHMENT0 + 1,
-                    gl::COLOR_ATTACHMENT0 + 2,
-                    gl::COLOR_ATTACHMENT0 + 3,
-                    gl::COLOR_ATTACHMENT0 + 4,
-                    gl::COLOR_ATTACHMENT0 + 5,
-                    gl::COLOR_ATTACHMENT0 + 6,
-                    gl::COLOR_ATTACHMENT0 + 7,
-                ].as_ptr(),
-            );
-        }
+        let obj = match self.gctx.null_device() {
+            Some(null) => null.create_framebuffer(),
+            None => unsafe { create_framebuffer(&self.attachments, &self.depth_attachment) },
+        };
 
         Framebuffer(Arc::new(FramebufferObject {
             obj,
@@ -281,3 +303,102 @@ impl FramebufferBuilder {
         }))
     }
 }
+
+/// Returns the attachment point for a texture or renderbuffer of the given format
+/// in the depth attachment of a framebuffer, or `None` if the format has no depth or stencil.
+fn depth_stencil_attachment_point(format: Format) -> Option<GLenum> {
+    let fmtinfo = format.get_format_info();
+    match (fmtinfo.has_depth(), fmtinfo.has_stencil()) {
+        (true, true) => Some(gl::DEPTH_STENCIL_ATTACHMENT),
+        (true, false) => Some(gl::DEPTH_ATTACHMENT),
+        (false, true) => Some(gl::STENCIL_ATTACHMENT),
+        (false, false) => None,
+    }
+}
+
+unsafe fn create_framebuffer(
+    attachments: &[OwnedFramebufferAttachment],
+    depth_attachment: &OwnedFramebufferAttachment,
+) -> GLuint {
+    let mut obj = 0;
+    gl::CreateFramebuffers(1, &mut obj);
+
+    for (index, attachment) in attachments.iter().enumerate() {
+        match attachment {
+            &OwnedFramebufferAttachment::Texture(ref tex) => {
+                gl::NamedFramebufferTexture(
+                    obj,
+                    gl::COLOR_ATTACHMENT0 + index as u32,
+                    tex.gl_object(),
+                    0,
+                );
+            }
+            &OwnedFramebufferAttachment::TextureLayer(ref tex, layer) => {
+                gl::NamedFramebufferTextureLayer(
+                    obj,
+                    gl::COLOR_ATTACHMENT0 + index as u32,
+                    tex.gl_object(),
+                    0,
+                    layer as i32,
+                );
+            }
+            &OwnedFramebufferAttachment::Renderbuffer(ref renderbuffer) => {
+                gl::NamedFramebufferRenderbuffer(
+                    obj,
+                    gl::COLOR_ATTACHMENT0 + index as u32,
+                    gl::RENDERBUFFER,
+                    renderbuffer.obj,
+                );
+            }
+            &OwnedFramebufferAttachment::Empty => (),
+            _ => unimplemented!("Framebuffer attachment not implemented"),
+        }
+    }
+
+    match depth_attachment {
+        &OwnedFramebufferAttachment::Texture(ref tex) => {
+            gl::NamedFramebufferTexture(
+                obj,
+                depth_stencil_attachment_point(tex.desc().format).unwrap(),
+                tex.gl_object(),
+                0,
+            );
+        }
+        &OwnedFramebufferAttachment::TextureLayer(ref tex, layer) => {
+            gl::NamedFramebufferTextureLayer(
+                obj,
+                depth_stencil_attachment_point(tex.desc().format).unwrap(),
+                tex.gl_object(),
+                0,
+                layer as i32,
+            );
+        }
+        &OwnedFramebufferAttachment::Renderbuffer(ref renderbuffer) => {
+            gl::NamedFramebufferRenderbuffer(
+                obj,
+                depth_stencil_attachment_point(renderbuffer.format).unwrap(),
+                gl::RENDERBUFFER,
+                renderbuffer.obj,
+            );
+        }
+        &OwnedFramebufferAttachment::Empty => (),
+        _ => unimplemented!("Framebuffer attachment not implemented"),
+    }
+
+    gl::NamedFramebufferDrawBuffers(
+        obj,
+        8,
+        [
+            gl::COLOR_ATTACHMENT0,
+            gl::COLOR_ATTACHMENT0 + 1,
+            gl::COLOR_ATTACHMENT0 + 2,
+            gl::COLOR_ATTACHMENT0 + 3,
+            gl::COLOR_ATTACHMENT0 + 4,
+            gl::COLOR_ATTACHMENT0 + 5,
+            gl::COLOR_ATTACHMENT0 + 6,
+            gl::COLOR_ATTACHMENT0 + 7,
+        ].as_ptr(),
+    );
+
+    obj
+}
@@ -99,13 +99,27 @@ impl Std140LayoutBuilder {
     }
 }
 
-struct Std140LayoutBuilder2 {
+/// Rules used to compute the offsets of the members of interface blocks.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum LayoutRules {
+    /// Uniform blocks.
+    Std140,
+    /// Shader storage blocks: as std140, except that the alignment of arrays, matrices and
+    /// structs is not rounded up to the alignment of a vec4.
+    Std430,
+}
+
+struct BlockLayoutBuilder {
+    rules: LayoutRules,
     next_offset: usize,
 }
 
-impl Std140LayoutBuilder2 {
-    fn new() -> Std140LayoutBuilder2 {
-        Std140LayoutBuilder2 { next_offset: 0 }
+impl BlockLayoutBuilder {
+    fn new(rules: LayoutRules) -> BlockLayoutBuilder {
+        BlockLayoutBuilder {
+            rules,
+            next_offset: 0,
+        }
     }
 
     fn align(&mut self, a: usize) -> usize {
@@ -113,6 +127,14 @@ impl Std140LayoutBuilder2 {
         self.next_offset
     }
 
+    /// Alignment of arrays and structs with members aligned to `align`.
+    fn aggregate_align(&self, align: usize) -> usize {
+        match self.rules {
+            LayoutRules::Std140 => max(16, align),
+            LayoutRules::Std430 => align,
+        }
+    }
+
     fn get_align_and_size(&self, ty: &TypeDesc) -> (usize, usize) {
         match *ty {
             TypeDesc::Primitive(PrimitiveType::Int)
@@ -131,19 +153,41 @@ impl Std140LayoutBuilder2 {
                 }
             }
             TypeDesc::Matrix(primty, rows, cols) => {
-                let (col_align, col_size) =
-                    self.get_align_and_size(&TypeDesc::Vector(primty, rows));
-                // alignment = column type align rounded up to vec4 align (16 bytes)
-                let base_align = max(16, col_align);
-                let stride = col_size + align_offset(col_size, col_align);
-                // total array size = num columns * stride, rounded up to the next multiple of the base alignment
-                let array_size = round_up(cols as usize * stride, base_align);
-                (base_align, array_size)
+                // column-major: laid out as an array of column vectors
+                self.get_align_and_size(&TypeDesc::Array(
+                    Box::new(TypeDesc::Vector(primty, rows)),
+                    cols as usize,
+                ))
+            }
+            TypeDesc::Array(ref elem_ty, len) => {
+                let (align, stride) = self.get_align_and_stride(elem_ty);
+                (align, len * stride)
+            }
+            // only the last member of a block: does not contribute to the size
+            TypeDesc::UnsizedArray(ref elem_ty, _) => (self.get_align_and_stride(elem_ty).0, 0),
+            TypeDesc::Struct(ref members) => {
+                let members_align = members
+                    .iter()
+                    .map(|&(_, ref member_ty)| self.get_align_and_size(member_ty).0)
+                    .max()
+                    .unwrap_or(1);
+                let align = self.aggregate_align(members_align);
+                let size = members.last().map_or(0, |&(offset, ref member_ty)| {
+                    offset + self.get_align_and_size(member_ty).1
+                });
+                (align, round_up(size, align))
             }
             _ => panic!("unsupported type"),
         }
     }
 
+    /// Alignment of an array, and distance between its elements.
+    fn get_align_and_stride(&self, elem_ty: &TypeDesc) -> (usize, usize) {
+        let (elem_align, elem_size) = self.get_align_and_size(elem_ty);
+        let align = self.aggregate_align(elem_align);
+        (align, round_up(elem_size, align))
+    }
+
     fn add_member(&mut self, ty: &TypeDesc) -> usize {
         let (align, size) = self.get_align_and_size(ty);
         let current_offset = self.align(align);
@@ -222,6 +266,41 @@ fn compare_types(shader_ty: &TypeDesc, host_ty: &TypeDesc) -> Result<(), Error>
             }
         }
         /////////////////////////////////////////////////////////
+        (
+            &TypeDesc::Array(ref shader_elem_ty, shader_len),
+            &TypeDesc::Array(ref host_elem_ty, host_len),
+        ) => {
+            compare_types(shader_elem_ty, host_elem_ty).context(format!(
+                "array element type mismatch: {:?} (shader) and {:?} (host)",
+                shader_ty, host_ty
+            ))?;
+            if shader_len != host_len {
+                bail!(
+                    "array length mismatch: {} (shader) and {} (host)",
+                    shader_len,
+                    host_len
+                )
+            }
+        }
+        /////////////////////////////////////////////////////////
+        (
+            &TypeDesc::UnsizedArray(ref shader_elem_ty, shader_stride),
+            &TypeDesc::UnsizedArray(ref host_elem_ty, host_stride),
+        ) => {
+            // the length is only known at runtime: check that the elements match
+            compare_types(shader_elem_ty, host_elem_ty).context(format!(
+                "array element type mismatch: {:?} (shader) and {:?} (host)",
+                shader_ty, host_ty
+            ))?;
+            if shader_stride != host_stride {
+                bail!(
+                    "array stride mismatch: {} (shader) and {} (host)",
+                    shader_stride,
+                    host_stride
+                )
+            }
+        }
+        /////////////////////////////////////////////////////////
         (&TypeDesc::Struct(ref shader), &TypeDesc::Struct(ref host)) => {
             let mut shader_member_index = 0;
             let mut host_member_index = 0;
@@ -261,6 +340,42 @@ fn compare_types(shader_ty: &TypeDesc, host_ty: &TypeDesc) -> Result<(), Error>
     Ok(())
 }
 
+/// Returns the type of the runtime array in a shader storage block that contains only that,
+/// so that the block can be bound to a slice of elements. Otherwise, returns the block type.
+fn storage_block_contents(block_ty: &TypeDesc) -> &TypeDesc {
+    match *block_ty {
+        TypeDesc::Struct(ref members) if members.len() == 1 => match members[0] {
+            (_, ref array_ty @ TypeDesc::UnsizedArray(..)) => array_ty,
+            _ => block_ty,
+        },
+        _ => block_ty,
+    }
+}
+
+/// Checks that the dimensions of an image in a shader match the dimensions of the texture
+/// bound by the host, if known.
+fn check_image_dimensions(
+    img: &ITypeImage,
+    dimensions: Option<gfx::TextureDimensions>,
+) -> Result<(), Error> {
+    let dim_ok = match dimensions {
+        Some(gfx::TextureDimensions::Tex1D) => img.dim == spirv::Dim::Dim1D,
+        Some(gfx::TextureDimensions::Tex2D) => img.dim == spirv::Dim::Dim2D,
+        Some(gfx::TextureDimensions::Tex3D) => img.dim == spirv::Dim::Dim3D,
+        Some(gfx::TextureDimensions::TexCube) => img.dim == spirv::Dim::DimCube,
+        None => true,
+        _ => bail!("unsupported texture dimension"),
+    };
+    if !dim_ok {
+        bail!(
+            "texture dimensions do not match (shader: {:?}, host: {:?})",
+            img.dim,
+            dimensions
+        );
+    }
+    Ok(())
+}
+
 impl ModuleWrapper {
     fn find_decoration(&self, id: u32, deco: spirv::Decoration) -> Option<&IDecorate> {
         self.0.instructions.iter().find_map(|inst| match *inst {
@@ -316,6 +431,13 @@ impl ModuleWrapper {
         })
     }
 
+    fn find_constant(&self, id: u32) -> Option<&IConstant> {
+        self.0.instructions.iter().find_map(|inst| match *inst {
+            Instruction::Constant(ref constant) if constant.result_id == id => Some(constant),
+            _ => None,
+        })
+    }
+
     fn find_location_decoration(&self, id: u32) -> Option<u32> {
         self.find_decoration(id, spirv::Decoration::Location)
             .map(|deco| deco.params[0])
@@ -352,8 +474,9 @@ impl ModuleWrapper {
         }
     }*/
 
-    /// Get a typedesc from a spirv instruction
-    fn optype_to_typedesc(&self, inst: &Instruction) -> TypeDesc {
+    /// Get a typedesc from a spirv instruction.
+    /// Offsets of struct members are computed with the specified layout rules.
+    fn optype_to_typedesc(&self, inst: &Instruction, rules: LayoutRules) -> TypeDesc {
         match *inst {
             Instruction::TypeVoid(_) => panic!(),
             Instruction::TypeBool(ref ty) => {
@@ -373,7 +496,7 @@ impl ModuleWrapper {
             }
             Instruction::TypeVector(ref ty) => {
                 let comp_ty = self.find_type(ty.component_id).unwrap();
-                let comp_ty = self.optype_to_typedesc(comp_ty);
+                let comp_ty = self.optype_to_typedesc(comp_ty, rules);
                 let comp_count = ty.count;
                 assert!(comp_count <= 4);
                 if let TypeDesc::Primitive(prim) = comp_ty {
@@ -387,7 +510,7 @@ impl ModuleWrapper {
                 let col_count = ty.column_count;
                 if let Instruction::TypeVector(column_ty) = column_ty {
                     let comp_ty = self.find_type(column_ty.component_id).unwrap();
-                    let comp_ty = self.optype_to_typedesc(comp_ty);
+                    let comp_ty = self.optype_to_typedesc(comp_ty, rules);
                     let row_count = column_ty.count;
                     if let TypeDesc::Primitive(prim) = comp_ty {
                         TypeDesc::Matrix(prim, row_count as u8, col_count as u8)
@@ -401,17 +524,30 @@ impl ModuleWrapper {
             Instruction::TypeImage(_) => unimplemented!(),
             Instruction::TypeSampler(_) => unimplemented!(),
             Instruction::TypeSampledImage(_) => unimplemented!(),
-            Instruction::TypeArray(_) => unimplemented!(),
-            Instruction::TypeRuntimeArray(_) => unimplemented!(),
+            Instruction::TypeArray(ref ty) => {
+                let elem_ty = self.find_type(ty.type_id).unwrap();
+                let elem_ty = self.optype_to_typedesc(elem_ty, rules);
+                let len = self
+                    .find_constant(ty.length_id)
+                    .expect("array length is not a constant")
+                    .data[0];
+                TypeDesc::Array(Box::new(elem_ty), len as usize)
+            }
+            Instruction::TypeRuntimeArray(ref ty) => {
+                let elem_ty = self.find_type(ty.type_id).unwrap();
+                let elem_ty = self.optype_to_typedesc(elem_ty, rules);
+                let (_, stride) = BlockLayoutBuilder::new(rules).get_align_and_stride(&elem_ty);
+                TypeDesc::UnsizedArray(Box::new(elem_ty), stride)
+            }
             Instruction::TypeStruct(ref ty) => {
                 // build layout
-                let mut std140_layout = Std140LayoutBuilder2::new();
+                let mut layout = BlockLayoutBuilder::new(rules);
                 //let mut member_index = 0;
                 let mut members = Vec::new();
                 for &member_ty in ty.member_types.iter() {
                     let member_ty = self.find_type(member_ty).unwrap();
-                    let member_ty = self.optype_to_typedesc(member_ty);
-                    let member_offset = std140_layout.add_member(&member_ty);
+                    let member_ty = self.optype_to_typedesc(member_ty, rules);
+                    let member_offset = layout.add_member(&member_ty);
                     members.push((member_offset, member_ty));
                 }
                 TypeDesc::Struct(members)
@@ -424,8 +560,13 @@ impl ModuleWrapper {
 
     /// Check that the type described by the spirv instruction
     /// is layout-compatible with the given host type description
-    fn compare_types(&self, ty_inst: &Instruction, host_ty: &TypeDesc) -> Result<(), Error> {
-        let shader_ty = self.optype_to_typedesc(ty_inst);
+    fn compare_types(
+        &self,
+        ty_inst: &Instruction,
+        host_ty: &TypeDesc,
+        rules: LayoutRules,
+    ) -> Result<(), Error> {
+        let shader_ty = self.optype_to_typedesc(ty_inst, rules);
         compare_types(&shader_ty, host_ty)
     }
 
@@ -472,7 +613,7 @@ impl ModuleWrapper {
                     if shader_loc != location {
                         continue;
                     }
-                    self.compare_types(resource_ty_inst, desc.ty)?;
+                    self.compare_types(resource_ty_inst, desc.ty, LayoutRules::Std140)?;
                     return Ok(true);
                 }
                 ShaderResource::UniformBuffer(desc) => {
@@ -494,7 +635,7 @@ impl ModuleWrapper {
                         continue;
                     }
                     if let Some(tydesc) = desc.tydesc {
-                        self.compare_types(resource_ty_inst, tydesc)?;
+                        self.compare_types(resource_ty_inst, tydesc, LayoutRules::Std140)?;
                     } else {
                         warn!("No buffer layout provided for {:?}", desc);
                     }
@@ -519,7 +660,14 @@ impl ModuleWrapper {
                     if shader_binding != binding {
                         continue;
                     }
-                    unimplemented!()
+                    if let Some(tydesc) = desc.tydesc {
+                        let shader_ty =
+                            self.optype_to_typedesc(resource_ty_inst, LayoutRules::Std430);
+                        compare_types(storage_block_contents(&shader_ty), tydesc)?;
+                    } else {
+                        warn!("No buffer layout provided for {:?}", desc);
+                    }
+                    return Ok(true);
                 }
                 ShaderResource::Image(desc) => {
                     // storage class is uniformconstant
@@ -528,14 +676,15 @@ impl ModuleWrapper {
                     }
                     let binding = desc.index.expect("must provide an image unit");
                     // type is TypeImage
-                    if let &Instruction::TypeImage(_) = resource_ty_inst {
+                    if let &Instruction::TypeImage(ref img) = resource_ty_inst {
                         let shader_binding = self
                             .find_binding_decoration(var.result_id)
                             .expect("image with no binding in shader");
                         if shader_binding != binding {
                             continue;
                         }
-                        unimplemented!()
+                        check_image_dimensions(img, desc.dimensions)?;
+                        return Ok(true);
                     } else {
                         continue;
                     }
@@ -557,23 +706,7 @@ impl ModuleWrapper {
                         // load OpTypeImage and check dimensions
                         let image_ty = self.find_type(sampled_image.image_type_id).unwrap();
                         if let &Instruction::TypeImage(ref img) = image_ty {
-                            let dim_ok = match desc.dimensions {
-                                Some(gfx::TextureDimensions::Tex1D) => img.dim == spirv::Dim::Dim1D,
-                                Some(gfx::TextureDimensions::Tex2D) => img.dim == spirv::Dim::Dim2D,
-                                Some(gfx::TextureDimensions::Tex3D) => img.dim == spirv::Dim::Dim3D,
-                                Some(gfx::TextureDimensions::TexCube) => {
-                                    img.dim == spirv::Dim::DimCube
-                                }
-                                None => true,
-                                _ => bail!("unsupported texture dimension"),
-                            };
-                            if !dim_ok {
-                                bail!(
-                                    "texture dimensions do not match (shader: {:?}, host: {:?})",
-                                    img.dim,
-                                    desc.dimensions
-                                );
-                            }
+                            check_image_dimensions(img, desc.dimensions)?;
                             return Ok(true);
                         } else {
                             panic!("malformed SPIR-V")
@@ -681,6 +814,17 @@ impl SpirvGraphicsPipelineModules {
                 errors.push(e);
             }
         }
+        for ssbo in interface.get_storage_buffers().iter() {
+            if let Err(e) = self.verify_shader_resource(&ShaderResource::ShaderStorageBuffer(ssbo))
+            {
+                errors.push(e);
+            }
+        }
+        for img in interface.get_images().iter() {
+            if let Err(e) = self.verify_shader_resource(&ShaderResource::Image(img)) {
+                errors.push(e);
+            }
+        }
         if !errors.is_empty() {
             Err(ShaderInterfaceVerificationError(errors))
         } else {
@@ -1,6 +1,6 @@
 use failure::Error;
 use gfx;
-use gfx::pipeline::GraphicsPipelineBuilder;
+use gfx::pipeline::{ComputePipelineBuilder, GraphicsPipelineBuilder};
 use gfx::pipeline::VertexAttribute;
 use gfx::shader;
 use gfx::shader_interface;
@@ -364,6 +364,81 @@ pub fn create_pipeline_via_gl<P: AsRef<Path>>(
     })
 }
 
+pub struct GlslComputeShaderPipeline {
+    pub compute: Shader,
+    pub program: GLuint,
+}
+
+impl Drop for GlslComputeShaderPipeline {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+        }
+    }
+}
+
+impl shader::ComputeShaderPipeline for GlslComputeShaderPipeline {
+    fn compute_shader(&self) -> &shader::ComputeShader {
+        &self.compute
+    }
+
+    fn is_compatible_with(
+        &self,
+        _interface: &shader_interface::ShaderInterfaceDesc,
+    ) -> Result<(), Error> {
+        // compiled by the driver: there is no SPIR-V module to reflect
+        bail!(
+            "shader interface checking is not supported for compute pipelines compiled from GLSL"
+        )
+    }
+
+    fn get_program(&self) -> Result<GLuint, Error> {
+        Ok(self.program)
+    }
+}
+
+/// Loads a combined GLSL source containing a compute shader (`#pragma stages(compute)`)
+/// and compiles it through the driver's GLSL compiler.
+/// Does not support interface checking.
+pub fn create_compute_pipeline_via_gl<P: AsRef<Path>>(
+    combined_src_path: P,
+) -> Result<GlslComputeShaderPipeline, Error> {
+    let pp = load_combined_shader_source(combined_src_path.as_ref())?;
+    let compute_src = pp.compute.as_ref().ok_or(format_err!(
+        "No compute shader defined in input file (missing `#pragma stages' directive?): {}",
+        combined_src_path.as_ref().display()
+    ))?;
+
+    let compute = Shader::compile(compute_src, gl::COMPUTE_SHADER).map_err(|log| {
+        error!("====================================================================");
+        error!(
+            "Shader compilation error ({:?}) | stage: {:?}",
+            combined_src_path.as_ref(),
+            PS_COMPUTE
+        );
+        error!("{}\n", log);
+        GlslCompilationError {
+            source_path: combined_src_path.as_ref().to_owned(),
+            stage: PS_COMPUTE,
+            log,
+        }
+    })?;
+
+    let program = unsafe { gl::CreateProgram() };
+    unsafe {
+        gl::AttachShader(program, compute.obj);
+    }
+
+    link_program(program).map_err(|log| {
+        unsafe {
+            gl::DeleteProgram(program);
+        }
+        format_err!("Program link failed: {}", log)
+    })?;
+
+    Ok(GlslComputeShaderPipeline { compute, program })
+}
+
 pub struct SpirvGraphicsShaderPipeline {
     pub vertex: Shader,
     pub fragment: Shader,
@@ -698,3 +773,15 @@ impl GraphicsPipelineBuilderExt for GraphicsPipelineBuilder {
         Ok(tmp)
     }
 }
+
+pub trait ComputePipelineBuilderExt: Sized {
+    /// Loads the compute shader from the GLSL combined source file specified by path.
+    fn with_glsl_file<P: AsRef<Path>>(self, path: P) -> Result<Self, Error>;
+}
+
+impl ComputePipelineBuilderExt for ComputePipelineBuilder {
+    fn with_glsl_file<P: AsRef<Path>>(self, path: P) -> Result<Self, Error> {
+        let compiled = create_compute_pipeline_via_gl(path)?;
+        Ok(self.with_shader_pipeline(Box::new(compiled)))
+    }
+}
@@ -213,6 +213,7 @@ fn preprocess_shader_internal<'a>(
                         slot: slot.unwrap(),
                         size: attrib_format.1,
                         normalized: attrib_format.2,
+                        instance_step_rate: 0,
                     });
 
                     //index += 1;
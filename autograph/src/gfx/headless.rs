@@ -0,0 +1,274 @@
+//! OpenGL contexts that are not associated to a window.
+//!
+//! On Linux, the context is created with EGL, on the surfaceless platform
+//! (`EGL_MESA_platform_surfaceless`) if available, and without any surface if the driver supports
+//! `EGL_KHR_surfaceless_context` (otherwise, a 1x1 pbuffer is used). This does not need a display server, and works with
+//! Mesa's software rasterizer on machines without a GPU (`LIBGL_ALWAYS_SOFTWARE=1`).
+//! On other platforms, the context of an invisible window is used instead.
+//!
+//! Headless contexts have no default framebuffer: render to textures attached to a framebuffer
+//! object, and read them back with `Framebuffer::read_pixels`.
+//!
+
+/// OpenGL version requested for headless contexts.
+/// 4.5 is the minimum for direct state access, and the version supported by Mesa's llvmpipe.
+const GL_VERSION: (u8, u8) = (4, 5);
+
+#[derive(Debug, Fail)]
+pub enum HeadlessContextError {
+    #[fail(display = "could not load libEGL")]
+    EglNotFound,
+    #[fail(display = "{} failed (EGL error 0x{:x})", function, error)]
+    EglError { function: &'static str, error: i32 },
+    #[fail(display = "no suitable EGL framebuffer configuration")]
+    NoConfig,
+    #[fail(display = "could not create the OpenGL context: {}", _0)]
+    Creation(String),
+}
+
+/// An OpenGL context without a window, current on the thread that created it.
+pub struct HeadlessContext {
+    _context: imp::Context,
+}
+
+impl HeadlessContext {
+    /// Creates an OpenGL core context, makes it current, and loads the OpenGL functions.
+    pub fn new() -> Result<HeadlessContext, HeadlessContextError> {
+        let context = unsafe { imp::Context::new()? };
+        Ok(HeadlessContext { _context: context })
+    }
+}
+
+impl ::std::fmt::Debug for HeadlessContext {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "HeadlessContext")
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use super::{HeadlessContextError, GL_VERSION};
+    use egl;
+    use egl::types::*;
+    use gl;
+    use libc;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_void;
+    use std::ptr;
+
+    pub(super) struct Context {
+        lib: *mut c_void,
+        display: EGLDisplay,
+        context: EGLContext,
+        /// Null if the context is surfaceless.
+        surface: EGLSurface,
+    }
+
+    unsafe fn egl_error(function: &'static str) -> HeadlessContextError {
+        HeadlessContextError::EglError {
+            function,
+            error: egl::GetError(),
+        }
+    }
+
+    unsafe fn extensions(display: EGLDisplay) -> String {
+        let ptr = egl::QueryString(display, egl::EXTENSIONS as EGLint);
+        if ptr.is_null() {
+            // client extensions are not supported before EGL 1.5
+            egl::GetError();
+            String::new()
+        } else {
+            CStr::from_ptr(ptr).to_string_lossy().into_owned()
+        }
+    }
+
+    unsafe fn load_egl() -> Option<*mut c_void> {
+        for name in [&b"libEGL.so.1\0"[..], &b"libEGL.so\0"[..]].iter() {
+            let lib = libc::dlopen(name.as_ptr() as *const _, libc::RTLD_NOW);
+            if !lib.is_null() {
+                egl::load_with(|symbol| {
+                    let symbol = CString::new(symbol).unwrap();
+                    libc::dlsym(lib, symbol.as_ptr()) as *const _
+                });
+                return Some(lib);
+            }
+        }
+        None
+    }
+
+    /// Returns an initialized display, preferring the surfaceless platform.
+    unsafe fn get_display() -> Result<EGLDisplay, HeadlessContextError> {
+        if egl::GetPlatformDisplay::is_loaded()
+            && extensions(ptr::null()).contains("EGL_MESA_platform_surfaceless")
+        {
+            let display = egl::GetPlatformDisplay(
+                egl::PLATFORM_SURFACELESS_MESA,
+                ptr::null_mut(),
+                ptr::null(),
+            );
+            if !display.is_null()
+                && egl::Initialize(display, ptr::null_mut(), ptr::null_mut()) == egl::TRUE
+            {
+                return Ok(display);
+            }
+            debug!("surfaceless EGL platform unavailable, using the default display");
+        }
+
+        let display = egl::GetDisplay(ptr::null());
+        if display.is_null() {
+            return Err(egl_error("eglGetDisplay"));
+        }
+        if egl::Initialize(display, ptr::null_mut(), ptr::null_mut()) != egl::TRUE {
+            return Err(egl_error("eglInitialize"));
+        }
+        Ok(display)
+    }
+
+    impl Context {
+        pub(super) unsafe fn new() -> Result<Context, HeadlessContextError> {
+            let lib = load_egl().ok_or(HeadlessContextError::EglNotFound)?;
+            let mut context = Context {
+                lib,
+                display: ptr::null(),
+                context: ptr::null(),
+                surface: ptr::null(),
+            };
+            // on error, `context` is dropped and releases what was created so far
+            context.display = get_display()?;
+
+            if egl::BindAPI(egl::OPENGL_API) != egl::TRUE {
+                return Err(egl_error("eglBindAPI"));
+            }
+
+            let config_attribs = [
+                egl::SURFACE_TYPE as EGLint,
+                egl::PBUFFER_BIT as EGLint,
+                egl::RENDERABLE_TYPE as EGLint,
+                egl::OPENGL_BIT as EGLint,
+                egl::NONE as EGLint,
+            ];
+            let mut config = ptr::null();
+            let mut num_configs = 0;
+            if egl::ChooseConfig(
+                context.display,
+                config_attribs.as_ptr(),
+                &mut config,
+                1,
+                &mut num_configs,
+            ) != egl::TRUE
+            {
+                return Err(egl_error("eglChooseConfig"));
+            }
+            if num_configs == 0 {
+                return Err(HeadlessContextError::NoConfig);
+            }
+
+            let context_attribs = [
+                egl::CONTEXT_MAJOR_VERSION as EGLint,
+                GL_VERSION.0 as EGLint,
+                egl::CONTEXT_MINOR_VERSION as EGLint,
+                GL_VERSION.1 as EGLint,
+                egl::CONTEXT_OPENGL_PROFILE_MASK as EGLint,
+                egl::CONTEXT_OPENGL_CORE_PROFILE_BIT as EGLint,
+                egl::CONTEXT_OPENGL_DEBUG as EGLint,
+                egl::TRUE as EGLint,
+                egl::NONE as EGLint,
+            ];
+            context.context = egl::CreateContext(
+                context.display,
+                config,
+                ptr::null(),
+                context_attribs.as_ptr(),
+            );
+            if context.context.is_null() {
+                return Err(egl_error("eglCreateContext"));
+            }
+
+            if !extensions(context.display).contains("EGL_KHR_surfaceless_context") {
+                let pbuffer_attribs = [
+                    egl::WIDTH as EGLint,
+                    1,
+                    egl::HEIGHT as EGLint,
+                    1,
+                    egl::NONE as EGLint,
+                ];
+                context.surface =
+                    egl::CreatePbufferSurface(context.display, config, pbuffer_attribs.as_ptr());
+                if context.surface.is_null() {
+                    return Err(egl_error("eglCreatePbufferSurface"));
+                }
+            }
+
+            if egl::MakeCurrent(
+                context.display,
+                context.surface,
+                context.surface,
+                context.context,
+            ) != egl::TRUE
+            {
+                return Err(egl_error("eglMakeCurrent"));
+            }
+
+            gl::load_with(|symbol| {
+                let symbol = CString::new(symbol).unwrap();
+                egl::GetProcAddress(symbol.as_ptr()) as *const _
+            });
+            Ok(context)
+        }
+    }
+
+    impl Drop for Context {
+        fn drop(&mut self) {
+            unsafe {
+                if !self.display.is_null() {
+                    egl::MakeCurrent(self.display, ptr::null(), ptr::null(), ptr::null());
+                    if !self.surface.is_null() {
+                        egl::DestroySurface(self.display, self.surface);
+                    }
+                    if !self.context.is_null() {
+                        egl::DestroyContext(self.display, self.context);
+                    }
+                    egl::Terminate(self.display);
+                }
+                libc::dlclose(self.lib);
+            }
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod imp {
+    use super::{HeadlessContextError, GL_VERSION};
+    use gl;
+    use glutin;
+    use glutin::GlContext;
+
+    pub(super) struct Context {
+        _window: glutin::GlWindow,
+        // must outlive the window
+        _events_loop: glutin::EventsLoop,
+    }
+
+    impl Context {
+        pub(super) unsafe fn new() -> Result<Context, HeadlessContextError> {
+            let events_loop = glutin::EventsLoop::new();
+            let window_builder = glutin::WindowBuilder::new()
+                .with_title("autograph (headless)")
+                .with_visibility(false);
+            let context_builder = glutin::ContextBuilder::new()
+                .with_gl_profile(glutin::GlProfile::Core)
+                .with_gl_debug_flag(true)
+                .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, GL_VERSION));
+            let window = glutin::GlWindow::new(window_builder, context_builder, &events_loop)
+                .map_err(|e| HeadlessContextError::Creation(e.to_string()))?;
+            window
+                .make_current()
+                .map_err(|e| HeadlessContextError::Creation(e.to_string()))?;
+            gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+            Ok(Context {
+                _window: window,
+                _events_loop: events_loop,
+            })
+        }
+    }
+}
@@ -9,8 +9,11 @@ pub mod format;
 pub mod frame;
 pub mod framebuffer;
 pub mod glsl;
+pub mod headless;
+pub mod null;
 pub mod pipeline;
 pub mod queue;
+pub mod readback;
 pub mod render_utils;
 pub mod sampler;
 pub mod shader;
@@ -29,8 +32,10 @@ pub use self::draw::*;
 pub use self::format::*;
 pub use self::frame::*;
 pub use self::framebuffer::*;
+pub use self::headless::{HeadlessContext, HeadlessContextError};
 pub use self::pipeline::*;
 pub use self::queue::*;
+pub use self::readback::*;
 pub use self::render_utils::*;
 pub use self::sampler::*;
 pub use self::shader::*;
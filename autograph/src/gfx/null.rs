@@ -0,0 +1,267 @@
+//! A backend that records commands instead of calling OpenGL.
+//!
+//! A context created with `Context::new_null` does not need an OpenGL context (nor a window):
+//! objects get fake names, buffer memory lives on the CPU, fences are signaled as soon as
+//! they are submitted, and the commands that would be sent to OpenGL are appended to a log
+//! that can be inspected with `Context::take_commands`.
+//!
+//! Commands are recorded after redundant state changes have been eliminated by the
+//! `StateCache`, so the log can be used to test the sequence of state changes of a frame.
+//!
+use gfx::{MemoryBarriers, StateGroupMask, TextureDesc};
+use gl::types::*;
+use std::collections::HashMap;
+use std::mem;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A command recorded by the null backend.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    CreateBuffer {
+        obj: GLuint,
+        byte_size: usize,
+    },
+    DeleteBuffer {
+        obj: GLuint,
+    },
+    CreateTexture {
+        obj: GLuint,
+        desc: TextureDesc,
+    },
+    DeleteTexture {
+        obj: GLuint,
+    },
+    CreateFramebuffer {
+        obj: GLuint,
+    },
+    /// `divisors` contains the instance step rate of each vertex buffer slot used
+    /// by the input layout.
+    CreateVertexArray {
+        obj: GLuint,
+        divisors: Vec<(u32, u32)>,
+    },
+    DeleteFramebuffer {
+        obj: GLuint,
+    },
+    /// `changed` contains the state groups of the pipeline that differ from the previous state:
+    /// only those are applied.
+    BindGraphicsPipeline {
+        vao: GLuint,
+        program: GLuint,
+        changed: StateGroupMask,
+    },
+    BindComputePipeline {
+        program: GLuint,
+    },
+    BindTarget {
+        framebuffer: GLuint,
+        viewports: Vec<(f32, f32, f32, f32)>,
+    },
+    BindUniformBuffer {
+        slot: u32,
+        obj: GLuint,
+        offset: usize,
+        size: usize,
+    },
+    BindStorageBuffer {
+        slot: u32,
+        obj: GLuint,
+        offset: usize,
+        size: usize,
+    },
+    BindVertexBuffer {
+        slot: u32,
+        obj: GLuint,
+        offset: usize,
+        stride: usize,
+    },
+    BindIndexBuffer {
+        obj: GLuint,
+    },
+    BindDrawIndirectBuffer {
+        obj: GLuint,
+    },
+    BindTexture {
+        slot: u32,
+        obj: GLuint,
+        sampler: GLuint,
+    },
+    BindImage {
+        slot: u32,
+        obj: GLuint,
+        mip_level: u32,
+        /// `None` if all layers are bound.
+        layer: Option<u32>,
+        access: GLenum,
+    },
+    SetScissors(Option<(i32, i32, i32, i32)>),
+    SetScissorTest(bool),
+    SetUniform {
+        program: GLuint,
+        location: u32,
+        value: Vec<f32>,
+    },
+    ClearTexture {
+        obj: GLuint,
+        mip_level: usize,
+    },
+    /// `buffer` is `gl::COLOR`, `gl::DEPTH`, `gl::STENCIL` or `gl::DEPTH_STENCIL`.
+    ClearFramebuffer {
+        obj: GLuint,
+        buffer: GLenum,
+        drawbuffer: usize,
+    },
+    MemoryBarrier(MemoryBarriers),
+    Draw {
+        topology: GLenum,
+        first: usize,
+        count: usize,
+    },
+    DrawIndexed {
+        topology: GLenum,
+        first: usize,
+        count: usize,
+        base_vertex: usize,
+    },
+    DrawInstanced {
+        topology: GLenum,
+        first: usize,
+        count: usize,
+        instance_count: usize,
+        base_instance: usize,
+    },
+    DrawIndexedInstanced {
+        topology: GLenum,
+        first: usize,
+        count: usize,
+        base_vertex: usize,
+        instance_count: usize,
+        base_instance: usize,
+    },
+    /// Indirect draws. `offset` is the byte offset of the first command in the indirect buffer.
+    DrawIndirect {
+        topology: GLenum,
+        offset: usize,
+        draw_count: usize,
+        stride: usize,
+    },
+    DrawIndexedIndirect {
+        topology: GLenum,
+        offset: usize,
+        draw_count: usize,
+        stride: usize,
+    },
+    Dispatch {
+        num_groups: (u32, u32, u32),
+    },
+    /// Copy of a mip level of a texture into a buffer. Textures have no contents in
+    /// the null backend, so the buffer is left untouched.
+    ReadTexture {
+        obj: GLuint,
+        mip_level: u32,
+        buffer: GLuint,
+    },
+    CopyBuffer {
+        src: GLuint,
+        src_offset: usize,
+        dst: GLuint,
+        dst_offset: usize,
+        size: usize,
+    },
+}
+
+/// State of a null context: the command log and the memory of buffers.
+#[derive(Debug)]
+pub(super) struct NullDevice {
+    next_object: AtomicUsize,
+    commands: Mutex<Vec<Command>>,
+    buffers: Mutex<HashMap<GLuint, Box<[u8]>>>,
+}
+
+impl NullDevice {
+    pub(super) fn new() -> NullDevice {
+        NullDevice {
+            next_object: AtomicUsize::new(1),
+            commands: Mutex::new(Vec::new()),
+            buffers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a new object name. Names are never reused, and 0 is never returned.
+    pub(super) fn gen_object(&self) -> GLuint {
+        self.next_object.fetch_add(1, Ordering::Relaxed) as GLuint
+    }
+
+    pub(super) fn record(&self, command: Command) {
+        self.commands.lock().unwrap().push(command);
+    }
+
+    pub(super) fn take_commands(&self) -> Vec<Command> {
+        mem::replace(&mut *self.commands.lock().unwrap(), Vec::new())
+    }
+
+    pub(super) fn create_buffer(&self, byte_size: usize, initial_data: Option<&[u8]>) -> GLuint {
+        let obj = self.gen_object();
+        let mut memory = vec![0u8; byte_size].into_boxed_slice();
+        if let Some(data) = initial_data {
+            memory.copy_from_slice(data);
+        }
+        self.buffers.lock().unwrap().insert(obj, memory);
+        self.record(Command::CreateBuffer { obj, byte_size });
+        obj
+    }
+
+    /// Returns a pointer to the memory of a buffer, valid until the buffer is deleted.
+    pub(super) fn map_buffer(&self, obj: GLuint) -> *mut c_void {
+        self.buffers
+            .lock()
+            .unwrap()
+            .get_mut(&obj)
+            .expect("unknown buffer")
+            .as_mut_ptr() as *mut c_void
+    }
+
+    pub(super) fn delete_buffer(&self, obj: GLuint) {
+        self.buffers.lock().unwrap().remove(&obj);
+        self.record(Command::DeleteBuffer { obj });
+    }
+
+    /// Copies data between the memory of two buffers.
+    pub(super) fn copy_buffer(
+        &self,
+        src: GLuint,
+        src_offset: usize,
+        dst: GLuint,
+        dst_offset: usize,
+        size: usize,
+    ) {
+        {
+            let mut buffers = self.buffers.lock().unwrap();
+            let data = buffers.get(&src).expect("unknown buffer")[src_offset..src_offset + size]
+                .to_vec();
+            buffers.get_mut(&dst).expect("unknown buffer")[dst_offset..dst_offset + size]
+                .copy_from_slice(&data);
+        }
+        self.record(Command::CopyBuffer {
+            src,
+            src_offset,
+            dst,
+            dst_offset,
+            size,
+        });
+    }
+
+    pub(super) fn create_texture(&self, desc: &TextureDesc) -> GLuint {
+        let obj = self.gen_object();
+        self.record(Command::CreateTexture { obj, desc: *desc });
+        obj
+    }
+
+    pub(super) fn create_framebuffer(&self) -> GLuint {
+        let obj = self.gen_object();
+        self.record(Command::CreateFramebuffer { obj });
+        obj
+    }
+}
@@ -1,4 +1,5 @@
 use super::context::Context;
+use super::null::Command;
 use super::state_group::*;
 use failure::Error;
 use gfx;
@@ -17,10 +18,14 @@ pub struct VertexAttribute {
     pub size: u32,
     pub relative_offset: i32,
     pub normalized: bool,
+    /// Number of instances drawn before advancing to the next element of the vertex buffer
+    /// bound at `slot`, or 0 to advance once per vertex.
+    /// Must be the same for all attributes sourced from the same slot.
+    pub instance_step_rate: u32,
 }
 
 pub(super) mod inner {
-    use gfx::shader::GraphicsShaderPipeline;
+    use gfx::shader::{ComputeShaderPipeline, GraphicsShaderPipeline};
     use gfx::state_group::*;
     use gfx::Context;
     use gl;
@@ -45,12 +50,28 @@ pub(super) mod inner {
 
     impl Drop for GraphicsPipeline {
         fn drop(&mut self) {
+            if self.gctx.is_null() {
+                return;
+            }
             unsafe {
                 //gl::DeleteProgram(self.program);
                 gl::DeleteVertexArrays(1, &mut self.vao);
             }
         }
     }
+
+    pub struct ComputePipeline {
+        pub gctx: Context,
+        pub shader_pipeline: Box<ComputeShaderPipeline>,
+        /// Local work group size declared in the compute shader.
+        pub workgroup_size: (u32, u32, u32),
+    }
+
+    impl ::std::fmt::Debug for ComputePipeline {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "ComputePipeline {{ workgroup_size: {:?} }}", self.workgroup_size)
+        }
+    }
 }
 
 /// trait GraphicsPipeline: Clone
@@ -62,6 +83,17 @@ pub(super) mod inner {
 #[derive(Clone, Debug, Deref, DerefMut)]
 pub struct GraphicsPipeline(Arc<inner::GraphicsPipeline>);
 
+/// A compute shader program, ready to be dispatched with `DrawExt::dispatch`.
+#[derive(Clone, Debug, Deref, DerefMut)]
+pub struct ComputePipeline(Arc<inner::ComputePipeline>);
+
+impl ComputePipeline {
+    /// Returns the local work group size declared in the compute shader (`layout(local_size_x=...)`).
+    pub fn workgroup_size(&self) -> (u32, u32, u32) {
+        self.0.workgroup_size
+    }
+}
+
 /// A graphics pipeline with an attached interface type
 //pub struct TypedGraphicsPipeline<T: ShaderInterface>(Arc<inner::GraphicsPipeline>);
 
@@ -81,6 +113,8 @@ pub struct GraphicsPipelineBuilder {
     depth_stencil_state: DepthStencilState,
     shader_pipeline: Option<Box<gfx::shader::GraphicsShaderPipeline>>,
     input_layout: Option<Vec<VertexAttribute>>,
+    /// Instance step rates by vertex buffer slot, applied to the input layout on `build`.
+    instance_step_rates: Vec<(u32, u32)>,
     primitive_topology: GLenum,
 }
 
@@ -101,6 +135,7 @@ unsafe fn gen_vertex_array(attribs: &[VertexAttribute]) -> GLuint {
             a.relative_offset as u32,
         );
         gl::VertexArrayAttribBinding(vao, i as u32, a.slot);
+        gl::VertexArrayBindingDivisor(vao, a.slot, a.instance_step_rate);
     }
 
     vao
@@ -112,6 +147,16 @@ pub enum GraphicsPipelineBuildError {
     MissingInputLayout,
     #[fail(display = "Shader pipeline was not specified")]
     MissingShaderPipeline,
+    #[fail(
+        display = "Attributes sourced from vertex buffer slot {} have different step rates",
+        slot
+    )]
+    InconsistentStepRate { slot: u32 },
+    #[fail(
+        display = "Instance step rate specified for vertex buffer slot {}, which no attribute is sourced from",
+        slot
+    )]
+    UnusedVertexBufferSlot { slot: u32 },
 }
 
 impl GraphicsPipelineBuilder {
@@ -123,6 +168,7 @@ impl GraphicsPipelineBuilder {
             depth_stencil_state: Default::default(),
             shader_pipeline: None,
             input_layout: None,
+            instance_step_rates: Vec::new(),
             primitive_topology: gl::TRIANGLES,
         }
     }
@@ -150,6 +196,14 @@ impl GraphicsPipelineBuilder {
         self
     }
 
+    /// Sets the instance step rate of all attributes of the input layout sourced from the
+    /// vertex buffer at `slot`, overriding the rate specified in the input layout.
+    /// The input layout can be specified before or after this call (e.g. with `with_glsl_file`).
+    pub fn with_instance_step_rate(mut self, slot: u32, instance_step_rate: u32) -> Self {
+        self.instance_step_rates.push((slot, instance_step_rate));
+        self
+    }
+
     pub fn with_rasterizer_state(mut self, rasterizer_state: &RasterizerState) -> Self {
         self.rasterizer_state = *rasterizer_state;
         self
@@ -166,10 +220,40 @@ impl GraphicsPipelineBuilder {
     }
 
     pub fn build(self, gctx: &Context) -> Result<GraphicsPipeline, Error> {
-        let vao = unsafe {
-            gen_vertex_array(&self
-                .input_layout
-                .ok_or(GraphicsPipelineBuildError::MissingInputLayout)?)
+        let mut input_layout = self
+            .input_layout
+            .ok_or(GraphicsPipelineBuildError::MissingInputLayout)?;
+        for &(slot, instance_step_rate) in self.instance_step_rates.iter() {
+            if !input_layout.iter().any(|a| a.slot == slot) {
+                return Err(GraphicsPipelineBuildError::UnusedVertexBufferSlot { slot }.into());
+            }
+            for a in input_layout.iter_mut().filter(|a| a.slot == slot) {
+                a.instance_step_rate = instance_step_rate;
+            }
+        }
+        for a in input_layout.iter() {
+            if input_layout
+                .iter()
+                .any(|b| b.slot == a.slot && b.instance_step_rate != a.instance_step_rate)
+            {
+                return Err(
+                    GraphicsPipelineBuildError::InconsistentStepRate { slot: a.slot }.into(),
+                );
+            }
+        }
+        let vao = match gctx.null_device() {
+            Some(null) => {
+                let obj = null.gen_object();
+                let mut divisors: Vec<(u32, u32)> = Vec::new();
+                for a in input_layout.iter() {
+                    if !divisors.iter().any(|&(slot, _)| slot == a.slot) {
+                        divisors.push((a.slot, a.instance_step_rate));
+                    }
+                }
+                null.record(Command::CreateVertexArray { obj, divisors });
+                obj
+            }
+            None => unsafe { gen_vertex_array(&input_layout) },
         };
 
         Ok(GraphicsPipeline(Arc::new(inner::GraphicsPipeline {
@@ -186,6 +270,54 @@ impl GraphicsPipelineBuilder {
     }
 }
 
+#[derive(Debug, Fail)]
+pub enum ComputePipelineBuildError {
+    #[fail(display = "Shader pipeline was not specified")]
+    MissingShaderPipeline,
+}
+
+/// Builder for compute pipelines
+pub struct ComputePipelineBuilder {
+    shader_pipeline: Option<Box<gfx::shader::ComputeShaderPipeline>>,
+}
+
+impl ComputePipelineBuilder {
+    /// Starts building a new compute pipeline.
+    pub fn new() -> Self {
+        ComputePipelineBuilder {
+            shader_pipeline: None,
+        }
+    }
+
+    pub fn with_shader_pipeline(
+        mut self,
+        shader_pipeline: Box<gfx::shader::ComputeShaderPipeline>,
+    ) -> Self {
+        self.shader_pipeline = Some(shader_pipeline);
+        self
+    }
+
+    pub fn build(self, gctx: &Context) -> Result<ComputePipeline, Error> {
+        let shader_pipeline = self
+            .shader_pipeline
+            .ok_or(ComputePipelineBuildError::MissingShaderPipeline)?;
+        let program = shader_pipeline.get_program()?;
+        let mut size = [1i32; 3];
+        // the null backend does not compile shaders: assume a work group size of 1
+        if !gctx.is_null() {
+            unsafe {
+                gl::GetProgramiv(program, gl::COMPUTE_WORK_GROUP_SIZE, size.as_mut_ptr());
+            }
+        }
+
+        Ok(ComputePipeline(Arc::new(inner::ComputePipeline {
+            gctx: gctx.clone(),
+            shader_pipeline,
+            workgroup_size: (size[0] as u32, size[1] as u32, size[2] as u32),
+        })))
+    }
+}
+
 /// A type representing a collection of shaders and pipeline state with an associated interface type.
 /// The interface is checked against the provided pipeline on creation.
 pub struct TypedGraphicsPipeline<T: ShaderInterface> {
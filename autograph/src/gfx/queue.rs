@@ -1,5 +1,6 @@
 use super::context::{Context, ContextConfig};
 use super::fence::{Fence, FenceValue};
+use super::headless::{HeadlessContext, HeadlessContextError};
 use super::upload_buffer::UploadBuffer;
 use std::cell::RefCell;
 use std::collections::VecDeque;
@@ -54,6 +55,12 @@ impl Queue {
         self.fence.borrow_mut().current_value()
     }
 
+    /// Blocks until the GPU has completed the frame with the specified fence value
+    /// (see `Frame::fence_value`).
+    pub fn wait_for(&self, value: FenceValue) {
+        self.fence.borrow_mut().wait_until(value);
+    }
+
     /// TODO document
     pub fn next_frame_fence_value(&self) -> FenceValue {
         self.fence.borrow().next_value()
@@ -78,11 +85,20 @@ impl Queue {
         fence.advance_async();
     }
 
-    pub(super) fn default_upload_buffer(&self) -> &UploadBuffer {
+    /// Returns the upload buffer used by `Frame::upload`.
+    /// Its overflow strategy can be changed, and it provides upload statistics.
+    pub fn default_upload_buffer(&self) -> &UploadBuffer {
         &self.default_upload_buffer
     }
 }
 
-pub fn create_context_and_queue(_config: &ContextConfig) -> (Context, Queue) {
-    unimplemented!()
+/// Creates a headless OpenGL context (see `gfx::headless`), and a context and queue using it.
+/// The OpenGL context is current on the calling thread, and is destroyed with the returned `Context`.
+pub fn create_context_and_queue(
+    config: &ContextConfig,
+) -> Result<(Context, Queue), HeadlessContextError> {
+    let headless = HeadlessContext::new()?;
+    let context = Context::new_headless(config, headless);
+    let queue = Queue::new(&context);
+    Ok((context, queue))
 }
@@ -0,0 +1,307 @@
+//! Asynchronous readback of textures and buffers.
+//!
+//! `Frame::read_texture` and `Frame::read_buffer` copy data into a staging buffer as part of the
+//! commands of the frame. The returned handles are resolved once the frame has completed on the GPU:
+//! poll them with `try_get`, or block with `wait`.
+//!
+use super::buffer::{BufferAny, BufferUsage};
+use super::buffer_data::BufferData;
+use super::context::Context;
+use super::fence::FenceValue;
+use super::format::{Format, GlFormatInfo};
+use super::frame::{Frame, ToBufferSlice};
+use super::null::Command;
+use super::queue::Queue;
+use super::texture::{TextureAny, TextureDesc, TextureDimensions};
+use gl;
+use gl::types::*;
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::slice;
+
+/// Persistently mapped buffer receiving the data of a readback.
+struct Staging {
+    buffer: BufferAny,
+    ptr: *const u8,
+    /// Fence value of the frame that writes the buffer.
+    fence_value: FenceValue,
+}
+
+impl Staging {
+    fn new(gctx: &Context, byte_size: usize, fence_value: FenceValue) -> Staging {
+        assert!(byte_size > 0, "empty readback");
+        let buffer = BufferAny::new(gctx, byte_size, BufferUsage::READBACK);
+        let ptr = unsafe { buffer.map_persistent_unsynchronized() as *const u8 };
+        Staging {
+            buffer,
+            ptr,
+            fence_value,
+        }
+    }
+
+    fn is_ready(&self, queue: &Queue) -> bool {
+        queue.last_completed_frame() >= self.fence_value
+    }
+
+    /// Unsafe because the GPU may still be writing to the buffer.
+    unsafe fn bytes(&self) -> &[u8] {
+        slice::from_raw_parts(self.ptr, self.buffer.byte_size())
+    }
+
+    fn try_get<T: Copy>(&self, queue: &Queue) -> Option<Vec<T>> {
+        if self.is_ready(queue) {
+            Some(unsafe { to_vec(self.bytes()) })
+        } else {
+            None
+        }
+    }
+
+    fn wait<T: Copy>(&self, queue: &Queue) -> Vec<T> {
+        queue.wait_for(self.fence_value);
+        unsafe { to_vec(self.bytes()) }
+    }
+}
+
+/// Copies bytes into a vector of `T`.
+/// Unsafe because the bytes may not be a valid representation of `T`.
+unsafe fn to_vec<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    let elem_size = mem::size_of::<T>();
+    assert!(
+        elem_size > 0 && bytes.len() % elem_size == 0,
+        "readback size is not a multiple of the element size"
+    );
+    let len = bytes.len() / elem_size;
+    let mut data = Vec::<T>::with_capacity(len);
+    ptr::copy_nonoverlapping(bytes.as_ptr(), data.as_mut_ptr() as *mut u8, bytes.len());
+    data.set_len(len);
+    data
+}
+
+/// Trait implemented by the types that texels can be read into.
+///
+/// Unsafe because implementors must be plain-old-data types for which any bit pattern
+/// is a valid value.
+pub unsafe trait TexelData: Copy {}
+
+macro_rules! impl_texel_data {
+    ($($t:ty),*) => {
+        $(
+            unsafe impl TexelData for $t {}
+            unsafe impl TexelData for [$t; 2] {}
+            unsafe impl TexelData for [$t; 3] {}
+            unsafe impl TexelData for [$t; 4] {}
+        )*
+    };
+}
+
+impl_texel_data!(u8, i8, u16, i16, u32, i32, f32);
+
+/// Size of a mip level of a texture, in texels (width, height, depth or number of layers).
+fn mip_level_size(desc: &TextureDesc, mip_level: u32) -> (u32, u32, u32) {
+    let m = |size: u32| cmp::max(1, size >> mip_level);
+    match desc.dimensions {
+        TextureDimensions::Tex1D => (m(desc.width), 1, 1),
+        TextureDimensions::Tex2D => (m(desc.width), m(desc.height), 1),
+        TextureDimensions::Tex3D => (m(desc.width), m(desc.height), m(desc.depth)),
+        TextureDimensions::Tex1DArray => (m(desc.width), desc.height, 1),
+        TextureDimensions::Tex2DArray => (m(desc.width), m(desc.height), desc.depth),
+        TextureDimensions::TexCube => (m(desc.width), m(desc.height), 6),
+    }
+}
+
+/// The contents of a mip level of a texture, available once the frame has completed.
+pub struct TextureReadback {
+    staging: Staging,
+    format: Format,
+    size: (u32, u32, u32),
+    /// Size of a texel in the staging buffer.
+    texel_size: usize,
+    /// Size of a component in the staging buffer (`None` for packed texels).
+    component_size: Option<usize>,
+}
+
+impl TextureReadback {
+    /// Format of the texels.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Size of the mip level, in texels.
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
+
+    /// Size of the texel data, in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.staging.buffer.byte_size()
+    }
+
+    /// Fence value of the frame that reads the texture.
+    pub fn fence_value(&self) -> FenceValue {
+        self.staging.fence_value
+    }
+
+    pub fn is_ready(&self, queue: &Queue) -> bool {
+        self.staging.is_ready(queue)
+    }
+
+    /// Returns the texels, bottom row first, if the frame has completed.
+    ///
+    /// `T` is either the type of a whole texel (e.g. `[u8; 4]` for `R8G8B8A8_UNORM`),
+    /// or the type of a component (`u8`), in which case there are several elements per texel.
+    /// Texels are laid out as the pixel transfer type of the format, which may be larger than
+    /// the format itself (`D32_SFLOAT_S8_UINT` texels take 8 bytes).
+    ///
+    /// Panics if the size or the alignment of `T` do not match the texel format.
+    pub fn try_get<T: TexelData>(&self, queue: &Queue) -> Option<Vec<T>> {
+        self.check_element_type::<T>();
+        self.staging.try_get(queue)
+    }
+
+    /// Waits for the frame to complete and returns the texels. See `try_get`.
+    pub fn wait<T: TexelData>(&self, queue: &Queue) -> Vec<T> {
+        self.check_element_type::<T>();
+        self.staging.wait(queue)
+    }
+
+    fn check_element_type<T>(&self) {
+        assert!(
+            self.texel_size % mem::size_of::<T>() == 0,
+            "element type does not match the texel size of {:?}",
+            self.format
+        );
+        // elements smaller than a texel must not straddle components (e.g. `f32` elements
+        // for `R8G8B8A8_UNORM`); packed formats can only be read as whole texels
+        assert!(
+            mem::size_of::<T>() == self.texel_size
+                || self
+                    .component_size
+                    .map_or(false, |size| mem::align_of::<T>() <= size),
+            "alignment of the element type does not match the components of {:?}",
+            self.format
+        );
+    }
+}
+
+/// The contents of a buffer slice, available once the frame has completed.
+pub struct BufferReadback<T: BufferData + ?Sized> {
+    staging: Staging,
+    _phantom: PhantomData<*const T>,
+}
+
+impl<T: BufferData + ?Sized> BufferReadback<T>
+where
+    T::Element: Copy,
+{
+    /// Fence value of the frame that reads the buffer.
+    pub fn fence_value(&self) -> FenceValue {
+        self.staging.fence_value
+    }
+
+    pub fn is_ready(&self, queue: &Queue) -> bool {
+        self.staging.is_ready(queue)
+    }
+
+    /// Returns the elements of the slice if the frame has completed.
+    pub fn try_get(&self, queue: &Queue) -> Option<Vec<T::Element>> {
+        self.staging.try_get(queue)
+    }
+
+    /// Waits for the frame to complete and returns the elements of the slice.
+    pub fn wait(&self, queue: &Queue) -> Vec<T::Element> {
+        self.staging.wait(queue)
+    }
+}
+
+impl<'q> Frame<'q> {
+    /// Reads back the contents of a mip level of a texture.
+    /// The texture is copied into a staging buffer when the commands of the frame are executed.
+    pub fn read_texture(&self, texture: &TextureAny, mip_level: u32) -> TextureReadback {
+        let desc = *texture.desc();
+        let fmtinfo = desc.format.get_format_info();
+        assert!(
+            !fmtinfo.is_compressed(),
+            "readback of compressed textures is not supported"
+        );
+        // texels are copied with the pixel type of the format, whose size may not be the
+        // size of the internal format
+        let glfmt = GlFormatInfo::from_format(desc.format);
+        let (texel_size, component_size) = glfmt.pixel_layout();
+        let size = mip_level_size(&desc, mip_level);
+        let byte_size = (size.0 * size.1 * size.2) as usize * texel_size;
+        let gctx = self.queue().context();
+        let staging = Staging::new(gctx, byte_size, self.queue().next_frame_fence_value());
+
+        match gctx.null_device() {
+            Some(null) => null.record(Command::ReadTexture {
+                obj: texture.gl_object(),
+                mip_level,
+                buffer: staging.buffer.gl_object(),
+            }),
+            None => unsafe {
+                // make image stores visible to the copy
+                gl::MemoryBarrier(gl::TEXTURE_UPDATE_BARRIER_BIT);
+                let mut prev_pack_alignment = 0;
+                gl::GetIntegerv(gl::PACK_ALIGNMENT, &mut prev_pack_alignment);
+                gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, staging.buffer.gl_object());
+                gl::GetTextureImage(
+                    texture.gl_object(),
+                    mip_level as i32,
+                    glfmt.upload_components,
+                    glfmt.upload_ty,
+                    byte_size as i32,
+                    0 as *mut GLvoid,
+                );
+                gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+                gl::PixelStorei(gl::PACK_ALIGNMENT, prev_pack_alignment);
+            },
+        }
+
+        self.ref_texture(texture.clone());
+        TextureReadback {
+            staging,
+            format: desc.format,
+            size,
+            texel_size,
+            component_size,
+        }
+    }
+
+    /// Reads back the contents of a buffer slice.
+    /// The slice is copied into a staging buffer when the commands of the frame are executed.
+    pub fn read_buffer<U: ToBufferSlice>(&self, buffer: &U) -> BufferReadback<U::Target> {
+        let slice = unsafe { buffer.to_slice_any() };
+        let gctx = self.queue().context();
+        let staging = Staging::new(gctx, slice.byte_size, self.queue().next_frame_fence_value());
+
+        match gctx.null_device() {
+            Some(null) => null.copy_buffer(
+                slice.owner.gl_object(),
+                slice.offset,
+                staging.buffer.gl_object(),
+                0,
+                slice.byte_size,
+            ),
+            None => unsafe {
+                // make shader storage writes visible to the copy
+                gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+                gl::CopyNamedBufferSubData(
+                    slice.owner.gl_object(),
+                    staging.buffer.gl_object(),
+                    slice.offset as isize,
+                    0,
+                    slice.byte_size as isize,
+                );
+            },
+        }
+
+        self.ref_buffer(slice.owner);
+        BufferReadback {
+            staging,
+            _phantom: PhantomData,
+        }
+    }
+}
@@ -7,6 +7,11 @@ use super::upload_buffer::UploadBuffer;
 use super::{Frame, ResourceTracker};
 use failure::Error;
 use gfx;
+use gl::types::*;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Mutex;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum PrimitiveType {
@@ -31,6 +36,9 @@ pub enum TypeDesc {
     Matrix(PrimitiveType, u8, u8),
     /// A structure type: (offset, typedesc)
     Struct(Vec<(usize, TypeDesc)>),
+    /// Array with a length only known at runtime (element type, stride in bytes).
+    /// Can only appear as the last member of a shader storage block.
+    UnsizedArray(Box<TypeDesc>, usize),
     Unknown,
 }
 
@@ -63,7 +71,7 @@ pub struct UniformConstantDesc {
     pub ty: &'static TypeDesc,
 }
 
-/// An uniform buffer
+/// An uniform buffer, or a shader storage buffer
 #[derive(Clone, Debug)]
 pub struct UniformBufferDesc {
     pub name: Option<String>,
@@ -152,6 +160,31 @@ impl SampledTextureInterface for SampledTexture2D {
     }
 }
 
+/// A trait defined for types that can be bound to an image unit.
+pub trait ImageInterface {
+    type TextureType: TextureInterface;
+    fn get_mip_level(&self) -> u32;
+    fn get_layer(&self) -> Option<u32>;
+    fn get_access(&self) -> GLenum;
+    fn to_texture_any(&self) -> TextureAny;
+}
+
+impl<T: TextureInterface + Clone> ImageInterface for ImageView<T> {
+    type TextureType = T;
+    fn get_mip_level(&self) -> u32 {
+        self.mip_level
+    }
+    fn get_layer(&self) -> Option<u32> {
+        self.layer
+    }
+    fn get_access(&self) -> GLenum {
+        self.access
+    }
+    fn to_texture_any(&self) -> TextureAny {
+        self.texture.clone().into()
+    }
+}
+
 /// Trait implemented by types that can serve as a vertex attribute.
 pub unsafe trait VertexAttributeType {
     /// The equivalent OpenGL type (the type seen by the shader).
@@ -247,6 +280,27 @@ impl_uniform_type!([[f32; 2]; 2], TypeDesc::Matrix(PrimitiveType::Float, 2, 2));
 impl_uniform_type!([[f32; 3]; 3], TypeDesc::Matrix(PrimitiveType::Float, 3, 3));
 impl_uniform_type!([[f32; 4]; 4], TypeDesc::Matrix(PrimitiveType::Float, 4, 4));
 
+// Slices are described as unsized arrays. Since there are no generic statics, the descriptions
+// are created on first use and kept until the end of the program.
+unsafe impl<T: BufferLayout + 'static> BufferLayout for [T] {
+    fn get_description() -> &'static TypeDesc {
+        lazy_static! {
+            static ref UNSIZED_ARRAYS: Mutex<HashMap<TypeId, &'static TypeDesc>> =
+                Mutex::new(HashMap::new());
+        }
+        *UNSIZED_ARRAYS
+            .lock()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                Box::leak(Box::new(TypeDesc::UnsizedArray(
+                    Box::new(T::get_description().clone()),
+                    mem::size_of::<T>(),
+                )))
+            })
+    }
+}
+
 /// Trait implemented by types that can be bound to the pipeline as a buffer object
 pub unsafe trait BufferInterface: gfx::ToBufferSliceAny {
     /// Get the layout of the buffer data, if it is known.
@@ -268,7 +322,7 @@ unsafe impl BufferInterface for gfx::BufferAny
 }*/
 
 // impl for typed buffers
-unsafe impl<T: BufferData + BufferLayout> BufferInterface for gfx::BufferSlice<T> {
+unsafe impl<T: BufferData + BufferLayout + ?Sized> BufferInterface for gfx::BufferSlice<T> {
     fn get_layout() -> Option<&'static TypeDesc> {
         Some(<T as BufferLayout>::get_description())
     }
@@ -341,6 +395,10 @@ pub trait ShaderInterfaceDesc: Sync + 'static {
     fn get_index_buffer(&self) -> Option<&IndexBufferDesc>;
     /// Returns the list of texture/sampler pairs (`#[texture_binding(index=...,data_type=...)]`)
     fn get_texture_bindings(&self) -> &[TextureBindingDesc];
+    /// Returns the list of shader storage buffers (`#[storage_buffer(index=...)]`)
+    fn get_storage_buffers(&self) -> &[UniformBufferDesc];
+    /// Returns the list of images bound to image units (`#[image(index=...)]`)
+    fn get_images(&self) -> &[TextureBindingDesc];
 }
 
 pub struct InterfaceBindingContext<'a> {
@@ -42,19 +42,42 @@ impl BlendState {
     }
 }
 
+/// Stencil test and operations for one face of a primitive.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct StencilOpState {
+    /// Operation when the stencil test fails.
+    pub stencil_fail_op: GLenum,
+    /// Operation when the stencil test passes but the depth test fails.
+    pub depth_fail_op: GLenum,
+    /// Operation when both tests pass.
+    pub pass_op: GLenum,
+    pub compare_func: GLenum,
+}
+
+impl Default for StencilOpState {
+    fn default() -> StencilOpState {
+        StencilOpState {
+            stencil_fail_op: gl::KEEP,
+            depth_fail_op: gl::KEEP,
+            pass_op: gl::KEEP,
+            compare_func: gl::ALWAYS,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct DepthStencilState {
     pub depth_test_enable: bool,
     pub depth_write_enable: bool,
     pub stencil_enable: bool,
     pub depth_test_func: GLenum,
-    pub stencil_face: GLenum,
-    pub stencil_func: GLenum,
+    pub stencil_front: StencilOpState,
+    pub stencil_back: StencilOpState,
     pub stencil_ref: i32,
-    pub stencil_mask: u32,
-    pub stencil_op_s_fail: GLenum,
-    pub stencil_op_dp_fail: GLenum,
-    pub stencil_op_dp_pass: GLenum,
+    /// Mask applied to the reference value and the stored stencil value before comparison.
+    pub stencil_read_mask: u32,
+    /// Bits of the stencil buffer that can be written.
+    pub stencil_write_mask: u32,
 }
 
 impl Default for DepthStencilState {
@@ -64,13 +87,11 @@ impl Default for DepthStencilState {
             depth_write_enable: false,
             stencil_enable: false,
             depth_test_func: gl::LEQUAL,
-            stencil_face: gl::FRONT_AND_BACK,
-            stencil_func: 0,
+            stencil_front: Default::default(),
+            stencil_back: Default::default(),
             stencil_ref: 0,
-            stencil_mask: 0xFFFFFFFF,
-            stencil_op_s_fail: 0,
-            stencil_op_dp_fail: 0,
-            stencil_op_dp_pass: 0,
+            stencil_read_mask: 0xFFFFFFFF,
+            stencil_write_mask: 0xFFFFFFFF,
         }
     }
 }
@@ -78,12 +99,23 @@ impl Default for DepthStencilState {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RasterizerState {
     pub fill_mode: GLenum,
+    /// Faces to cull: `gl::NONE`, `gl::FRONT`, `gl::BACK` or `gl::FRONT_AND_BACK`.
     pub cull_mode: GLenum,
+    /// Winding of front faces: `gl::CCW` or `gl::CW`.
     pub front_face: GLenum,
+    /// Constant depth offset, in units of the minimum resolvable depth difference.
     pub depth_bias: f32,
+    /// Depth offset scaled by the maximum depth slope of the polygon.
     pub slope_scaled_depth_bias: f32,
-    pub depth_clip_enable: bool,
+    /// Maximum (or minimum, if negative) depth offset. Zero disables clamping.
+    pub depth_bias_clamp: f32,
+    /// Clamp depth values instead of clipping primitives against the near and far planes.
+    pub depth_clamp_enable: bool,
+    /// If false, the scissor rectangles are ignored.
     pub scissor_enable: bool,
+    pub line_width: f32,
+    pub multisample_enable: bool,
+    pub alpha_to_coverage_enable: bool,
 }
 
 impl Default for RasterizerState {
@@ -92,10 +124,14 @@ impl Default for RasterizerState {
             fill_mode: gl::FILL,
             cull_mode: gl::NONE,
             front_face: gl::CCW,
-            depth_bias: 1.0f32,
-            slope_scaled_depth_bias: 1.0f32,
-            depth_clip_enable: false,
-            scissor_enable: false,
+            depth_bias: 0.0f32,
+            slope_scaled_depth_bias: 0.0f32,
+            depth_bias_clamp: 0.0f32,
+            depth_clamp_enable: false,
+            scissor_enable: true,
+            line_width: 1.0f32,
+            multisample_enable: true,
+            alpha_to_coverage_enable: false,
         }
     }
 }
@@ -1,5 +1,6 @@
 use super::context::Context;
 use super::format::*;
+use super::null::Command;
 use super::sampler::SamplerDesc;
 use gl;
 use gl::types::*;
@@ -8,7 +9,7 @@ use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 
 /// The dimensions of a texture.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum TextureDimensions {
     Tex1D,
@@ -27,7 +28,7 @@ bitflags! {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum MipMaps {
     Auto,
@@ -35,7 +36,7 @@ pub enum MipMaps {
 }
 
 //2+2+4+4+4+2+2+1 = 21 bytes
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct TextureDesc {
     /// Texture dimensions.
     pub dimensions: TextureDimensions,
@@ -144,6 +145,7 @@ impl From<Texture2DDesc> for TextureDesc {
 pub struct TextureObject {
     pub obj: GLuint,
     desc: TextureDesc,
+    gctx: Context,
 }
 
 /// Trait for pixel types that can be uploaded to the GPU with glTextureSubImage*.
@@ -178,7 +180,7 @@ impl TextureObject {
     }
 
     /// Create a new texture object based on the given description
-    pub fn new(_gctx: &Context, desc: &TextureDesc) -> TextureObject {
+    pub fn new(gctx: &Context, desc: &TextureDesc) -> TextureObject {
         let target = match desc.dimensions {
             TextureDimensions::Tex1D => gl::TEXTURE_1D,
             TextureDimensions::Tex2D => if desc.sample_count > 1 {
@@ -201,6 +203,14 @@ impl TextureObject {
             }
         };
 
+        if let Some(null) = gctx.null_device() {
+            return TextureObject {
+                desc: desc.clone(),
+                obj: null.create_texture(desc),
+                gctx: gctx.clone(),
+            };
+        }
+
         unsafe {
             gl::CreateTextures(target, 1, &mut obj);
 
@@ -259,6 +269,7 @@ impl TextureObject {
         TextureObject {
             desc: desc.clone(),
             obj,
+            gctx: gctx.clone(),
         }
     }
 
@@ -287,6 +298,9 @@ impl TextureObject {
             "image data size mismatch"
         );
         // TODO check size of mip level
+        if self.gctx.is_null() {
+            return;
+        }
         let glfmt = GlFormatInfo::from_format(self.desc.format);
 
         let mut prev_unpack_alignment = 0;
@@ -423,6 +437,10 @@ impl TextureObject {
 
 impl Drop for TextureObject {
     fn drop(&mut self) {
+        if let Some(null) = self.gctx.null_device() {
+            null.record(Command::DeleteTexture { obj: self.obj });
+            return;
+        }
         unsafe {
             gl::DeleteTextures(1, &self.obj);
         }
@@ -477,6 +495,14 @@ impl Texture2D {
     pub fn sampled(&self, sampler: &SamplerDesc) -> SampledTexture2D {
         SampledTexture2D(self.clone(), sampler.clone())
     }
+    pub fn image(&self, mip_level: u32, access: GLenum) -> ImageView<Texture2D> {
+        ImageView {
+            texture: self.clone(),
+            mip_level,
+            layer: None,
+            access,
+        }
+    }
     pub fn into_texture_any(self) {}
 }
 
@@ -504,3 +530,14 @@ pub struct SampledTexture2D(pub Texture2D, pub SamplerDesc);
 /// A combination of a 3D texture and a sampler
 #[derive(Clone, Debug)]
 pub struct SampledTexture3D(pub Texture3D, pub SamplerDesc);
+
+/// A mip level of a texture, as bound to an image unit for load/store operations.
+#[derive(Clone, Debug)]
+pub struct ImageView<T> {
+    pub texture: T,
+    pub mip_level: u32,
+    /// Layer of an array, cube map or 3D texture. All layers are bound if `None`.
+    pub layer: Option<u32>,
+    /// One of `gl::READ_ONLY`, `gl::WRITE_ONLY` or `gl::READ_WRITE`.
+    pub access: GLenum,
+}
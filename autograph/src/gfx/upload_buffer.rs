@@ -1,31 +1,87 @@
-use super::buffer::{BufferAny, BufferSlice, BufferSliceAny, BufferUsage};
+use super::buffer::{BufferAny, BufferSlice, BufferUsage};
 use super::buffer_data::BufferData;
 use super::context::Context;
 use super::fence::FenceValue;
-use std::cell::RefCell;
+use super::frame::ResourceTracker;
+use super::queue::Queue;
+use std::cell::{Cell, RefCell};
+use std::cmp::max;
 use std::collections::vec_deque::VecDeque;
 use std::mem;
 use std::ptr::copy_nonoverlapping;
 
+/// What to do when an upload does not fit in the ring buffers of an `UploadBuffer`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OverflowStrategy {
+    /// Return an error.
+    Fail,
+    /// Allocate an additional ring buffer, at least as large as the first one,
+    /// and keep it for subsequent uploads.
+    Chain,
+    /// Block until the GPU has finished with the oldest regions allocated by previous frames.
+    /// Fails if all regions in use were allocated by the current frame.
+    Wait,
+    /// Copy the data into a separate buffer, released when the frame is complete.
+    OneOff,
+}
+
+#[derive(Debug, Fail)]
+pub enum UploadError {
+    #[fail(display = "upload buffer is full (uploading {} bytes)", size)]
+    OutOfSpace { size: usize },
+    #[fail(
+        display = "upload of {} bytes is larger than the upload buffer ({} bytes)",
+        size, capacity
+    )]
+    TooLarge { size: usize, capacity: usize },
+}
+
+/// Upload statistics of a frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UploadStats {
+    /// Fence value of the frame.
+    pub frame: FenceValue,
+    /// Bytes uploaded during the frame.
+    pub uploaded_bytes: usize,
+    /// Maximum number of bytes in use in the ring buffers during the frame, including
+    /// the regions of previous frames that were not reclaimed yet.
+    pub high_water_mark: usize,
+    /// Number of uploads that did not fit in the ring buffers.
+    pub overflows: usize,
+    /// Bytes uploaded to one-off buffers.
+    pub one_off_bytes: usize,
+}
+
+impl UploadStats {
+    fn new(frame: FenceValue) -> UploadStats {
+        UploadStats {
+            frame,
+            uploaded_bytes: 0,
+            high_water_mark: 0,
+            overflows: 0,
+            one_off_bytes: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
 struct FencedRegion {
     fence_value: FenceValue,
     begin_ptr: usize,
     end_ptr: usize,
 }
 
-pub struct UploadBufferState {
+/// Allocation logic of a ring buffer.
+/// Regions are allocated after the last one, and reclaimed in allocation order once the GPU
+/// is done with the frame they were allocated for.
+/// Only deals with offsets, the memory is managed by `UploadBuffer`.
+#[derive(Debug)]
+pub struct RingAllocator {
+    size: usize,
     write: usize,
     begin: usize,
     used: usize,
     fenced_regions: VecDeque<FencedRegion>,
-    // TODO frame fences
-    //frame_fences:
-}
-
-pub struct UploadBuffer {
-    buffer: BufferAny, // Owned
-    state: RefCell<UploadBufferState>,
-    mapped_region: *mut u8,
 }
 
 fn align_offset(align: usize, size: usize, ptr: usize, space: usize) -> Option<usize> {
@@ -40,124 +96,314 @@ fn align_offset(align: usize, size: usize, ptr: usize, space: usize) -> Option<u
     }
 }
 
-impl UploadBuffer {
-    pub fn new(gctx: &Context, buffer_size: usize) -> UploadBuffer {
-        //UploadBuffer { _phantom: PhantomData }
+impl RingAllocator {
+    pub fn new(size: usize) -> RingAllocator {
+        RingAllocator {
+            size,
+            write: 0,
+            begin: 0,
+            used: 0,
+            fenced_regions: VecDeque::new(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of bytes between the oldest region not yet reclaimed and the end of the last
+    /// allocated region, including alignment padding.
+    pub fn used(&self) -> usize {
+        if self.fenced_regions.is_empty() {
+            0
+        } else if self.begin < self.write {
+            self.write - self.begin
+        } else {
+            self.size - self.begin + self.write
+        }
+    }
+
+    /// Returns the fence value of the oldest region not yet reclaimed.
+    pub fn oldest_fence_value(&self) -> Option<FenceValue> {
+        self.fenced_regions.front().map(|region| region.fence_value)
+    }
+
+    /// Allocates a region of `size` bytes used until the frame with the specified fence value
+    /// is complete, and returns its offset.
+    /// Returns `None` if there is not enough contiguous free space.
+    pub fn allocate(
+        &mut self,
+        size: usize,
+        align: usize,
+        fence_value: FenceValue,
+    ) -> Option<usize> {
+        if (self.begin < self.write) || (self.begin == self.write && self.used == 0) {
+            let slack = self.size - self.write;
+            // try to put the buffer in the slack space at the end
+            if let Some(newptr) = align_offset(align, size, self.write, slack) {
+                self.write = newptr;
+            } else {
+                // else, try to put it at the beginning (which is always correctly
+                // aligned)
+                if size > self.begin {
+                    return None;
+                }
+                self.write = 0;
+            }
+        } else {
+            // begin_ptr > write_ptr
+            // reclaim space in the middle
+            if let Some(newptr) = align_offset(align, size, self.write, self.begin - self.write) {
+                self.write = newptr;
+            } else {
+                return None;
+            }
+        }
+
+        let alloc_begin = self.write;
+        self.used += size;
+        self.write += size;
+        self.fenced_regions.push_back(FencedRegion {
+            begin_ptr: alloc_begin,
+            end_ptr: alloc_begin + size,
+            fence_value,
+        });
+        Some(alloc_begin)
+    }
+
+    /// Reclaims all regions allocated for frames up to `reclaim_until` (included).
+    pub fn reclaim(&mut self, reclaim_until: FenceValue) {
+        while !self.fenced_regions.is_empty()
+            && self.fenced_regions.front().unwrap().fence_value <= reclaim_until
+        {
+            let region = self.fenced_regions.pop_front().unwrap();
+            self.begin = region.end_ptr;
+            self.used -= region.end_ptr - region.begin_ptr;
+        }
+        // nothing in use: restart from the beginning to make the whole buffer available
+        if self.fenced_regions.is_empty() {
+            self.begin = 0;
+            self.write = 0;
+        }
+    }
+}
+
+/// A persistently mapped buffer managed by a `RingAllocator`.
+struct Ring {
+    buffer: BufferAny, // Owned
+    mapped_region: *mut u8,
+    allocator: RingAllocator,
+}
+
+impl Ring {
+    fn new(gctx: &Context, buffer_size: usize) -> Ring {
         let buffer = BufferAny::new(gctx, buffer_size, BufferUsage::UPLOAD);
         let mapped_region = unsafe { buffer.map_persistent_unsynchronized() as *mut u8 };
-
-        UploadBuffer {
+        Ring {
             buffer,
-            state: RefCell::new(UploadBufferState {
-                begin: 0,
-                used: 0,
-                write: 0,
-                fenced_regions: VecDeque::new(),
-            }),
             mapped_region,
+            allocator: RingAllocator::new(buffer_size),
+        }
+    }
+}
+
+pub struct UploadBuffer {
+    gctx: Context,
+    ring_size: usize,
+    overflow_strategy: Cell<OverflowStrategy>,
+    rings: RefCell<Vec<Ring>>,
+    current_frame_stats: Cell<Option<UploadStats>>,
+    last_frame_stats: Cell<Option<UploadStats>>,
+}
+
+impl UploadBuffer {
+    /// Creates an upload buffer that chains additional ring buffers when full.
+    pub fn new(gctx: &Context, buffer_size: usize) -> UploadBuffer {
+        UploadBuffer::with_overflow_strategy(gctx, buffer_size, OverflowStrategy::Chain)
+    }
+
+    pub fn with_overflow_strategy(
+        gctx: &Context,
+        buffer_size: usize,
+        overflow_strategy: OverflowStrategy,
+    ) -> UploadBuffer {
+        UploadBuffer {
+            gctx: gctx.clone(),
+            ring_size: buffer_size,
+            overflow_strategy: Cell::new(overflow_strategy),
+            rings: RefCell::new(vec![Ring::new(gctx, buffer_size)]),
+            current_frame_stats: Cell::new(None),
+            last_frame_stats: Cell::new(None),
         }
     }
 
-    /// Unsafe because the transient buffer can be reclaimed at any time with `reclaim`
-    /// according to the `reclaim_until` parameter.
+    pub fn overflow_strategy(&self) -> OverflowStrategy {
+        self.overflow_strategy.get()
+    }
+
+    pub fn set_overflow_strategy(&self, overflow_strategy: OverflowStrategy) {
+        self.overflow_strategy.set(overflow_strategy);
+    }
+
+    /// Total size of the ring buffers, including chained ones.
+    pub fn capacity(&self) -> usize {
+        self.rings
+            .borrow()
+            .iter()
+            .map(|ring| ring.allocator.size())
+            .sum()
+    }
+
+    /// Statistics of the frame currently uploading, if it has uploaded anything.
+    pub fn current_frame_stats(&self) -> Option<UploadStats> {
+        self.current_frame_stats.get()
+    }
+
+    /// Statistics of the last frame that uploaded something before the current one.
+    pub fn last_frame_stats(&self) -> Option<UploadStats> {
+        self.last_frame_stats.get()
+    }
+
+    /// Unsafe because the transient buffer is reclaimed once the GPU has completed the current
+    /// frame of `queue`, regardless of the lifetime of the returned slice.
     /// For a safe implementation, allocate through Frame
-    /// TODO do not panic if the upload buffer is full
     pub unsafe fn upload<T: BufferData + ?Sized>(
         &self,
         data: &T,
         align: usize,
-        fence_value: FenceValue,
-        reclaim_until: FenceValue,
-    ) -> BufferSlice<T> {
+        queue: &Queue,
+        tracker: &mut ResourceTracker,
+    ) -> Result<BufferSlice<T>, UploadError> {
         let byte_size = mem::size_of_val(data);
         let ptr = data as *const T as *const u8;
-        let slice = self
-            .allocate(byte_size, align, fence_value, reclaim_until)
-            .expect("upload buffer is full"); // TODO expand? wait?
-        copy_nonoverlapping(
-            ptr,
-            self.mapped_region.offset(slice.offset as isize),
-            byte_size,
+        let fence_value = queue.next_frame_fence_value();
+        let mut stats = match self.current_frame_stats.get() {
+            Some(stats) if stats.frame == fence_value => stats,
+            other => {
+                if other.is_some() {
+                    self.last_frame_stats.set(other);
+                }
+                UploadStats::new(fence_value)
+            }
+        };
+
+        let allocation = self.allocate(byte_size, align, fence_value, queue.last_completed_frame());
+        let allocation = match allocation {
+            Some(allocation) => allocation,
+            None => {
+                stats.overflows += 1;
+                let result = match self.overflow_strategy.get() {
+                    OverflowStrategy::Fail => Err(self.overflow_error(byte_size)),
+                    OverflowStrategy::Chain => {
+                        Ok(self.chain_and_allocate(byte_size, align, fence_value))
+                    }
+                    OverflowStrategy::Wait => {
+                        self.wait_and_allocate(byte_size, align, fence_value, queue)
+                    }
+                    OverflowStrategy::OneOff => {
+                        let buffer = BufferAny::with_data(&self.gctx, BufferUsage::DEFAULT, data);
+                        tracker.ref_buffer(buffer.clone());
+                        stats.uploaded_bytes += byte_size;
+                        stats.one_off_bytes += byte_size;
+                        self.current_frame_stats.set(Some(stats));
+                        return Ok(buffer.get_full_slice().into_typed::<T>());
+                    }
+                };
+                match result {
+                    Ok(allocation) => allocation,
+                    Err(e) => {
+                        self.current_frame_stats.set(Some(stats));
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let rings = self.rings.borrow();
+        let (ring_index, offset) = allocation;
+        let ring = &rings[ring_index];
+        copy_nonoverlapping(ptr, ring.mapped_region.offset(offset as isize), byte_size);
+        let slice = ring.buffer.get_slice(offset, byte_size);
+        stats.uploaded_bytes += byte_size;
+        stats.high_water_mark = max(
+            stats.high_water_mark,
+            rings.iter().map(|ring| ring.allocator.used()).sum(),
         );
-        slice.into_typed::<T>()
+        self.current_frame_stats.set(Some(stats));
+        Ok(slice.into_typed::<T>())
     }
 
-    /// Unsafe for the same reasons as `upload`
-    unsafe fn allocate(
+    /// Allocates in the first ring buffer with enough space, and returns (ring index, offset).
+    fn allocate(
         &self,
         size: usize,
         align: usize,
         fence_value: FenceValue,
         reclaim_until: FenceValue,
-    ) -> Option<BufferSliceAny> {
-        //debug!("alloc size={}, align={}, fence_value={:?}", size, align, fence_value);
-        if let Some(offset) = self.try_allocate_contiguous(size, align, fence_value) {
-            Some(self.buffer.get_slice(offset, size))
-        } else {
+    ) -> Option<(usize, usize)> {
+        let mut rings = self.rings.borrow_mut();
+        for (i, ring) in rings.iter_mut().enumerate() {
+            if let Some(offset) = ring.allocator.allocate(size, align, fence_value) {
+                return Some((i, offset));
+            }
             // reclaim and try again (not enough contiguous free space)
-            self.reclaim(reclaim_until);
-            if let Some(offset) = self.try_allocate_contiguous(size, align, fence_value) {
-                Some(self.buffer.get_slice(offset, size))
-            } else {
-                None
+            ring.allocator.reclaim(reclaim_until);
+            if let Some(offset) = ring.allocator.allocate(size, align, fence_value) {
+                return Some((i, offset));
             }
         }
+        None
     }
 
-    fn try_allocate_contiguous(
+    fn chain_and_allocate(
         &self,
         size: usize,
         align: usize,
         fence_value: FenceValue,
-    ) -> Option<usize> {
-        //assert!(size < self.buffer.size);
-        let mut state = self.state.borrow_mut();
+    ) -> (usize, usize) {
+        let mut rings = self.rings.borrow_mut();
+        // the new buffer is empty: its start is aligned for any allocation
+        let mut ring = Ring::new(&self.gctx, max(self.ring_size, size));
+        let offset = ring
+            .allocator
+            .allocate(size, align, fence_value)
+            .expect("allocation failed in a new ring buffer");
+        rings.push(ring);
+        (rings.len() - 1, offset)
+    }
 
-        if (state.begin < state.write) || (state.begin == state.write && state.used == 0) {
-            let slack = self.buffer.byte_size() - state.write;
-            // try to put the buffer in the slack space at the end
-            if let Some(newptr) = align_offset(align, size, state.write, slack) {
-                state.write = newptr;
-            } else {
-                // else, try to put it at the beginning (which is always correctly
-                // aligned)
-                if size > state.begin {
-                    return None;
+    fn wait_and_allocate(
+        &self,
+        size: usize,
+        align: usize,
+        fence_value: FenceValue,
+        queue: &Queue,
+    ) -> Result<(usize, usize), UploadError> {
+        let mut rings = self.rings.borrow_mut();
+        for (i, ring) in rings.iter_mut().enumerate() {
+            while let Some(oldest) = ring.allocator.oldest_fence_value() {
+                if oldest >= fence_value {
+                    // the remaining regions are used by the current frame
+                    break;
+                }
+                queue.wait_for(oldest);
+                ring.allocator.reclaim(oldest);
+                if let Some(offset) = ring.allocator.allocate(size, align, fence_value) {
+                    return Ok((i, offset));
                 }
-                state.write = 0;
-            }
-        } else {
-            // begin_ptr > write_ptr
-            // reclaim space in the middle
-            if let Some(newptr) = align_offset(align, size, state.write, state.begin - state.write)
-            {
-                state.write = newptr;
-            } else {
-                return None;
             }
         }
-
-        let alloc_begin = state.write;
-        state.used += size;
-        state.write += size;
-        state.fenced_regions.push_back(FencedRegion {
-            begin_ptr: alloc_begin,
-            end_ptr: alloc_begin + size,
-            fence_value,
-        });
-        Some(alloc_begin)
+        Err(self.overflow_error(size))
     }
 
-    fn reclaim(&self, reclaim_until: FenceValue) {
-        //debug!("reclaiming: last_completed_fence_step={:?}", last_completed_fence_step);
-        let mut state = self.state.borrow_mut();
-        while !state.fenced_regions.is_empty()
-            && state.fenced_regions.front().unwrap().fence_value <= reclaim_until
-        {
-            let region = state.fenced_regions.pop_front().unwrap();
-            //debug!("reclaiming region {}-{} because all commands using these regions have completed (region={:?} < last_completed_fence_step={:?})", region.begin_ptr, region.end_ptr, region.fence_value, last_completed_fence_step);
-            state.begin = region.end_ptr;
-            state.used -= region.end_ptr - region.begin_ptr;
+    fn overflow_error(&self, size: usize) -> UploadError {
+        if size > self.ring_size {
+            UploadError::TooLarge {
+                size,
+                capacity: self.ring_size,
+            }
+        } else {
+            UploadError::OutOfSpace { size }
         }
     }
 }
@@ -174,3 +420,93 @@ fn test_upload_buffer_lifetimes() {
     // frame drops here
     // ctx drops here
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::context::ContextConfig;
+    use super::super::frame::Frame;
+    use super::*;
+
+    #[test]
+    fn test_ring_allocator_alignment() {
+        let mut ring = RingAllocator::new(1024);
+        assert_eq!(ring.allocate(10, 1, FenceValue(0)), Some(0));
+        assert_eq!(ring.allocate(16, 256, FenceValue(0)), Some(256));
+        assert_eq!(ring.allocate(4, 4, FenceValue(0)), Some(272));
+        assert_eq!(ring.used(), 276);
+        // does not fit at the end, and the beginning is still in use
+        assert_eq!(ring.allocate(16, 1024, FenceValue(0)), None);
+    }
+
+    #[test]
+    fn test_ring_allocator_wrap_around() {
+        let mut ring = RingAllocator::new(1024);
+        assert_eq!(ring.allocate(512, 256, FenceValue(0)), Some(0));
+        assert_eq!(ring.allocate(256, 256, FenceValue(1)), Some(512));
+        assert_eq!(ring.allocate(512, 256, FenceValue(2)), None);
+        assert_eq!(ring.oldest_fence_value(), Some(FenceValue(0)));
+
+        // frame 0 is complete: wraps around to the beginning
+        ring.reclaim(FenceValue(0));
+        assert_eq!(ring.oldest_fence_value(), Some(FenceValue(1)));
+        assert_eq!(ring.allocate(512, 256, FenceValue(2)), Some(0));
+        // full
+        assert_eq!(ring.used(), 1024);
+        assert_eq!(ring.allocate(1, 1, FenceValue(2)), None);
+
+        // space freed in the middle
+        ring.reclaim(FenceValue(1));
+        assert_eq!(ring.allocate(256, 256, FenceValue(3)), Some(512));
+        assert_eq!(ring.allocate(512, 256, FenceValue(3)), None);
+    }
+
+    #[test]
+    fn test_ring_allocator_reclaim_all() {
+        let mut ring = RingAllocator::new(1024);
+        assert_eq!(ring.allocate(768, 256, FenceValue(0)), Some(0));
+        assert_eq!(ring.allocate(768, 256, FenceValue(1)), None);
+        ring.reclaim(FenceValue(0));
+        assert_eq!(ring.used(), 0);
+        assert_eq!(ring.oldest_fence_value(), None);
+        // an empty ring can hold an allocation of its full size
+        assert_eq!(ring.allocate(1024, 256, FenceValue(1)), Some(0));
+        // too large
+        ring.reclaim(FenceValue(1));
+        assert_eq!(ring.allocate(1025, 1, FenceValue(2)), None);
+    }
+
+    #[test]
+    fn test_wait_overflow_strategy() {
+        let context = Context::new_null(&ContextConfig {
+            max_frames_in_flight: 2,
+        });
+        let mut queue = Queue::new(&context);
+        // the GPU completes frames only when waited on
+        queue.fence.borrow_mut().signal_null_on_wait();
+        let upload_buffer =
+            UploadBuffer::with_overflow_strategy(&context, 1024, OverflowStrategy::Wait);
+        let data = [0u8; 768];
+
+        {
+            let frame = Frame::new(&mut queue);
+            frame.upload_into(&upload_buffer, &data);
+            frame.submit();
+        }
+        assert_eq!(queue.last_completed_frame(), FenceValue(-1));
+
+        // the ring is full with the data of frame 0: wait for it
+        {
+            let frame = Frame::new(&mut queue);
+            assert!(frame.try_upload_into(&upload_buffer, &data).is_ok());
+            frame.submit();
+        }
+        assert_eq!(queue.last_completed_frame(), FenceValue(0));
+        let stats = upload_buffer.current_frame_stats().unwrap();
+        assert_eq!(stats.frame, FenceValue(1));
+        assert_eq!(stats.overflows, 1);
+        assert_eq!(stats.uploaded_bytes, 768);
+        assert_eq!(stats.high_water_mark, 768);
+        // no ring buffer was chained
+        assert_eq!(upload_buffer.capacity(), 1024);
+    }
+}
@@ -32,8 +32,12 @@ extern crate notify;
 extern crate num_traits;
 extern crate petgraph;
 extern crate rspirv;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate shaderc;
 extern crate spirv_headers as spirv;
+extern crate toml;
 extern crate url;
 #[macro_use]
 extern crate derive_deref;
@@ -51,6 +55,8 @@ macro_rules! vertex_type_offset_of {
 pub mod aabb;
 pub mod cache;
 pub mod camera;
+#[cfg(all(unix, not(target_os = "macos")))]
+mod egl;
 pub mod framegraph;
 pub mod gfx;
 pub mod gl;
@@ -20,10 +20,13 @@ use self::glutin;
 use self::glutin::GlContext;
 use self::image;
 use self::time;
+use std::env;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::Read;
 use std::os::raw::c_void;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct TestWindowConfig {
     pub name: &'static str,
@@ -134,6 +137,321 @@ where
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Offscreen rendering tests.
+//
+// The scene is rendered with a headless context into a texture, read back, and compared against
+// a reference image in `tests/reference`. When the comparison fails, the output and an image
+// highlighting the differing pixels are saved in `target/render-tests`.
+//
+// The tests do not need a display server or a GPU: run them with Mesa's software rasterizer
+// (`LIBGL_ALWAYS_SOFTWARE=1`). Set `AUTOGRAPH_UPDATE_REFERENCES=1` to overwrite the reference
+// images with the current output.
+//
+// 8-bit render targets are stored as PNG. Floating-point render targets are stored in the
+// Radiance HDR format (without alpha), as the `image` crate does not support OpenEXR.
+
+pub const REFERENCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reference");
+pub const OUTPUT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../target/render-tests");
+pub const UPDATE_REFERENCES_VAR: &str = "AUTOGRAPH_UPDATE_REFERENCES";
+
+/// Maximum difference allowed between a rendered image and its reference.
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+    /// Maximum absolute difference between two channels of a pixel, for the pixels to be
+    /// considered identical (8-bit channels are normalized to `[0,1]`).
+    pub max_channel_difference: f32,
+    /// Number of pixels that can exceed `max_channel_difference`.
+    pub max_differing_pixels: usize,
+}
+
+impl Tolerance {
+    pub fn exact() -> Tolerance {
+        Tolerance {
+            max_channel_difference: 0.0,
+            max_differing_pixels: 0,
+        }
+    }
+}
+
+impl Default for Tolerance {
+    /// Allows small differences in rounding between OpenGL implementations.
+    fn default() -> Tolerance {
+        Tolerance {
+            max_channel_difference: 2.0 / 255.0,
+            max_differing_pixels: 0,
+        }
+    }
+}
+
+pub struct OffscreenTestConfig {
+    /// Name of the reference image, without extension.
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// `R8G8B8A8_UNORM`, `R8G8B8A8_SRGB` or `R32G32B32A32_SFLOAT`.
+    pub format: gfx::Format,
+    /// Format of the depth (or depth-stencil) attachment, if any.
+    pub depth_stencil_format: Option<gfx::Format>,
+    pub tolerance: Tolerance,
+}
+
+/// An RGBA image, top row first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+    /// Saved as HDR instead of PNG.
+    pub hdr: bool,
+}
+
+impl TestImage {
+    pub fn new(width: u32, height: u32, pixels: Vec<[f32; 4]>, hdr: bool) -> TestImage {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        TestImage {
+            width,
+            height,
+            pixels,
+            hdr,
+        }
+    }
+
+    /// Creates an image from rows read back from OpenGL (bottom row first).
+    pub fn from_gl_rows(width: u32, height: u32, pixels: Vec<[f32; 4]>, hdr: bool) -> TestImage {
+        let mut flipped = Vec::with_capacity(pixels.len());
+        for row in pixels.chunks(width as usize).rev() {
+            flipped.extend_from_slice(row);
+        }
+        TestImage::new(width, height, flipped, hdr)
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [f32; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn extension(&self) -> &'static str {
+        if self.hdr {
+            "hdr"
+        } else {
+            "png"
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, hdr: bool) -> Result<TestImage, failure::Error> {
+        if hdr {
+            let decoder = image::hdr::HDRDecoder::new(io::BufReader::new(File::open(path)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()?
+                .iter()
+                .map(|p| [p.data[0], p.data[1], p.data[2], 1.0])
+                .collect();
+            Ok(TestImage::new(metadata.width, metadata.height, pixels, true))
+        } else {
+            let img = image::open(path)?.to_rgba();
+            let (width, height) = img.dimensions();
+            let pixels = img
+                .pixels()
+                .map(|p| {
+                    let c = |i: usize| p.data[i] as f32 / 255.0;
+                    [c(0), c(1), c(2), c(3)]
+                })
+                .collect();
+            Ok(TestImage::new(width, height, pixels, false))
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), failure::Error> {
+        if self.hdr {
+            let pixels: Vec<_> = self
+                .pixels
+                .iter()
+                .map(|p| image::Rgb([p[0], p[1], p[2]]))
+                .collect();
+            image::hdr::HDREncoder::new(File::create(path)?).encode(
+                &pixels,
+                self.width as usize,
+                self.height as usize,
+            )?;
+        } else {
+            let bytes = self
+                .pixels
+                .iter()
+                .flat_map(|p| p.iter().map(|&c| to_unorm8(c)).collect::<Vec<_>>())
+                .collect();
+            image::RgbaImage::from_raw(self.width, self.height, bytes)
+                .unwrap()
+                .save(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_unorm8(c: f32) -> u8 {
+    (c.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+/// Result of the comparison of two images of the same size.
+pub struct ImageDiff {
+    pub differing_pixels: usize,
+    pub max_channel_difference: f32,
+    /// Differing pixels in red, over a darkened grayscale version of the compared image.
+    pub diff_image: TestImage,
+}
+
+pub fn diff_images(image: &TestImage, reference: &TestImage, tolerance: &Tolerance) -> ImageDiff {
+    assert_eq!(
+        (image.width, image.height),
+        (reference.width, reference.height)
+    );
+    let mut differing_pixels = 0;
+    let mut max_channel_difference = 0.0f32;
+    let mut pixels = Vec::with_capacity(image.pixels.len());
+    for (a, b) in image.pixels.iter().zip(reference.pixels.iter()) {
+        let diff = (0..4).fold(0.0f32, |d, i| d.max((a[i] - b[i]).abs()));
+        max_channel_difference = max_channel_difference.max(diff);
+        if diff > tolerance.max_channel_difference {
+            differing_pixels += 1;
+            pixels.push([1.0, 0.0, 0.0, 1.0]);
+        } else {
+            let luma = 0.25 * (0.2126 * a[0] + 0.7152 * a[1] + 0.0722 * a[2]).min(1.0);
+            pixels.push([luma, luma, luma, 1.0]);
+        }
+    }
+    ImageDiff {
+        differing_pixels,
+        max_channel_difference,
+        diff_image: TestImage::new(image.width, image.height, pixels, false),
+    }
+}
+
+/// Renders a single frame into an offscreen render target and reads it back.
+pub fn render_offscreen<F>(config: &OffscreenTestConfig, body: F) -> TestImage
+where
+    F: FnOnce(&gfx::Context, &mut TestFrameInfo),
+{
+    let (context, mut queue) = gfx::create_context_and_queue(&gfx::ContextConfig {
+        max_frames_in_flight: 1,
+    }).expect("could not create a headless OpenGL context");
+    let texture = gfx::Texture2D::new(
+        &context,
+        &gfx::Texture2DDesc::simple(config.format, config.width, config.height),
+    );
+    let mut framebuffer = gfx::FramebufferBuilder::new(&context);
+    framebuffer.attach(0, &texture).unwrap();
+    let depth_stencil_texture = config.depth_stencil_format.map(|format| {
+        gfx::Texture2D::new(
+            &context,
+            &gfx::Texture2DDesc::simple(format, config.width, config.height),
+        )
+    });
+    if let Some(ref depth_stencil_texture) = depth_stencil_texture {
+        framebuffer.attach_depth(depth_stencil_texture).unwrap();
+    }
+    let framebuffer = framebuffer.build();
+
+    let readback = {
+        let mut frame = gfx::Frame::new(&mut queue);
+        {
+            let mut frame_info = TestFrameInfo {
+                frame: &mut frame,
+                framebuffer: &framebuffer,
+                width: config.width,
+                height: config.height,
+                aspect_ratio: config.width as f32 / config.height as f32,
+                frame_index: 0,
+                delta_s: 0.0,
+            };
+            body(&context, &mut frame_info);
+        }
+        let readback = frame.read_texture(&texture, 0);
+        frame.submit();
+        readback
+    };
+
+    match config.format {
+        gfx::Format::R8G8B8A8_UNORM | gfx::Format::R8G8B8A8_SRGB => {
+            let pixels = readback
+                .wait::<[u8; 4]>(&queue)
+                .iter()
+                .map(|p| {
+                    let c = |i: usize| p[i] as f32 / 255.0;
+                    [c(0), c(1), c(2), c(3)]
+                })
+                .collect();
+            TestImage::from_gl_rows(config.width, config.height, pixels, false)
+        }
+        gfx::Format::R32G32B32A32_SFLOAT => {
+            let pixels = readback.wait::<[f32; 4]>(&queue);
+            TestImage::from_gl_rows(config.width, config.height, pixels, true)
+        }
+        other => panic!("unsupported render target format for offscreen tests: {:?}", other),
+    }
+}
+
+/// Compares an image against the reference image with the given name.
+/// Panics if they differ by more than the tolerance, after saving the image and the differences
+/// in `OUTPUT_DIR`.
+pub fn check_against_reference(name: &str, image: &TestImage, tolerance: &Tolerance) {
+    let file_name = format!("{}.{}", name, image.extension());
+    let reference_path = Path::new(REFERENCE_DIR).join(&file_name);
+
+    if env::var_os(UPDATE_REFERENCES_VAR).is_some() {
+        fs::create_dir_all(REFERENCE_DIR).unwrap();
+        image.save(&reference_path).unwrap();
+        return;
+    }
+
+    let save_output = |suffix: &str, image: &TestImage| -> PathBuf {
+        fs::create_dir_all(OUTPUT_DIR).unwrap();
+        let path =
+            Path::new(OUTPUT_DIR).join(format!("{}{}.{}", name, suffix, image.extension()));
+        image.save(&path).unwrap();
+        path
+    };
+
+    let reference = match TestImage::load(&reference_path, image.hdr) {
+        Ok(reference) => reference,
+        Err(e) => panic!(
+            "could not load reference image {} ({}); output saved to {} (set {}=1 to accept it)",
+            reference_path.display(),
+            e,
+            save_output("", image).display(),
+            UPDATE_REFERENCES_VAR
+        ),
+    };
+
+    assert_eq!(
+        (image.width, image.height),
+        (reference.width, reference.height),
+        "{}: size mismatch; output saved to {}",
+        name,
+        save_output("", image).display()
+    );
+
+    let diff = diff_images(image, &reference, tolerance);
+    assert!(
+        diff.differing_pixels <= tolerance.max_differing_pixels,
+        "{}: {} pixels differ from the reference (at most {} allowed, max. channel difference {}); output saved to {}, differences to {}",
+        name,
+        diff.differing_pixels,
+        tolerance.max_differing_pixels,
+        diff.max_channel_difference,
+        save_output("", image).display(),
+        save_output("-diff", &diff.diff_image).display()
+    );
+}
+
+/// Renders a single frame offscreen and compares it against the reference image `config.name`.
+pub fn run_offscreen_test<F>(config: &OffscreenTestConfig, body: F)
+where
+    F: FnOnce(&gfx::Context, &mut TestFrameInfo),
+{
+    let image = render_offscreen(config, body);
+    check_against_reference(config.name, &image, &config.tolerance);
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub fn load_tex2d<P: AsRef<Path>>(
     ctx: &gfx::Context,
@@ -192,13 +510,15 @@ pub fn load_spv_modules(src: &str) -> SpirvModules {
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
-pub fn dump_error(error: &failure::Error) {
+/// Formats an error and its causes.
+pub fn format_error(error: &failure::Error) -> String {
     let mut fail = error.cause();
-    eprintln!("error: {}", fail);
+    let mut message = format!("error: {}", fail);
     while let Some(cause) = fail.cause() {
-        eprintln!("Caused by: {}", cause);
+        message += &format!("\nCaused by: {}", cause);
         fail = cause;
     }
+    message
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -214,11 +534,11 @@ pub fn load_pipeline_and_check_interface<I: ShaderInterface>(src: &str) {
         spv.tes.as_ref().map(|v| v.as_ref()),
     );
     if let Err(ShaderInterfaceVerificationError(ref errors)) = result {
-        for err in errors.iter() {
-            dump_error(err);
-            eprintln!();
-        }
-        panic!()
+        let messages: Vec<String> = errors.iter().map(format_error).collect();
+        panic!(
+            "shader interface verification failed:\n{}",
+            messages.join("\n")
+        );
     }
 }
 
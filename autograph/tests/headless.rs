@@ -0,0 +1,38 @@
+extern crate autograph;
+
+use autograph::gfx;
+use autograph::gfx::DrawExt;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[test]
+fn test_headless_clear_and_read_back() {
+    let (context, mut queue) = match gfx::create_context_and_queue(&gfx::ContextConfig {
+        max_frames_in_flight: 3,
+    }) {
+        Ok(context_and_queue) => context_and_queue,
+        Err(e) => {
+            // no GPU or EGL driver on this machine
+            eprintln!("skipping test_headless_clear_and_read_back: {}", e);
+            return;
+        }
+    };
+    assert!(context.is_headless());
+
+    let texture = gfx::Texture2D::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 16, 8),
+    );
+    let mut framebuffer = gfx::FramebufferBuilder::new(&context);
+    framebuffer.attach(0, &texture).unwrap();
+    let framebuffer = framebuffer.build();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        frame.clear_framebuffer_color(&framebuffer, 0, &[1.0, 0.0, 0.0, 1.0]);
+        frame.submit();
+    }
+
+    let pixels = framebuffer.read_pixels(0);
+    assert_eq!(pixels.len(), 16 * 8 * 4);
+    assert!(pixels.chunks(4).all(|p| p == [255, 0, 0, 255]));
+}
@@ -0,0 +1,844 @@
+extern crate autograph;
+extern crate failure;
+
+use autograph::framegraph;
+use autograph::gfx;
+use autograph::gfx::null::Command;
+use autograph::gfx::shader_interface::ShaderInterfaceDesc;
+use autograph::gfx::DrawExt;
+use autograph::gfx::ToBufferSliceAny;
+use autograph::gl;
+use autograph::gl::types::*;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Shader pipelines that are not backed by any GL program: the null backend does not compile shaders.
+struct NullShader;
+impl gfx::Shader for NullShader {}
+impl gfx::VertexShader for NullShader {}
+impl gfx::FragmentShader for NullShader {}
+impl gfx::ComputeShader for NullShader {}
+
+struct NullProgram(GLuint);
+
+impl gfx::GraphicsShaderPipeline for NullProgram {
+    fn vertex_shader(&self) -> &gfx::VertexShader {
+        &NullShader
+    }
+    fn fragment_shader(&self) -> &gfx::FragmentShader {
+        &NullShader
+    }
+    fn geometry_shader(&self) -> Option<&gfx::GeometryShader> {
+        None
+    }
+    fn tess_control_shader(&self) -> Option<&gfx::TessControlShader> {
+        None
+    }
+    fn tess_eval_shader(&self) -> Option<&gfx::TessEvalShader> {
+        None
+    }
+    fn is_compatible_with(&self, _interface: &ShaderInterfaceDesc) -> Result<(), failure::Error> {
+        Ok(())
+    }
+    fn get_program(&self) -> Result<GLuint, failure::Error> {
+        Ok(self.0)
+    }
+}
+
+impl gfx::ComputeShaderPipeline for NullProgram {
+    fn compute_shader(&self) -> &gfx::ComputeShader {
+        &NullShader
+    }
+    fn is_compatible_with(&self, _interface: &ShaderInterfaceDesc) -> Result<(), failure::Error> {
+        Ok(())
+    }
+    fn get_program(&self) -> Result<GLuint, failure::Error> {
+        Ok(self.0)
+    }
+}
+
+fn null_context() -> (gfx::Context, gfx::Queue, GLuint) {
+    let context = gfx::Context::new_null(&gfx::ContextConfig {
+        max_frames_in_flight: 3,
+    });
+    let queue = gfx::Queue::new(&context);
+    // the queue creates its default upload buffer
+    let commands = context.take_commands();
+    let upload_buffer = match commands[..] {
+        [Command::CreateBuffer { obj, byte_size }] => {
+            assert_eq!(byte_size, gfx::DEFAULT_UPLOAD_BUFFER_SIZE);
+            obj
+        }
+        ref other => panic!("unexpected commands: {:?}", other),
+    };
+    (context, queue, upload_buffer)
+}
+
+fn graphics_pipeline(context: &gfx::Context, program: GLuint) -> gfx::GraphicsPipeline {
+    gfx::GraphicsPipelineBuilder::new()
+        .with_shader_pipeline(Box::new(NullProgram(program)))
+        .with_input_layout(vec![gfx::VertexAttribute {
+            slot: 0,
+            ty: gl::FLOAT,
+            size: 2,
+            relative_offset: 0,
+            normalized: false,
+            instance_step_rate: 0,
+        }])
+        .build(context)
+        .unwrap()
+}
+
+fn render_target(context: &gfx::Context) -> (gfx::Texture2D, gfx::Framebuffer) {
+    let texture = gfx::Texture2D::new(
+        context,
+        &gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 64, 64),
+    );
+    let mut framebuffer = gfx::FramebufferBuilder::new(context);
+    framebuffer.attach(0, &texture).unwrap();
+    (texture, framebuffer.build())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[test]
+fn test_null_redundant_binds() {
+    let (context, mut queue, upload_buffer) = null_context();
+    let (_texture, framebuffer) = render_target(&context);
+    let pipeline_a = graphics_pipeline(&context, 10);
+    let pipeline_b = graphics_pipeline(&context, 11);
+    context.take_commands();
+
+    let vertices = [[0.0f32, 0.0], [1.0, 0.0], [0.0, 1.0]];
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        {
+            let vbo = frame.upload(&vertices[..]);
+            for &pipeline in [&pipeline_a, &pipeline_a, &pipeline_b].iter() {
+                frame
+                    .draw(
+                        &framebuffer,
+                        pipeline,
+                        gfx::DrawCmd::DrawArrays { first: 0, count: 3 },
+                    )
+                    .with_vertex_buffer(0, &vbo)
+                    .submit();
+            }
+        }
+        frame.submit();
+    }
+
+    let bind_vbo = Command::BindVertexBuffer {
+        slot: 0,
+        obj: upload_buffer,
+        offset: 0,
+        stride: 8,
+    };
+    let draw = Command::Draw {
+        topology: gl::TRIANGLES,
+        first: 0,
+        count: 3,
+    };
+    assert_eq!(
+        context.take_commands(),
+        vec![
+            Command::BindGraphicsPipeline {
+                vao: pipeline_a.vao,
+                program: 10,
+                changed: gfx::SG_ALL_GRAPHICS_PIPELINE,
+            },
+            Command::SetScissorTest(false),
+            Command::BindTarget {
+                framebuffer: framebuffer.gl_object(),
+                viewports: vec![(0.0, 0.0, 64.0, 64.0); 8],
+            },
+            bind_vbo.clone(),
+            draw.clone(),
+            // same pipeline and target: not bound again
+            bind_vbo.clone(),
+            draw.clone(),
+            // same fixed-function state
+            Command::BindGraphicsPipeline {
+                vao: pipeline_b.vao,
+                program: 11,
+                changed: gfx::SG_VERTEX_ARRAY | gfx::SG_PROGRAM,
+            },
+            bind_vbo,
+            draw,
+        ]
+    );
+}
+
+#[test]
+fn test_null_compute_and_graphics() {
+    let (context, mut queue, _) = null_context();
+    let (texture, framebuffer) = render_target(&context);
+    let pipeline = graphics_pipeline(&context, 10);
+    let compute = gfx::ComputePipelineBuilder::new()
+        .with_shader_pipeline(Box::new(NullProgram(20)))
+        .build(&context)
+        .unwrap();
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        frame
+            .dispatch(&compute, (8, 8, 1))
+            .with_image(0, &texture, 0, gl::WRITE_ONLY)
+            .submit();
+        frame.memory_barrier(gfx::MB_TEXTURE_FETCH);
+        frame
+            .draw(
+                &framebuffer,
+                &pipeline,
+                gfx::DrawCmd::DrawArrays { first: 0, count: 3 },
+            )
+            .submit();
+        // the compute program replaced the program of the graphics pipeline
+        frame.dispatch(&compute, (1, 1, 1)).submit();
+        frame.submit();
+    }
+
+    assert_eq!(
+        context.take_commands(),
+        vec![
+            Command::BindComputePipeline { program: 20 },
+            Command::BindImage {
+                slot: 0,
+                obj: texture.gl_object(),
+                mip_level: 0,
+                layer: None,
+                access: gl::WRITE_ONLY,
+            },
+            Command::Dispatch {
+                num_groups: (8, 8, 1),
+            },
+            Command::MemoryBarrier(gfx::MB_TEXTURE_FETCH),
+            Command::BindGraphicsPipeline {
+                vao: pipeline.vao,
+                program: 10,
+                changed: gfx::SG_ALL_GRAPHICS_PIPELINE,
+            },
+            Command::SetScissorTest(false),
+            Command::BindTarget {
+                framebuffer: framebuffer.gl_object(),
+                viewports: vec![(0.0, 0.0, 64.0, 64.0); 8],
+            },
+            Command::Draw {
+                topology: gl::TRIANGLES,
+                first: 0,
+                count: 3,
+            },
+            Command::BindComputePipeline { program: 20 },
+            Command::Dispatch {
+                num_groups: (1, 1, 1),
+            },
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "compute pass dispatched over buffer elements of size zero")]
+fn test_null_compute_pass_zero_element_size() {
+    let (context, _queue, _) = null_context();
+    let compute = gfx::ComputePipelineBuilder::new()
+        .with_shader_pipeline(Box::new(NullProgram(20)))
+        .build(&context)
+        .unwrap();
+    let mut fg = framegraph::FrameGraph::new();
+    let buf = {
+        let mut pass = fg.create_render_pass(
+            "init",
+            |_frame: &gfx::Frame, _ectx: &framegraph::ExecutionContext| {},
+        );
+        let buf = pass.create_buffer("buf", 1024, framegraph::ResourceUsage::ShaderStorageBuffer);
+        pass.build();
+        buf
+    };
+    let mut pass = fg.create_compute_pass(
+        "compute",
+        &compute,
+        |_frame: &gfx::Frame, _ectx: &framegraph::ExecutionContext, cmd: gfx::DispatchCmdBuilder| {
+            cmd.submit()
+        },
+    );
+    pass.read_storage_buffer(0, buf);
+    pass.dispatch(framegraph::DispatchSize::BufferElements {
+        resource: buf,
+        element_size: 0,
+    });
+}
+
+#[test]
+fn test_null_resource_tracking() {
+    let (context, mut queue, _) = null_context();
+    let (_target, framebuffer) = render_target(&context);
+    let pipeline = graphics_pipeline(&context, 10);
+    let texture = gfx::TextureAny::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 16, 16).into(),
+    );
+    let obj = texture.gl_object();
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        frame.record_used_objects();
+        frame
+            .draw(
+                &framebuffer,
+                &pipeline,
+                gfx::DrawCmd::DrawArrays { first: 0, count: 3 },
+            )
+            .with_texture(0, &texture, &gfx::NEAREST_CLAMP_SAMPLER)
+            .submit();
+        assert_eq!(
+            frame.take_used_objects(),
+            vec![gfx::UsedObject::Texture(obj)]
+        );
+        frame.submit();
+    }
+    // the frame holds a reference to the texture
+    drop(texture);
+    assert!(!context
+        .take_commands()
+        .contains(&Command::DeleteTexture { obj }));
+
+    // fences of a null context are signaled on submission: the texture is released
+    // when the next frame is submitted
+    gfx::Frame::new(&mut queue).submit();
+    assert_eq!(context.take_commands(), vec![Command::DeleteTexture { obj }]);
+}
+
+#[test]
+fn test_null_readback() {
+    let (context, mut queue, _) = null_context();
+    let texture = gfx::TextureAny::new(
+        &context,
+        &gfx::TextureDesc {
+            mip_map_count: gfx::MipMaps::Count(2),
+            ..gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 64, 64).into()
+        },
+    );
+
+    let (buffer_readback, texture_readback) = {
+        let frame = gfx::Frame::new(&mut queue);
+        let buffer_readback = {
+            let data = frame.upload(&[1u32, 2, 3, 4][..]);
+            frame.read_buffer(&data)
+        };
+        let texture_readback = frame.read_texture(&texture, 1);
+        assert_eq!(buffer_readback.fence_value(), frame.fence_value());
+        frame.submit();
+        (buffer_readback, texture_readback)
+    };
+
+    // fences of a null context are signaled on submission
+    assert_eq!(buffer_readback.try_get(&queue), Some(vec![1, 2, 3, 4]));
+    assert_eq!(texture_readback.size(), (32, 32, 1));
+    let texels = texture_readback.wait::<[u8; 4]>(&queue);
+    assert_eq!(texels.len(), 32 * 32);
+    assert_eq!(texture_readback.wait::<u8>(&queue).len(), 32 * 32 * 4);
+}
+
+#[test]
+fn test_null_readback_depth_stencil() {
+    let (context, mut queue, _) = null_context();
+    let texture = gfx::TextureAny::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::D32_SFLOAT_S8_UINT, 16, 8).into(),
+    );
+    let texture_readback = {
+        let frame = gfx::Frame::new(&mut queue);
+        let texture_readback = frame.read_texture(&texture, 0);
+        frame.submit();
+        texture_readback
+    };
+    // 32-bit depth and 24 unused bits followed by the stencil, not the 5 bytes of the format
+    assert_eq!(texture_readback.byte_size(), 16 * 8 * 8);
+    assert_eq!(texture_readback.wait::<[u32; 2]>(&queue).len(), 16 * 8);
+}
+
+#[test]
+#[should_panic(expected = "alignment of the element type does not match")]
+fn test_null_readback_misaligned_element_type() {
+    let (context, mut queue, _) = null_context();
+    let texture = gfx::TextureAny::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 64, 64).into(),
+    );
+    let texture_readback = {
+        let frame = gfx::Frame::new(&mut queue);
+        let texture_readback = frame.read_texture(&texture, 0);
+        frame.submit();
+        texture_readback
+    };
+    // each element would span two components
+    texture_readback.wait::<u16>(&queue);
+}
+
+#[test]
+fn test_null_depth_stencil_target() {
+    let (context, mut queue, _) = null_context();
+    let color = gfx::Texture2D::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 64, 64),
+    );
+    let depth_stencil = gfx::Texture2D::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::D32_SFLOAT_S8_UINT, 64, 64),
+    );
+    let mut framebuffer = gfx::FramebufferBuilder::new(&context);
+    framebuffer.attach(0, &color).unwrap();
+    match framebuffer.attach_depth(&color) {
+        Err(gfx::FramebufferError::NotDepthStencilFormat(gfx::Format::R8G8B8A8_UNORM)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    framebuffer.attach_depth(&depth_stencil).unwrap();
+    let framebuffer = framebuffer.build();
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        frame
+            .clear_framebuffer_stencil(&framebuffer, 1)
+            .clear_framebuffer_depth_stencil(&framebuffer, 1.0, 0);
+        frame.submit();
+    }
+
+    assert_eq!(
+        context.take_commands(),
+        vec![
+            Command::ClearFramebuffer {
+                obj: framebuffer.gl_object(),
+                buffer: gl::STENCIL,
+                drawbuffer: 0,
+            },
+            Command::ClearFramebuffer {
+                obj: framebuffer.gl_object(),
+                buffer: gl::DEPTH_STENCIL,
+                drawbuffer: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_null_depth_texture_layer() {
+    let (context, _queue, _) = null_context();
+    let color = gfx::Texture2D::new(
+        &context,
+        &gfx::Texture2DDesc::simple(gfx::Format::R8G8B8A8_UNORM, 64, 64),
+    );
+    let shadow_maps = gfx::TextureAny::new(
+        &context,
+        &gfx::TextureDesc {
+            dimensions: gfx::TextureDimensions::Tex2DArray,
+            format: gfx::Format::D32_SFLOAT,
+            width: 64,
+            height: 64,
+            depth: 4,
+            ..Default::default()
+        },
+    );
+    let mut framebuffer = gfx::FramebufferBuilder::new(&context);
+    framebuffer.attach(0, &color).unwrap();
+    framebuffer
+        .attach_depth(gfx::FramebufferAttachment::TextureLayer(&shadow_maps, 2))
+        .unwrap();
+    let framebuffer = framebuffer.build();
+    assert_eq!(framebuffer.size(), (64, 64));
+}
+
+#[test]
+fn test_null_state_groups() {
+    let (context, mut queue, _) = null_context();
+    let (_texture, framebuffer) = render_target(&context);
+    let pipeline_with_rasterizer_state = |rasterizer_state: &gfx::RasterizerState| {
+        gfx::GraphicsPipelineBuilder::new()
+            .with_shader_pipeline(Box::new(NullProgram(10)))
+            .with_input_layout(Vec::<gfx::VertexAttribute>::new())
+            .with_rasterizer_state(rasterizer_state)
+            .build(&context)
+            .unwrap()
+    };
+    let default = pipeline_with_rasterizer_state(&Default::default());
+    let shadow = pipeline_with_rasterizer_state(&gfx::RasterizerState {
+        cull_mode: gl::FRONT,
+        depth_bias: 2.0,
+        slope_scaled_depth_bias: 1.5,
+        ..Default::default()
+    });
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        for &pipeline in [&default, &shadow, &default].iter() {
+            frame
+                .draw(
+                    &framebuffer,
+                    pipeline,
+                    gfx::DrawCmd::DrawArrays { first: 0, count: 3 },
+                )
+                .submit();
+        }
+        frame.submit();
+    }
+
+    let binds: Vec<_> = context
+        .take_commands()
+        .into_iter()
+        .filter_map(|command| match command {
+            Command::BindGraphicsPipeline { vao, changed, .. } => Some((vao, changed)),
+            _ => None,
+        })
+        .collect();
+    // the program is shared, the other groups are only applied when they differ
+    assert_eq!(
+        binds,
+        vec![
+            (default.vao, gfx::SG_ALL_GRAPHICS_PIPELINE),
+            (shadow.vao, gfx::SG_RASTERIZER_STATE | gfx::SG_VERTEX_ARRAY),
+            (default.vao, gfx::SG_RASTERIZER_STATE | gfx::SG_VERTEX_ARRAY),
+        ]
+    );
+}
+
+#[test]
+fn test_null_instanced_and_indirect_draws() {
+    let (context, mut queue, upload_buffer) = null_context();
+    let (_texture, framebuffer) = render_target(&context);
+    let pipeline = graphics_pipeline(&context, 10);
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        {
+            let indices = frame.upload(&[0u16, 1, 2, 2, 1, 3][..]);
+            let array_commands = frame.upload(
+                &[gfx::DrawArraysIndirectCommand {
+                    count: 3,
+                    instance_count: 4,
+                    ..Default::default()
+                }; 2][..],
+            );
+            let indexed_commands = frame.upload(
+                &[gfx::DrawIndexedIndirectCommand {
+                    count: 6,
+                    instance_count: 2,
+                    ..Default::default()
+                }; 3][..],
+            );
+            frame
+                .draw(
+                    &framebuffer,
+                    &pipeline,
+                    gfx::DrawCmd::DrawArraysInstanced {
+                        first: 0,
+                        count: 3,
+                        instance_count: 4,
+                        base_instance: 1,
+                    },
+                )
+                .submit();
+            frame
+                .draw(
+                    &framebuffer,
+                    &pipeline,
+                    gfx::DrawCmd::DrawIndexedInstanced {
+                        first: 3,
+                        count: 3,
+                        base_vertex: 0,
+                        instance_count: 2,
+                        base_instance: 0,
+                    },
+                )
+                .with_index_buffer(&indices)
+                .submit();
+            frame
+                .draw(
+                    &framebuffer,
+                    &pipeline,
+                    gfx::DrawCmd::DrawArraysIndirect {
+                        first: 0,
+                        draw_count: 2,
+                    },
+                )
+                .with_indirect_buffer(&array_commands)
+                .submit();
+            frame
+                .draw(
+                    &framebuffer,
+                    &pipeline,
+                    gfx::DrawCmd::DrawIndexedIndirect {
+                        first: 1,
+                        draw_count: 2,
+                    },
+                )
+                .with_index_buffer(&indices)
+                .with_indirect_buffer(&indexed_commands)
+                .submit();
+        }
+        frame.submit();
+    }
+
+    let commands: Vec<_> = context
+        .take_commands()
+        .into_iter()
+        .filter(|command| match *command {
+            Command::BindGraphicsPipeline { .. }
+            | Command::BindDrawIndirectBuffer { .. }
+            | Command::DrawInstanced { .. }
+            | Command::DrawIndexedInstanced { .. }
+            | Command::DrawIndirect { .. }
+            | Command::DrawIndexedIndirect { .. } => true,
+            _ => false,
+        })
+        .collect();
+    // uploads are 256-byte aligned: indirect commands start at 256 and 512
+    // the pipeline state is applied once for all draws
+    assert_eq!(
+        commands,
+        vec![
+            Command::BindGraphicsPipeline {
+                vao: pipeline.vao,
+                program: 10,
+                changed: gfx::SG_ALL_GRAPHICS_PIPELINE,
+            },
+            Command::DrawInstanced {
+                topology: gl::TRIANGLES,
+                first: 0,
+                count: 3,
+                instance_count: 4,
+                base_instance: 1,
+            },
+            Command::DrawIndexedInstanced {
+                topology: gl::TRIANGLES,
+                first: 3,
+                count: 3,
+                base_vertex: 0,
+                instance_count: 2,
+                base_instance: 0,
+            },
+            Command::BindDrawIndirectBuffer { obj: upload_buffer },
+            Command::DrawIndirect {
+                topology: gl::TRIANGLES,
+                offset: 256,
+                draw_count: 2,
+                stride: 16,
+            },
+            Command::BindDrawIndirectBuffer { obj: upload_buffer },
+            Command::DrawIndexedIndirect {
+                topology: gl::TRIANGLES,
+                offset: 512 + 20,
+                draw_count: 2,
+                stride: 20,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_null_instance_step_rate() {
+    let (context, _queue, _) = null_context();
+    let attribute = |slot, relative_offset, instance_step_rate| gfx::VertexAttribute {
+        slot,
+        ty: gl::FLOAT,
+        size: 2,
+        relative_offset,
+        normalized: false,
+        instance_step_rate,
+    };
+    // the step rate can be set before the input layout
+    let instanced = gfx::GraphicsPipelineBuilder::new()
+        .with_shader_pipeline(Box::new(NullProgram(10)))
+        .with_instance_step_rate(1, 2)
+        .with_input_layout(vec![
+            attribute(0, 0, 0),
+            attribute(1, 0, 0),
+            attribute(1, 8, 0),
+        ])
+        .build(&context)
+        .unwrap();
+    assert_eq!(
+        context.take_commands(),
+        vec![Command::CreateVertexArray {
+            obj: instanced.vao,
+            divisors: vec![(0, 0), (1, 2)],
+        }]
+    );
+    // attributes of the same vertex buffer must step at the same rate
+    let inconsistent = gfx::GraphicsPipelineBuilder::new()
+        .with_shader_pipeline(Box::new(NullProgram(10)))
+        .with_input_layout(vec![attribute(1, 0, 1), attribute(1, 8, 0)])
+        .build(&context);
+    assert!(inconsistent.is_err());
+    // no attribute is sourced from slot 2
+    let unused_slot = gfx::GraphicsPipelineBuilder::new()
+        .with_shader_pipeline(Box::new(NullProgram(10)))
+        .with_input_layout(vec![attribute(0, 0, 0)])
+        .with_instance_step_rate(2, 1)
+        .build(&context);
+    assert!(unused_slot.is_err());
+}
+
+#[test]
+fn test_null_indirect_draw_out_of_bounds() {
+    let (context, mut queue, _) = null_context();
+    let (_texture, framebuffer) = render_target(&context);
+    let pipeline = graphics_pipeline(&context, 10);
+    context.take_commands();
+
+    let result = {
+        let frame = gfx::Frame::new(&mut queue);
+        let result = {
+            let commands = frame.upload(&[gfx::DrawArraysIndirectCommand::default(); 2][..]);
+            frame
+                .draw(
+                    &framebuffer,
+                    &pipeline,
+                    gfx::DrawCmd::DrawArraysIndirect {
+                        first: 1,
+                        draw_count: 2,
+                    },
+                )
+                .with_indirect_buffer(&commands)
+                .try_submit()
+        };
+        frame.submit();
+        result
+    };
+
+    match result {
+        Err(gfx::DrawError::IndirectCommandsOutOfBounds {
+            first: 1,
+            end: 3,
+            len: 2,
+        }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(!context.take_commands().iter().any(|command| match *command {
+        Command::DrawIndirect { .. } => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn test_null_draw_storage_buffer_and_image() {
+    let (context, mut queue, upload_buffer) = null_context();
+    let (texture, framebuffer) = render_target(&context);
+    let pipeline = graphics_pipeline(&context, 10);
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        {
+            let counters = frame.upload(&[0u32; 4][..]);
+            frame
+                .draw(
+                    &framebuffer,
+                    &pipeline,
+                    gfx::DrawCmd::DrawArrays { first: 0, count: 3 },
+                )
+                .with_storage_buffer(1, &counters)
+                .with_image(0, &texture, 0, gl::READ_ONLY)
+                .with_image_layer(1, &texture, 2, 0, gl::READ_WRITE)
+                .submit();
+        }
+        frame.submit();
+    }
+
+    let binds: Vec<_> = context
+        .take_commands()
+        .into_iter()
+        .filter(|command| match *command {
+            Command::BindStorageBuffer { .. } | Command::BindImage { .. } => true,
+            _ => false,
+        })
+        .collect();
+    assert_eq!(
+        binds,
+        vec![
+            Command::BindStorageBuffer {
+                slot: 1,
+                obj: upload_buffer,
+                offset: 0,
+                size: 16,
+            },
+            Command::BindImage {
+                slot: 0,
+                obj: texture.gl_object(),
+                mip_level: 0,
+                layer: None,
+                access: gl::READ_ONLY,
+            },
+            Command::BindImage {
+                slot: 1,
+                obj: texture.gl_object(),
+                mip_level: 2,
+                layer: Some(0),
+                access: gl::READ_WRITE,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_null_upload_overflow_strategies() {
+    let (context, mut queue, _) = null_context();
+    let data = vec![0u8; 768];
+
+    let fail =
+        gfx::UploadBuffer::with_overflow_strategy(&context, 1024, gfx::OverflowStrategy::Fail);
+    let chain = gfx::UploadBuffer::new(&context, 1024);
+    let one_off =
+        gfx::UploadBuffer::with_overflow_strategy(&context, 1024, gfx::OverflowStrategy::OneOff);
+    context.take_commands();
+
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        {
+            assert!(frame.try_upload_into(&fail, &data[..]).is_ok());
+            match frame.try_upload_into(&fail, &data[..]) {
+                Err(gfx::UploadError::OutOfSpace { size: 768 }) => {}
+                _ => panic!("expected an out of space error"),
+            }
+            match frame.try_upload_into(&fail, &vec![0u8; 2048][..]) {
+                Err(gfx::UploadError::TooLarge {
+                    size: 2048,
+                    capacity: 1024,
+                }) => {}
+                _ => panic!("expected an allocation too large error"),
+            }
+
+            let a = frame.upload_into(&chain, &data[..]);
+            let b = frame.upload_into(&chain, &data[..]);
+            assert_ne!(
+                unsafe { a.to_slice_any() }.owner.gl_object(),
+                unsafe { b.to_slice_any() }.owner.gl_object()
+            );
+            assert_eq!(chain.capacity(), 2048);
+
+            frame.upload_into(&one_off, &data[..]);
+            frame.upload_into(&one_off, &data[..]);
+            assert_eq!(one_off.capacity(), 1024);
+        }
+
+        let stats = one_off.current_frame_stats().unwrap();
+        assert_eq!(stats.frame, frame.fence_value());
+        assert_eq!(stats.uploaded_bytes, 1536);
+        assert_eq!(stats.one_off_bytes, 768);
+        assert_eq!(stats.overflows, 1);
+        assert_eq!(stats.high_water_mark, 768);
+        frame.submit();
+    }
+
+    // the previous frame is complete: its regions are reclaimed
+    {
+        let frame = gfx::Frame::new(&mut queue);
+        frame.upload_into(&fail, &data[..]);
+        let last_frame_stats = fail.last_frame_stats().unwrap();
+        assert_eq!(last_frame_stats.uploaded_bytes, 768);
+        assert_eq!(last_frame_stats.overflows, 2);
+        assert_eq!(fail.current_frame_stats().unwrap().uploaded_bytes, 768);
+        frame.submit();
+    }
+}
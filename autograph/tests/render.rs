@@ -0,0 +1,200 @@
+#[macro_use]
+extern crate autograph;
+#[macro_use]
+extern crate autograph_derive;
+
+mod common;
+
+use autograph::gfx;
+use autograph::gfx::glsl::GraphicsPipelineBuilderExt;
+use autograph::gfx::DrawExt;
+use autograph::gl;
+use common::*;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[test]
+fn test_image_diff() {
+    let reference = TestImage::new(2, 2, vec![[0.0, 0.0, 0.0, 1.0]; 4], false);
+    let mut image = reference.clone();
+    image.pixels[1] = [0.005, 0.0, 0.0, 1.0];
+    image.pixels[2] = [0.0, 1.0, 0.0, 1.0];
+
+    let diff = diff_images(&image, &reference, &Tolerance::default());
+    assert_eq!(diff.differing_pixels, 1);
+    assert_eq!(diff.max_channel_difference, 1.0);
+    assert_eq!(diff.diff_image.pixel(0, 1), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(diff.diff_image.pixel(0, 0), [0.0, 0.0, 0.0, 1.0]);
+
+    let diff = diff_images(&image, &reference, &Tolerance::exact());
+    assert_eq!(diff.differing_pixels, 2);
+}
+
+#[test]
+fn test_offscreen_clear() {
+    let config = OffscreenTestConfig {
+        name: "clear",
+        width: 64,
+        height: 64,
+        format: gfx::Format::R8G8B8A8_UNORM,
+        depth_stencil_format: None,
+        tolerance: Tolerance::default(),
+    };
+
+    run_offscreen_test(&config, |_, frame_info| {
+        frame_info
+            .frame
+            .clear_framebuffer_color(frame_info.framebuffer, 0, &[0.2, 0.4, 0.6, 1.0]);
+    });
+}
+
+#[test]
+fn test_offscreen_clear_hdr() {
+    let config = OffscreenTestConfig {
+        name: "clear_hdr",
+        width: 16,
+        height: 16,
+        format: gfx::Format::R32G32B32A32_SFLOAT,
+        depth_stencil_format: None,
+        tolerance: Tolerance::default(),
+    };
+
+    run_offscreen_test(&config, |_, frame_info| {
+        frame_info
+            .frame
+            .clear_framebuffer_color(frame_info.framebuffer, 0, &[2.0, 0.5, 0.25, 1.0]);
+    });
+}
+
+#[test]
+fn test_offscreen_draw() {
+    let config = OffscreenTestConfig {
+        name: "quadrant",
+        width: 64,
+        height: 64,
+        format: gfx::Format::R8G8B8A8_UNORM,
+        depth_stencil_format: None,
+        tolerance: Tolerance::default(),
+    };
+
+    run_offscreen_test(&config, |context, frame_info| {
+        let pipeline = fill_pipeline(context, &Default::default());
+        let frame = &*frame_info.frame;
+        frame.clear_framebuffer_color(frame_info.framebuffer, 0, &[0.0, 0.0, 0.0, 1.0]);
+        let vbo = frame.upload(&QUADRANT[..]);
+        let red = frame.upload(&[1.0f32, 0.0, 0.0, 1.0]);
+        frame
+            .draw(
+                frame_info.framebuffer,
+                &pipeline,
+                gfx::DrawCmd::DrawArrays { first: 0, count: 6 },
+            )
+            .with_vertex_buffer(0, &vbo)
+            .with_uniform_buffer(0, &red)
+            .submit();
+    });
+}
+
+#[test]
+fn test_offscreen_stencil() {
+    let config = OffscreenTestConfig {
+        name: "stencil",
+        width: 64,
+        height: 64,
+        format: gfx::Format::R8G8B8A8_UNORM,
+        depth_stencil_format: Some(gfx::Format::D24_UNORM_S8_UINT),
+        tolerance: Tolerance::default(),
+    };
+
+    run_offscreen_test(&config, |context, frame_info| {
+        // marks the quadrant in the stencil buffer
+        let replace = gfx::StencilOpState {
+            pass_op: gl::REPLACE,
+            ..Default::default()
+        };
+        let write_stencil = fill_pipeline(
+            context,
+            &gfx::DepthStencilState {
+                stencil_enable: true,
+                stencil_front: replace,
+                stencil_back: replace,
+                stencil_ref: 1,
+                ..Default::default()
+            },
+        );
+        // then fills everything outside
+        let not_equal = gfx::StencilOpState {
+            compare_func: gl::NOTEQUAL,
+            ..Default::default()
+        };
+        let test_stencil = fill_pipeline(
+            context,
+            &gfx::DepthStencilState {
+                stencil_enable: true,
+                stencil_front: not_equal,
+                stencil_back: not_equal,
+                stencil_ref: 1,
+                ..Default::default()
+            },
+        );
+
+        let frame = &*frame_info.frame;
+        frame
+            .clear_framebuffer_color(frame_info.framebuffer, 0, &[0.0, 0.0, 0.0, 1.0])
+            .clear_framebuffer_depth_stencil(frame_info.framebuffer, 1.0, 0);
+        let quadrant = frame.upload(&QUADRANT[..]);
+        let fullscreen = frame.upload(&FULLSCREEN[..]);
+        let red = frame.upload(&[1.0f32, 0.0, 0.0, 1.0]);
+        let green = frame.upload(&[0.0f32, 1.0, 0.0, 1.0]);
+        frame
+            .draw(
+                frame_info.framebuffer,
+                &write_stencil,
+                gfx::DrawCmd::DrawArrays { first: 0, count: 6 },
+            )
+            .with_vertex_buffer(0, &quadrant)
+            .with_uniform_buffer(0, &red)
+            .submit();
+        frame
+            .draw(
+                frame_info.framebuffer,
+                &test_stencil,
+                gfx::DrawCmd::DrawArrays { first: 0, count: 6 },
+            )
+            .with_vertex_buffer(0, &fullscreen)
+            .with_uniform_buffer(0, &green)
+            .submit();
+    });
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The bottom-left quadrant of the viewport. Reference images also check that rows are not flipped.
+const QUADRANT: [[f32; 2]; 6] = [
+    [-1.0, -1.0],
+    [0.0, -1.0],
+    [0.0, 0.0],
+    [-1.0, -1.0],
+    [0.0, 0.0],
+    [-1.0, 0.0],
+];
+
+const FULLSCREEN: [[f32; 2]; 6] = [
+    [-1.0, -1.0],
+    [1.0, -1.0],
+    [1.0, 1.0],
+    [-1.0, -1.0],
+    [1.0, 1.0],
+    [-1.0, 1.0],
+];
+
+/// A pipeline filling triangles with the color in the uniform buffer at slot 0.
+fn fill_pipeline(
+    context: &gfx::Context,
+    depth_stencil_state: &gfx::DepthStencilState,
+) -> gfx::GraphicsPipeline {
+    gfx::GraphicsPipelineBuilder::new()
+        .with_glsl_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/shaders/fill.glsl"))
+        .unwrap()
+        .with_depth_stencil_state(depth_stencil_state)
+        .build(context)
+        .unwrap()
+}
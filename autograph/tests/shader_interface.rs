@@ -48,3 +48,94 @@ layout(binding=0,std140) uniform U {
 };
 "# });
 }
+
+#[repr(C)]
+#[derive(Copy, Clone, BufferLayout)]
+struct Particle {
+    // 16 bytes with std430, 32 bytes with std140
+    orientation: [[f32; 2]; 2],
+    position: [f32; 2],
+    mass: f32,
+}
+
+#[derive(ShaderInterface)]
+struct Interface1 {
+    #[storage_buffer(index = "1")]
+    particle: gfx::BufferSlice<Particle>,
+    #[image(index = "0")]
+    output: gfx::ImageView<gfx::Texture2D>,
+}
+
+#[derive(ShaderInterface)]
+struct Interface2 {
+    #[uniform_buffer(index = "1")]
+    particle: gfx::BufferSlice<Particle>,
+}
+
+#[test]
+fn test_shader_interface_storage_buffer_and_image() {
+    load_pipeline_and_check_interface::<Interface1>(make_interface_test_shader! { r#"
+layout(binding=0,rgba8) uniform writeonly image2D outputImage;
+
+layout(binding=1,std430) buffer P {
+        mat2 orientation;
+        vec2 position;
+        float mass;
+};
+"# });
+}
+
+#[test]
+#[should_panic(expected = "member offset mismatch: #1(<unnamed>) @ 32 (shader)")]
+fn test_shader_interface_std140_layout_mismatch() {
+    load_pipeline_and_check_interface::<Interface2>(make_interface_test_shader! { r#"
+layout(binding=1,std140) uniform P {
+        mat2 orientation;
+        vec2 position;
+        float mass;
+};
+"# });
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, BufferLayout)]
+struct Vertex {
+    position: [f32; 4],
+    color: [f32; 4],
+}
+
+#[derive(ShaderInterface)]
+struct Interface3 {
+    #[storage_buffer(index = "2")]
+    vertices: gfx::BufferSlice<[Vertex]>,
+}
+
+#[derive(ShaderInterface)]
+struct Interface4 {
+    #[storage_buffer(index = "2")]
+    positions: gfx::BufferSlice<[[f32; 3]]>,
+}
+
+#[test]
+fn test_shader_interface_runtime_array() {
+    load_pipeline_and_check_interface::<Interface3>(make_interface_test_shader! { r#"
+struct Vertex {
+        vec4 position;
+        vec4 color;
+};
+
+layout(binding=2,std430) buffer V {
+        Vertex vertices[];
+};
+"# });
+}
+
+#[test]
+#[should_panic(expected = "array stride mismatch: 16 (shader) and 12 (host)")]
+fn test_shader_interface_runtime_array_stride_mismatch() {
+    load_pipeline_and_check_interface::<Interface4>(make_interface_test_shader! { r#"
+layout(binding=2,std430) buffer P {
+        vec3 positions[];
+};
+"# });
+}
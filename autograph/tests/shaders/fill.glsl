@@ -0,0 +1,29 @@
+#version 450
+#pragma stages(vertex,fragment)
+#pragma input_layout(rg32f,0,0)
+#pragma primitive_topology(triangle)
+
+#ifdef _VERTEX_
+
+layout(location = 0) in vec2 pos;
+
+void main() {
+  gl_Position = vec4(pos, 0.0, 1.0);
+}
+
+#endif
+
+#ifdef _FRAGMENT_
+
+layout(binding = 0, std140) uniform Fill {
+  vec4 fill_color;
+};
+
+layout(location = 0) out vec4 color;
+
+void main()
+{
+    color = fill_color;
+}
+
+#endif